@@ -0,0 +1,155 @@
+//! the `cvldoc` command line tool, built with the `cli` feature.
+//!
+//! `cvldoc lint [OPTIONS] FILE...` prints the diagnostics of each file, as rendered by
+//! [diagnostics::render_all], and fails if any of them is an error or a warning.
+//! each documentation policy lint of [LintConfig] is enabled for the element kinds given to its option,
+//! separated by commas, as in `--require-title rule,invariant`.
+//!
+//! `cvldoc fingerprint write BASELINE FILE...` saves the [fingerprint::baseline] of the files
+//! as JSON, and `cvldoc fingerprint changed BASELINE FILE...` prints the [fingerprint::element_key]
+//! of each element whose documentation changed since, as in `rule foo`, and fails if there are any.
+//! the elements of all files are fingerprinted together, so their keys should be unique.
+
+use cvldoc_parser_core::diagnostics::{self, check_collection, CollectionConfig, LintConfig};
+use cvldoc_parser_core::fingerprint::{self, BaselineFile};
+use cvldoc_parser_core::parse::builder::Builder;
+use cvldoc_parser_core::util::{strip_bom, RangeConverter};
+use eyre::{bail, eyre, Result, WrapErr};
+use lsp_types::DiagnosticSeverity;
+use ropey::Rope;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+usage: cvldoc lint [--require-title KINDS] [--require-notice KINDS]
+                   [--require-dev-for-filtered KINDS] [--strict-methods-params] [--color] FILE...
+       cvldoc fingerprint (write | changed) BASELINE FILE...";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let result = match args.split_first() {
+        Some((command, args)) if command == "lint" => lint(args),
+        Some((command, args)) if command == "fingerprint" => fingerprint(args),
+        _ => Err(eyre!(USAGE)),
+    };
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("{error:#}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// returns whether no file has errors or warnings
+fn lint(args: &[String]) -> Result<bool> {
+    let mut config = LintConfig::default();
+    let mut opts = diagnostics::RenderOptions::default();
+    let mut files = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut kinds = || -> Result<Vec<String>> {
+            let kinds = args
+                .next()
+                .ok_or_else(|| eyre!("{arg} expects a list of element kinds"))?;
+            Ok(kinds
+                .split(',')
+                .map(|kind| kind.trim().to_string())
+                .collect())
+        };
+
+        match arg.as_str() {
+            "--require-title" => config.require_title = kinds()?,
+            "--require-notice" => config.require_notice = kinds()?,
+            "--require-dev-for-filtered" => config.require_dev_for_filtered = kinds()?,
+            "--strict-methods-params" => config.strict_methods_params = true,
+            "--color" => opts.ansi = true,
+            option if option.starts_with("--") => bail!("unknown option {option}\n{USAGE}"),
+            file => files.push(file),
+        }
+    }
+    if files.is_empty() {
+        bail!(USAGE);
+    }
+
+    let mut clean = true;
+    for file in files {
+        let src = read(file)?;
+        let src = src.as_str();
+
+        let builder = Builder::new(src);
+        let mut diags = builder.source_diagnostics();
+        let elements = builder
+            .build()
+            .wrap_err_with(|| format!("cannot parse {file}"))?;
+
+        let converter = RangeConverter::new(Rope::from_str(src));
+        for element in &elements {
+            diags.extend(element.enumerate_diagnostics_with(converter.clone(), &config));
+        }
+        let collection = check_collection(&elements, &CollectionConfig::default());
+        diags.extend(collection.into_iter().map(|(_, diag)| diag));
+
+        print!("{}", diagnostics::render_all(&diags, src, file, &opts));
+        clean &= diags.iter().all(|diag| {
+            matches!(
+                diag.severity,
+                Some(DiagnosticSeverity::INFORMATION | DiagnosticSeverity::HINT)
+            )
+        });
+    }
+
+    Ok(clean)
+}
+
+/// returns whether no element changed since the baseline
+fn fingerprint(args: &[String]) -> Result<bool> {
+    let [mode, baseline_path, files @ ..] = args else {
+        bail!(USAGE);
+    };
+    if files.is_empty() {
+        bail!(USAGE);
+    }
+
+    let mut docs = Vec::new();
+    for file in files {
+        let elements = Builder::new(&read(file)?)
+            .build()
+            .wrap_err_with(|| format!("cannot parse {file}"))?;
+        docs.extend(elements);
+    }
+
+    match mode.as_str() {
+        "write" => {
+            let baseline = serde_json::to_string_pretty(&fingerprint::baseline(&docs))?;
+            std::fs::write(baseline_path, baseline + "\n")
+                .wrap_err_with(|| format!("cannot write {baseline_path}"))?;
+            Ok(true)
+        }
+        "changed" => {
+            let baseline = std::fs::read_to_string(baseline_path)
+                .wrap_err_with(|| format!("cannot read {baseline_path}"))?;
+            let baseline: BaselineFile = serde_json::from_str(&baseline)
+                .wrap_err_with(|| format!("{baseline_path} is not a baseline file"))?;
+
+            let changed = fingerprint::changed(&docs, &baseline);
+            for key in changed
+                .iter()
+                .filter_map(|element| fingerprint::element_key(element))
+            {
+                println!("{key}");
+            }
+            Ok(changed.is_empty())
+        }
+        _ => bail!(USAGE),
+    }
+}
+
+/// the contents of `file`, without its byte order mark, to which spans are relative
+fn read(file: &str) -> Result<String> {
+    let src = std::fs::read_to_string(file).wrap_err_with(|| format!("cannot read {file}"))?;
+    Ok(strip_bom(&src).to_string())
+}
@@ -0,0 +1,146 @@
+//! a cache of parse results, shared between threads, for servers which parse
+//! the same unchanged files repeatedly.
+
+use crate::parse::builder::Builder;
+use crate::util::strip_bom;
+use crate::CvlElement;
+use eyre::Result;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+struct Entry {
+    hash: u64,
+    src: Arc<str>,
+    elements: Arc<Vec<CvlElement>>,
+    /// the value of [Inner::clock] when the entry was last used
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    clock: u64,
+}
+
+/// parse results keyed by URI, holding up to `capacity` files. when full, the least recently
+/// used file is evicted, and with a capacity of 0 nothing is cached.
+/// a cached result is returned only if the source is unchanged:
+/// sources are compared by hash, and then in full, so a hash collision is never a hit.
+///
+/// parsing happens outside of the lock, so threads parsing different files do not wait for each other.
+/// a lookup updates the recency of its entry, so a `Mutex` is used rather than a `RwLock`.
+pub struct ParseCache {
+    capacity: usize,
+    hash: fn(&str) -> u64,
+    inner: Mutex<Inner>,
+}
+
+impl ParseCache {
+    pub fn new(capacity: usize) -> ParseCache {
+        ParseCache::with_hash_fn(capacity, default_hash)
+    }
+
+    pub(crate) fn with_hash_fn(capacity: usize, hash: fn(&str) -> u64) -> ParseCache {
+        ParseCache {
+            capacity,
+            hash,
+            inner: Mutex::default(),
+        }
+    }
+
+    /// the elements of `src`, from the cache if `uri` was last parsed with the same source.
+    /// otherwise `src` is parsed, and the result replaces the cached one for `uri`.
+    /// failed parses are not cached. a byte order mark at the start of `src` is ignored.
+    pub fn parse(&self, uri: &str, src: &str) -> Result<Arc<Vec<CvlElement>>> {
+        let src = strip_bom(src);
+        let hash = (self.hash)(src);
+
+        if let Some(elements) = self.get(uri, hash, src) {
+            return Ok(elements);
+        }
+
+        let elements = Arc::new(Builder::new(src).build()?);
+        self.insert(uri, hash, src, Arc::clone(&elements));
+
+        Ok(elements)
+    }
+
+    pub fn invalidate(&self, uri: &str) {
+        self.lock().entries.remove(uri);
+    }
+
+    pub fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    pub fn contains(&self, uri: &str) -> bool {
+        self.lock().entries.contains_key(uri)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, uri: &str, hash: u64, src: &str) -> Option<Arc<Vec<CvlElement>>> {
+        let mut inner = self.lock();
+        inner.clock += 1;
+        let now = inner.clock;
+
+        let entry = inner.entries.get_mut(uri)?;
+        if entry.hash != hash || *entry.src != *src {
+            return None;
+        }
+
+        entry.last_used = now;
+        Some(Arc::clone(&entry.elements))
+    }
+
+    fn insert(&self, uri: &str, hash: u64, src: &str, elements: Arc<Vec<CvlElement>>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut inner = self.lock();
+        inner.clock += 1;
+        let now = inner.clock;
+
+        if !inner.entries.contains_key(uri) && inner.entries.len() >= self.capacity {
+            let least_recent = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(uri, _)| uri.clone());
+            if let Some(least_recent) = least_recent {
+                inner.entries.remove(&least_recent);
+            }
+        }
+
+        let entry = Entry {
+            hash,
+            src: Arc::from(src),
+            elements,
+            last_used: now,
+        };
+        inner.entries.insert(uri.to_string(), entry);
+    }
+
+    /// a panic while holding the lock cannot leave the entries inconsistent,
+    /// so a poisoned lock is still used
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn default_hash(src: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    src.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,34 @@
+//! completions of tag names in documentation, for a language server.
+
+use crate::{Ast, TagKind};
+use lsp_types::{CompletionItem, CompletionItemKind};
+
+/// a completion of each tag the element supports, as in `@notice`, in the default [crate::TagOrder].
+/// when the element is not known yet, as while writing documentation above a declaration,
+/// every tag kind in [TagKind::ALL] is offered.
+pub fn tag_completions(ast: Option<&Ast>) -> Vec<CompletionItem> {
+    let kinds = ast.map_or(TagKind::ALL, Ast::supported_tags);
+
+    kinds
+        .iter()
+        .map(|kind| CompletionItem {
+            label: kind.as_keyword(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            detail: Some(detail(kind).to_string()),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn detail(kind: &TagKind) -> &'static str {
+    match kind {
+        TagKind::Title => "the title of the element",
+        TagKind::Notice => "an explanation of the element, for readers of the specification",
+        TagKind::Dev => "details for maintainers of the specification",
+        TagKind::Param => "a parameter, by name, followed by its description",
+        TagKind::Return => "the returned value",
+        TagKind::Formula => "the property checked by the element, as a formula",
+        TagKind::Requirement => "the IDs of the requirements this element checks",
+        TagKind::Satisfies => "the IDs of the requirements this element satisfies",
+    }
+}
@@ -0,0 +1,274 @@
+//! documentation coverage of elements, and of the entries of `methods` blocks.
+//! this is opt-in: nothing here runs unless it is called.
+
+use crate::parse::builder::ContentLines;
+use crate::util::Span;
+use crate::{Ast, CvlElement, Param, TagKind};
+use serde::{Deserialize, Serialize};
+
+/// what makes the documentation of an element complete. see [CvlElement::has_complete_documentation].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletenessConfig {
+    /// require a `@notice` or `@title` tag. untagged leading text counts as `@notice`.
+    pub require_summary: bool,
+    /// require a `@param` tag for each parameter of the element
+    pub require_all_params: bool,
+    /// with `require_all_params`, also require a `@param` tag for `method` parameters
+    /// of parametric rules, which are usually self-explanatory. see [crate::Ast::is_parametric].
+    pub require_method_params: bool,
+    /// require a `@return` tag for elements which return a value
+    pub require_return: bool,
+}
+
+impl Default for CompletenessConfig {
+    fn default() -> Self {
+        CompletenessConfig {
+            require_summary: true,
+            require_all_params: true,
+            require_method_params: false,
+            require_return: false,
+        }
+    }
+}
+
+impl CompletenessConfig {
+    /// freeform comments are always complete, and undocumented elements never are.
+    pub fn is_complete(&self, element: &CvlElement) -> bool {
+        if element.is_freeform() {
+            return true;
+        }
+        if element.doc.is_empty() {
+            return false;
+        }
+
+        let has_tag = |kind| element.doc.iter().any(|tag| tag.kind == kind);
+
+        let has_summary = has_tag(TagKind::Notice) || has_tag(TagKind::Title);
+        if self.require_summary && !has_summary {
+            return false;
+        }
+
+        let params = element.ast.params().unwrap_or_default();
+        let param_is_documented = |name: &str| {
+            element
+                .doc
+                .iter()
+                .any(|tag| tag.param_path().first() == Some(&name))
+        };
+        let param_is_required = |param: &&Param| self.require_method_params || !param.is_method();
+        let all_params_documented = params
+            .iter()
+            .filter(param_is_required)
+            .all(|param| param_is_documented(&param.name));
+        if self.require_all_params && !all_params_documented {
+            return false;
+        }
+
+        let has_return_value = element.ast.returns().is_some();
+        if self.require_return && has_return_value && !has_tag(TagKind::Return) {
+            return false;
+        }
+
+        true
+    }
+}
+
+/// the points a tag of this kind is worth in [complexity_score]. `@param` is worth this much
+/// for each documented parameter; other kinds count once, however many tags there are.
+fn points(kind: &TagKind) -> u32 {
+    match kind {
+        TagKind::Notice | TagKind::Formula => 2,
+        TagKind::Title | TagKind::Dev | TagKind::Param | TagKind::Return => 1,
+        TagKind::Requirement | TagKind::Satisfies => 0,
+    }
+}
+
+/// a rough measure of how thoroughly an element is documented: 2 for `@notice` and `@formula`,
+/// 1 for `@title`, `@dev` and `@return`, and 1 for each parameter with a `@param`.
+/// only tags the element supports count, so the score never exceeds [max_score].
+/// like [CompletenessConfig::default], `method` parameters of parametric rules are not scored.
+/// freeform comments score 0.
+pub fn complexity_score(element: &CvlElement) -> u32 {
+    if element.is_freeform() {
+        return 0;
+    }
+
+    let has_tag = |kind: &TagKind| element.doc.iter().any(|tag| tag.kind == *kind);
+
+    element
+        .ast
+        .supported_tags()
+        .iter()
+        .map(|kind| match kind {
+            TagKind::Param => {
+                let documented = scored_params(element).filter(|param| {
+                    element
+                        .doc
+                        .iter()
+                        .any(|tag| tag.param_path().first() == Some(&param.name.as_str()))
+                });
+                documented.count() as u32 * points(kind)
+            }
+            TagKind::Return if element.ast.returns().is_none() => 0,
+            kind if has_tag(kind) => points(kind),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// the [complexity_score] of the most thorough documentation of this element,
+/// which depends on the tags it supports and on its number of parameters.
+pub fn max_score(element: &CvlElement) -> u32 {
+    if element.is_freeform() {
+        return 0;
+    }
+
+    element
+        .ast
+        .supported_tags()
+        .iter()
+        .map(|kind| match kind {
+            TagKind::Param => scored_params(element).count() as u32 * points(kind),
+            TagKind::Return if element.ast.returns().is_none() => 0,
+            kind => points(kind),
+        })
+        .sum()
+}
+
+/// the parameters which [complexity_score] expects a `@param` for
+fn scored_params(element: &CvlElement) -> impl Iterator<Item = &Param> {
+    let params = element.ast.params().unwrap_or_default();
+    params.iter().filter(|param| !param.is_method())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MethodsEntry {
+    /// the entry, without its terminating semicolon
+    pub text: String,
+    /// the documentation directly preceding the entry, with comment markers removed
+    pub doc: Option<String>,
+}
+
+impl MethodsEntry {
+    /// whether this entry summarizes the method, as in `function foo() external => NONDET;`
+    pub fn is_summarized(&self) -> bool {
+        self.text.contains("=>")
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MethodsCoverage {
+    pub documented: Vec<MethodsEntry>,
+    pub undocumented: Vec<MethodsEntry>,
+}
+
+impl MethodsCoverage {
+    /// returns `None` if `element` is not a `methods` block.
+    /// the coverage is computed from the block, whether or not it was recorded when parsing.
+    pub fn of(element: &CvlElement) -> Option<MethodsCoverage> {
+        match &element.ast {
+            Ast::Methods { block, .. } => Some(MethodsCoverage::of_block(block)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn of_block(block: &str) -> MethodsCoverage {
+        let (documented, undocumented) = scan_entries(block)
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .partition(|entry| entry.doc.is_some());
+
+        MethodsCoverage {
+            documented,
+            undocumented,
+        }
+    }
+
+    /// summarized entries are a common source of confusion, so they should be explained.
+    pub fn undocumented_summaries(&self) -> impl Iterator<Item = &MethodsEntry> {
+        self.undocumented
+            .iter()
+            .filter(|entry| entry.is_summarized())
+    }
+}
+
+/// the entries of a `methods` block, which end at each `;` outside of comments and brackets.
+/// each entry is paired with its byte range in `block`, from its first character
+/// to its last character which is not in a comment.
+pub(crate) fn scan_entries(block: &str) -> Vec<(Span, MethodsEntry)> {
+    let mut entries = Vec::new();
+    let mut pending_doc: Option<String> = None;
+    let mut current = String::new();
+    let mut range: Option<Span> = None;
+    let mut depth = 0usize;
+    let mut rest = block;
+
+    let mut push_entry =
+        |current: &mut String, range: &mut Option<Span>, pending_doc: &mut Option<String>| {
+            if let Some(range) = range.take() {
+                let entry = MethodsEntry {
+                    text: current.trim().to_string(),
+                    doc: pending_doc.take(),
+                };
+                entries.push((range, entry));
+            }
+            current.clear();
+        };
+
+    while let Some(ch) = rest.chars().next() {
+        let comment_end = if rest.starts_with("//") {
+            Some(rest.find('\n').map(|i| i + 1).unwrap_or(rest.len()))
+        } else if rest.starts_with("/*") {
+            Some(rest.find("*/").map(|i| i + 2).unwrap_or(rest.len()))
+        } else {
+            None
+        };
+
+        if let Some(comment_end) = comment_end {
+            let (comment, after) = rest.split_at(comment_end);
+            let is_doc = (comment.starts_with("///") && !comment.starts_with("////"))
+                || (comment.starts_with("/**") && !comment.starts_with("/***"));
+
+            if is_doc {
+                let text: String = ContentLines::new(comment, 0..comment.len(), &['/', '*'])
+                    .map(|(line, _)| line)
+                    .collect();
+
+                // consecutive slashed lines make up a single documentation block
+                match &mut pending_doc {
+                    Some(doc) if current.trim().is_empty() && comment.starts_with("///") => {
+                        doc.push('\n');
+                        doc.push_str(&text);
+                    }
+                    _ => pending_doc = Some(text),
+                }
+            }
+
+            rest = after;
+            continue;
+        }
+
+        match ch {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+
+        if ch == ';' && depth == 0 {
+            push_entry(&mut current, &mut range, &mut pending_doc);
+        } else {
+            current.push(ch);
+            if !ch.is_whitespace() {
+                let start = block.len() - rest.len();
+                let end = start + ch.len_utf8();
+                range = Some(range.map_or(start, |range| range.start)..end);
+            }
+        }
+
+        rest = &rest[ch.len_utf8()..];
+    }
+
+    push_entry(&mut current, &mut range, &mut pending_doc);
+
+    entries
+}
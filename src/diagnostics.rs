@@ -1,62 +1,431 @@
-use crate::util::RangeConverter;
+use crate::parse::builder::Builder;
+use crate::parse::bundle::Bundle;
+use crate::util::{
+    compute_edit_diff, count_lines, mentions_receiver, ByteSpan, RangeConverter, SimplePosition,
+    SimpleRange, Span,
+};
 use crate::{Ast, CvlElement, DocumentationTag, TagKind};
-use lsp_types::{Diagnostic, DiagnosticSeverity};
+use itertools::Itertools;
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, TextEdit};
+use ropey::Rope;
+use std::collections::HashMap;
+use std::sync::Arc;
 
-impl Ast {
-    fn supported_tags(&self) -> &[TagKind] {
-        use TagKind::*;
-        match self {
-            Ast::Rule { .. } => &[Title, Notice, Dev, Param, Formula],
-            Ast::Invariant { .. } => &[Title, Notice, Dev, Param],
-            Ast::Function { .. } => &[Notice, Dev, Param, Return],
-            Ast::Definition { .. } => &[Notice, Dev, Param, Return],
-            Ast::GhostFunction { .. } | Ast::GhostMapping { .. } => &[Notice, Dev, Param, Return],
-            Ast::Methods { .. } => &[Notice, Dev],
-            Ast::FreeFormComment { .. } => &[Notice, Dev],
-            Ast::Import { .. }
-            | Ast::Using { .. }
-            | Ast::UseRule { .. }
-            | Ast::UseBuiltinRule { .. }
-            | Ast::UseInvariant { .. }
-            | Ast::HookSload { .. }
-            | Ast::HookSstore { .. }
-            | Ast::HookCreate { .. }
-            | Ast::HookOpcode { .. } => &[Dev],
-        }
-    }
+/// leading words of a `@return` description which are not taken as the name of the return value,
+/// even when a parameter has the same name
+const RETURN_STOP_WORDS: &[&str] = &["the", "a", "whether", "true", "false"];
 
+impl Ast {
     fn supports(&self, tag: &TagKind) -> bool {
         self.supported_tags().contains(tag)
     }
 
+    /// the keys of a ghost mapping are parameters, named either as declared
+    /// or by their position, as in `key0` for the outermost key
     fn defines_param(&self, param_name: &str) -> bool {
-        if let Some(params) = self.params() {
+        if let Some(mapping_type) = self.mapping_type() {
+            mapping_type.keys.iter().enumerate().any(|(i, key)| {
+                key.name == param_name || param_name.strip_prefix("key") == Some(&i.to_string())
+            })
+        } else if let Some(params) = self.params() {
             params.iter().any(|param| param.name == param_name)
         } else {
-            false
+            matches!(self, Ast::Unknown { .. } | Ast::Detached)
+        }
+    }
+}
+
+/// opt-in lints enforcing a documentation policy.
+/// each lint lists the element kinds it applies to, as displayed by [Ast],
+/// such as `"rule"` or `"invariant"`. a lint with no kinds is disabled, which is the default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LintConfig {
+    /// `require-title`: the documentation must have a `@title` tag
+    pub require_title: Vec<String>,
+    /// `require-notice`: the documentation must have a `@notice` tag, or leading untagged text
+    pub require_notice: Vec<String>,
+    /// `require-dev-for-filtered`: elements with filters must have a `@dev` tag
+    pub require_dev_for_filtered: Vec<String>,
+    /// by default, a `@param` of a `methods` block documents a receiver of its entries,
+    /// as in `@param token` for `function token.balanceOf(address) external returns (uint)`,
+    /// and is reported only if no entry mentions it. with this set, such tags are unsupported.
+    pub strict_methods_params: bool,
+}
+
+impl LintConfig {
+    fn applies(kinds: &[String], ast: &Ast) -> bool {
+        let kind = ast.to_string();
+        kinds.contains(&kind)
+    }
+}
+
+/// configuration of [check_collection]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectionConfig {
+    /// whether elements of different files may be duplicates of each other.
+    /// elements are of the same file if they share their [CvlElement::src].
+    pub cross_file_duplicates: bool,
+    /// `header-level-skip`: a freeform header is more than one level below the previous header
+    pub header_level_skip: bool,
+    /// `duplicate-header`: a freeform header repeats another header of the same section
+    pub duplicate_header: bool,
+}
+
+impl Default for CollectionConfig {
+    fn default() -> Self {
+        CollectionConfig {
+            cross_file_duplicates: true,
+            header_level_skip: true,
+            duplicate_header: true,
+        }
+    }
+}
+
+/// diagnostics which involve more than one element, possibly of different files.
+/// each diagnostic is paired with the index in `docs` of the element it applies to,
+/// and its range is within the source of that element.
+///
+/// `duplicate-element-name`: an element has the same kind and name as an earlier element.
+/// `methods` blocks and unrecognized declarations are ignored.
+///
+/// the headers of freeform comments, see [CvlElement::freeform_header], are checked within each file:
+/// `header-level-skip` for a header more than one level below the previous header, as in `##` followed
+/// by `####`, and `duplicate-header` for a header with the same text as an earlier header under
+/// the same parent. header text is compared case-insensitively, and headers of different sections may repeat.
+pub fn check_collection(
+    docs: &[CvlElement],
+    config: &CollectionConfig,
+) -> Vec<(usize, Diagnostic)> {
+    let mut diagnostics = Vec::new();
+    let mut declarations: HashMap<(String, &str), Vec<&CvlElement>> = HashMap::new();
+
+    for (i, element) in docs.iter().enumerate() {
+        if matches!(element.ast, Ast::Methods { .. } | Ast::Unknown { .. }) {
+            continue;
+        }
+        let Some(name) = element.element_name() else {
+            continue;
+        };
+
+        let kind = element.ast.to_string();
+        let earlier = declarations.entry((kind.clone(), name)).or_default();
+        let is_duplicate = earlier
+            .iter()
+            .any(|earlier| config.cross_file_duplicates || Arc::ptr_eq(&earlier.src, &element.src));
+
+        if is_duplicate {
+            let span = element.name_span().unwrap_or(element.element_span.clone());
+            let converter = RangeConverter::new(Rope::from_str(&element.src));
+
+            let diagnostic = Diagnostic {
+                range: converter.to_range(span),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("duplicate-element-name".to_string())),
+                message: format!("another {kind} is named {name}"),
+                ..Default::default()
+            };
+            diagnostics.push((i, diagnostic));
         }
+
+        earlier.push(element);
+    }
+
+    diagnostics.extend(check_headers(docs, config));
+    diagnostics.sort_by_key(|(i, _)| *i);
+
+    diagnostics
+}
+
+fn check_headers(docs: &[CvlElement], config: &CollectionConfig) -> Vec<(usize, Diagnostic)> {
+    /// an open section: the level of its header, and the normalized headers of its subsections
+    type Section = (usize, Vec<String>);
+
+    let mut diagnostics = Vec::new();
+    let mut sections_by_file: HashMap<*const str, Vec<Section>> = HashMap::new();
+
+    for (i, element) in docs.iter().enumerate() {
+        let Some((level, header)) = element.freeform_header() else {
+            continue;
+        };
+
+        // the root section, of level 0, holds the top-level headers of the file
+        let sections = sections_by_file
+            .entry(Arc::as_ptr(&element.src))
+            .or_insert_with(|| vec![(0, Vec::new())]);
+        let previous_level = sections.last().map(|(level, _)| *level).unwrap_or(0);
+
+        while sections.last().is_some_and(|(open, _)| *open >= level) {
+            sections.pop();
+        }
+
+        let mut add = |message, code: &str| {
+            let converter = RangeConverter::new(Rope::from_str(&element.src));
+            let diagnostic = Diagnostic {
+                range: converter.to_range(element.element_span.clone()),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String(code.to_string())),
+                message,
+                ..Default::default()
+            };
+            diagnostics.push((i, diagnostic));
+        };
+
+        if config.header_level_skip && previous_level > 0 && level > previous_level + 1 {
+            let message = format!("header skips from level {previous_level} to level {level}");
+            add(message, "header-level-skip");
+        }
+
+        let normalized = header.to_lowercase();
+        let (_, siblings) = sections
+            .last_mut()
+            .expect("the root section is never closed");
+        if config.duplicate_header && siblings.contains(&normalized) {
+            let message = format!("another header of this section is titled {header:?}");
+            add(message, "duplicate-header");
+        }
+        siblings.push(normalized);
+
+        sections.push((level, Vec::new()));
+    }
+
+    diagnostics
+}
+
+impl Builder<'_> {
+    /// diagnostics of the source as a whole, rather than of one of its elements:
+    /// `started-inside-comment` for the text skipped by [Builder::skipped_start].
+    pub fn source_diagnostics(&self) -> Vec<Diagnostic> {
+        let Some(skipped) = self.skipped_start() else {
+            return Vec::new();
+        };
+
+        let converter = RangeConverter::new(Rope::from_str(self.src()));
+        vec![Diagnostic {
+            range: converter.to_range(skipped),
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("started-inside-comment".to_string())),
+            message: "the source starts inside a comment, whose end is skipped".to_string(),
+            ..Default::default()
+        }]
+    }
+}
+
+impl Bundle {
+    /// diagnostics of the bundle as a whole: `malformed-marker` for [Bundle::malformed_marker],
+    /// on the line of the marker within the bundle.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let Some(marker) = &self.malformed_marker else {
+            return Vec::new();
+        };
+
+        let line = marker.line as u32;
+        let range = SimpleRange {
+            start: SimplePosition { line, character: 0 },
+            end: SimplePosition {
+                line,
+                character: marker.text.chars().count() as u32,
+            },
+        };
+        vec![Diagnostic {
+            range: range.into(),
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("malformed-marker".to_string())),
+            message: "malformed file marker: the rest of the bundle is part of the previous file"
+                .to_string(),
+            ..Default::default()
+        }]
     }
 }
 
 enum DiagSpan<'a> {
-    #[allow(unused)]
     EntireDoc,
     SingleTag(&'a DocumentationTag),
+    /// the first line of the documentation, or the element name if it is undocumented
+    Header,
+    Range(Span),
 }
 
 impl CvlElement {
+    fn header_span(&self) -> Span {
+        let first_line = |span: &Span| {
+            let len = self
+                .src
+                .chars()
+                .skip(span.start)
+                .take(span.len())
+                .take_while(|&c| c != '\n')
+                .count();
+            span.start..span.start + len
+        };
+
+        if let Some(doc_span) = &self.doc_span {
+            return first_line(doc_span);
+        }
+
+        self.name_span()
+            .unwrap_or_else(|| first_line(&self.element_span))
+    }
+
+    /// the span of the name of the element, which is looked up after the leading keyword
+    fn name_span(&self) -> Option<Span> {
+        let name = self.element_name()?;
+        let element_text: String = self
+            .src
+            .chars()
+            .skip(self.element_span.start)
+            .take(self.element_span.len())
+            .collect();
+        let keyword_len = element_text.find(char::is_whitespace)?;
+        let byte_start = keyword_len + element_text[keyword_len..].find(name)?;
+        let start = self.element_span.start + element_text[..byte_start].chars().count();
+
+        Some(start..start + name.chars().count())
+    }
+
+    /// the declaration index of the parameter of each `@param` tag, paired with the tag,
+    /// in the order of the tags. `None` if a tag names no parameter of the element.
+    fn param_tag_order(&self) -> Option<Vec<(usize, &DocumentationTag)>> {
+        let params = self.ast.params()?;
+
+        self.doc
+            .iter()
+            .filter(|tag| tag.param_name().is_some())
+            .map(|tag| {
+                let top_level_param = tag.param_path()[0];
+                let index = params
+                    .iter()
+                    .position(|param| param.name == top_level_param)?;
+                Some((index, tag))
+            })
+            .collect()
+    }
+
+    fn params_out_of_order(&self) -> bool {
+        self.param_tag_order()
+            .is_some_and(|order| order.windows(2).any(|pair| pair[0].0 > pair[1].0))
+    }
+
+    /// the edits which reorder the `@param` tags to the order in which the parameters are declared,
+    /// as the fix of a `param-order` diagnostic. each tag moves along with all of its lines,
+    /// and the other tags stay where they are.
+    ///
+    /// `None` if the tags are already in order, or if they cannot be moved as whole lines:
+    /// when a tag starts in the middle of a line, or shares a line with the start or end of the comment.
+    pub fn param_order_fix(&self) -> Option<Vec<TextEdit>> {
+        if !self.params_out_of_order() {
+            return None;
+        }
+        let order = self.param_tag_order()?;
+
+        let src = &*self.src;
+        let doc_bytes = self.doc_span.as_ref()?.to_byte_span(src)?;
+        let line_starts: Vec<usize> = std::iter::once(doc_bytes.start)
+            .chain(
+                src[doc_bytes.clone()]
+                    .match_indices('\n')
+                    .map(|(i, _)| doc_bytes.start + i + 1),
+            )
+            .collect();
+        let line_of = |byte: usize| line_starts.partition_point(|&start| start <= byte) - 1;
+        // the documentation span does not include the line break ending the comment
+        let comment_end = src[doc_bytes.end..]
+            .find('\n')
+            .map_or(src.len(), |i| doc_bytes.end + i + 1);
+        let line_end = |line: usize| line_starts.get(line + 1).copied().unwrap_or(comment_end);
+
+        // the lines of each tag, from the line it starts on to the line the next tag starts on
+        let mut tag_lines = Vec::new();
+        for (i, tag) in self.doc.iter().enumerate() {
+            let tag_bytes = tag.span.to_byte_span(src)?;
+            let first_line = line_of(tag_bytes.start);
+
+            let decoration = &src[line_starts[first_line]..tag_bytes.start];
+            if !decoration
+                .chars()
+                .all(|c| c.is_whitespace() || "/*!".contains(c))
+            {
+                return None;
+            }
+
+            let end = match self.doc.get(i + 1) {
+                Some(next) => line_starts[line_of(next.span.to_byte_span(src)?.start)],
+                None => {
+                    let mut last_line =
+                        line_of(tag_bytes.end.saturating_sub(1).max(tag_bytes.start));
+                    let is_closing = |line| {
+                        src[line_starts[line]..line_end(line)]
+                            .trim_start()
+                            .starts_with("*/")
+                    };
+                    if last_line > first_line && is_closing(last_line) {
+                        last_line -= 1;
+                    }
+                    line_end(last_line)
+                }
+            };
+            tag_lines.push(line_starts[first_line]..end);
+        }
+
+        let param_tags: Vec<usize> = self
+            .doc
+            .iter()
+            .enumerate()
+            .filter(|(_, tag)| tag.param_name().is_some())
+            .map(|(i, _)| i)
+            .collect();
+
+        let is_movable = |i: &usize| {
+            let lines = &src[tag_lines[*i].clone()];
+            lines.ends_with('\n') && !lines.contains("/*") && !lines.contains("*/")
+        };
+        if !param_tags.iter().all(is_movable) {
+            return None;
+        }
+
+        // each `@param` tag takes the place of the tag which is before it in declaration order
+        let mut sorted = order.iter().zip(&param_tags).collect::<Vec<_>>();
+        sorted.sort_by_key(|((index, _), _)| *index);
+        let mut moved = sorted.into_iter().map(|(_, i)| *i);
+
+        let (first, last) = (*param_tags.first()?, *param_tags.last()?);
+        let mut reordered = String::new();
+        for i in first..=last {
+            let i = match param_tags.contains(&i) {
+                true => moved.next()?,
+                false => i,
+            };
+            reordered.push_str(&src[tag_lines[i].clone()]);
+        }
+
+        let region = tag_lines[first].start..tag_lines[last].end;
+        let new_src = format!("{}{reordered}{}", &src[..region.start], &src[region.end..]);
+        Some(compute_edit_diff(src, &new_src))
+    }
+
     pub fn enumerate_diagnostics(&self, converter: RangeConverter) -> Vec<Diagnostic> {
+        self.enumerate_diagnostics_with(converter, &LintConfig::default())
+    }
+
+    /// like [CvlElement::enumerate_diagnostics], additionally running the lints enabled in `config`.
+    pub fn enumerate_diagnostics_with(
+        &self,
+        converter: RangeConverter,
+        config: &LintConfig,
+    ) -> Vec<Diagnostic> {
         let mut diagnostics = Vec::new();
 
-        let mut add = |message, diag_span, severity| {
+        let mut add = |message, diag_span, severity, code: Option<&str>| {
             let span = match diag_span {
                 DiagSpan::EntireDoc => self.element_span.clone(),
                 DiagSpan::SingleTag(tag) => tag.span.clone(),
+                DiagSpan::Header => self.header_span(),
+                DiagSpan::Range(span) => span,
             };
 
             let diag = Diagnostic {
                 range: converter.to_range(span),
                 severity: Some(severity),
+                code: code.map(|code| NumberOrString::String(code.to_string())),
                 message,
                 ..Default::default()
             };
@@ -67,36 +436,366 @@ impl CvlElement {
         // if self.doc.iter().all(|tag| tag.kind != TagKind::Notice) {
         //     //Any applicable item is missing a notice
         //     let message = "associated element is undocumented".to_string();
-        //     add(message, DiagSpan::EntireDoc, WARNING);
+        //     add(message, DiagSpan::EntireDoc, WARNING, None);
         // }
 
+        if matches!(self.ast, Ast::Detached) {
+            add(
+                "documentation has no associated element".to_string(),
+                DiagSpan::EntireDoc,
+                DiagnosticSeverity::WARNING,
+                Some("no-associated-element"),
+            );
+        }
+
+        for gap in &self.doc_gaps {
+            let gap_text = gap.byte_slice(&self.src).unwrap_or_default();
+            let blank_lines = count_lines(gap_text) - 1;
+            let plural = if blank_lines == 1 { "" } else { "s" };
+            let element = match self.ast {
+                Ast::Detached => "next declaration".to_string(),
+                ref ast => ast.to_string(),
+            };
+            let message = format!(
+                "{blank_lines} blank line{plural} between the documentation and the {element}"
+            );
+            add(
+                message,
+                DiagSpan::Range(gap.clone()),
+                DiagnosticSeverity::INFORMATION,
+                Some("doc-element-gap"),
+            );
+        }
+
         let tags_with_params = self.doc.iter().filter_map(|tag| {
             let param = tag.param_name()?;
             Some((tag, param))
         });
 
+        let methods_params =
+            matches!(self.ast, Ast::Methods { .. }) && !config.strict_methods_params;
+
         for (i, (tag, param)) in tags_with_params.enumerate() {
-            if !self.ast.defines_param(param) {
+            // allow documenting fields of a parameter, as in `@param args.amount`
+            let top_level_param = tag.param_path()[0];
+
+            if methods_params {
+                let entries = self.ast.methods_entries().unwrap_or_default();
+                if !entries
+                    .iter()
+                    .any(|entry| mentions_receiver(entry, top_level_param))
+                {
+                    let message = format!("no entry of the methods block mentions {param}");
+                    add(
+                        message,
+                        DiagSpan::SingleTag(tag),
+                        DiagnosticSeverity::ERROR,
+                        None,
+                    );
+                }
+            } else if !self.ast.defines_param(top_level_param) {
                 //A @param is provided for a non-existent parameter
                 let message = format!("no such parameter: {param}");
-                add(message, DiagSpan::SingleTag(tag), DiagnosticSeverity::ERROR);
+                add(
+                    message,
+                    DiagSpan::SingleTag(tag),
+                    DiagnosticSeverity::ERROR,
+                    None,
+                );
             } else if self.doc[..i]
                 .iter()
                 .any(|tag| tag.param_name() == Some(param))
             {
                 //Each parameter must be documented at most once
                 let message = "parameter is already documented".to_string();
-                add(message, DiagSpan::SingleTag(tag), DiagnosticSeverity::ERROR);
+                add(
+                    message,
+                    DiagSpan::SingleTag(tag),
+                    DiagnosticSeverity::ERROR,
+                    None,
+                );
             }
         }
 
         for tag in &self.doc {
-            if !self.ast.supports(&tag.kind) {
+            let is_empty_tag = |line: &str| {
+                let rest = line.trim_start().strip_prefix('@');
+                rest.is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+            };
+            if tag.description.lines().any(is_empty_tag) {
+                add(
+                    "`@` is not followed by a tag name".to_string(),
+                    DiagSpan::SingleTag(tag),
+                    DiagnosticSeverity::WARNING,
+                    Some("empty-tag"),
+                );
+            }
+
+            if let Err(error) = tag.validate() {
+                add(
+                    error.to_string(),
+                    DiagSpan::SingleTag(tag),
+                    DiagnosticSeverity::WARNING,
+                    Some("invalid-tag"),
+                );
+            }
+
+            let methods_param = methods_params && tag.kind == TagKind::Param;
+            if !self.ast.supports(&tag.kind) && !methods_param {
                 let message = format!("this tag is unsupported for {} blocks", self.ast);
-                add(message, DiagSpan::SingleTag(tag), DiagnosticSeverity::ERROR);
+                add(
+                    message,
+                    DiagSpan::SingleTag(tag),
+                    DiagnosticSeverity::ERROR,
+                    None,
+                );
+            }
+        }
+
+        let params = self.ast.params().unwrap_or_default();
+        if !params.is_empty() && self.ast.returns().is_some() {
+            let returns = self.doc.iter().filter(|tag| tag.kind == TagKind::Return);
+
+            for tag in returns {
+                let Some(word) = tag.description.split_whitespace().next() else {
+                    continue;
+                };
+                let word = word.trim_end_matches(|c: char| !c.is_alphanumeric() && c != '_');
+                let is_stop_word = RETURN_STOP_WORDS
+                    .iter()
+                    .any(|stop_word| stop_word.eq_ignore_ascii_case(word));
+
+                if !is_stop_word && params.iter().any(|param| param.name == word) {
+                    let message = format!(
+                        "@return starts with the parameter name {word}: \
+                        rename the return value, or remove the leading word"
+                    );
+                    add(
+                        message,
+                        DiagSpan::SingleTag(tag),
+                        DiagnosticSeverity::WARNING,
+                        Some("return-shadows-param"),
+                    );
+                }
+            }
+        }
+
+        if let Ast::FreeFormComment {
+            truncated: true, ..
+        } = &self.ast
+        {
+            add(
+                "comment is too large to be parsed, and was kept as a freeform comment".to_string(),
+                DiagSpan::EntireDoc,
+                DiagnosticSeverity::WARNING,
+                Some("comment-too-large"),
+            );
+        }
+
+        if let Ast::Invariant {
+            proof: Some(proof), ..
+        } = &self.ast
+        {
+            if proof.is_empty() {
+                let message = "invariant has an empty proof block".to_string();
+                add(
+                    message,
+                    DiagSpan::EntireDoc,
+                    DiagnosticSeverity::WARNING,
+                    Some("empty-proof-block"),
+                );
+            }
+        }
+
+        if let Ast::GhostFunction {
+            axioms: Some(_), ..
+        }
+        | Ast::GhostMapping {
+            axioms: Some(_), ..
+        } = &self.ast
+        {
+            if self.source_text().ends_with(';') {
+                let end = self.element_span.end;
+                add(
+                    "redundant semicolon after the ghost block".to_string(),
+                    DiagSpan::Range(end - 1..end),
+                    DiagnosticSeverity::INFORMATION,
+                    Some("redundant-semicolon"),
+                );
             }
         }
 
+        let has_tag = |kind| self.doc.iter().any(|tag| tag.kind == kind);
+
+        if LintConfig::applies(&config.require_title, &self.ast) && !has_tag(TagKind::Title) {
+            let message = format!("{} is missing a @title tag", self.ast);
+            add(
+                message,
+                DiagSpan::Header,
+                DiagnosticSeverity::WARNING,
+                Some("require-title"),
+            );
+        }
+
+        if LintConfig::applies(&config.require_notice, &self.ast) && !has_tag(TagKind::Notice) {
+            let message = format!("{} is missing a @notice tag", self.ast);
+            add(
+                message,
+                DiagSpan::Header,
+                DiagnosticSeverity::WARNING,
+                Some("require-notice"),
+            );
+        }
+
+        if LintConfig::applies(&config.require_dev_for_filtered, &self.ast)
+            && self.ast.filters().is_some()
+            && !has_tag(TagKind::Dev)
+        {
+            let message = format!("{} has filters but is missing a @dev tag", self.ast);
+            add(
+                message,
+                DiagSpan::Header,
+                DiagnosticSeverity::WARNING,
+                Some("require-dev-for-filtered"),
+            );
+        }
+
+        if self.params_out_of_order() {
+            let param_tags = self
+                .doc
+                .iter()
+                .filter(|tag| tag.param_name().is_some())
+                .collect_vec();
+            let span = param_tags[0].span.start..param_tags[param_tags.len() - 1].span.end;
+
+            let params = self.ast.params().unwrap_or_default();
+            let expected = params.iter().map(|param| param.name.as_str()).join(", ");
+
+            // the fix is carried as data, for the language server to offer as a code action
+            let fix = self.param_order_fix();
+            diagnostics.push(Diagnostic {
+                range: converter.to_range(span),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("param-order".to_string())),
+                message: format!("@param tags are not in the order of the parameters: {expected}"),
+                data: fix.map(|edits| serde_json::json!({ "edits": edits })),
+                ..Default::default()
+            });
+        }
+
         diagnostics
     }
 }
+
+/// configuration of [render] and [render_all]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// color the severity and the underline with ANSI escape codes
+    pub ansi: bool,
+}
+
+/// renders `diag` in the style of rustc: the severity, code and message,
+/// the location as `filename:line:column`, and the source line with the range underlined.
+/// a range spanning several lines is underlined to the end of its first line, followed by `...`.
+pub fn render(diag: &Diagnostic, src: &str, filename: &str, opts: &RenderOptions) -> String {
+    let severity = severity_name(diag.severity);
+    let (color, reset) = match opts.ansi {
+        true => (severity_color(diag.severity), "\x1b[0m"),
+        false => ("", ""),
+    };
+    let code = match &diag.code {
+        Some(NumberOrString::String(code)) => format!("[{code}]"),
+        Some(NumberOrString::Number(code)) => format!("[{code}]"),
+        None => String::new(),
+    };
+
+    // an inverted range is rendered as the range between the same positions
+    let start = diag.range.start.min(diag.range.end);
+    let end = diag.range.start.max(diag.range.end);
+    let line_number = (start.line + 1).to_string();
+    let gutter = " ".repeat(line_number.len());
+
+    let line = src
+        .split('\n')
+        .nth(start.line as usize)
+        .unwrap_or_default()
+        .trim_end_matches('\r');
+    let line_len = line.chars().count();
+    let start_col = (start.character as usize).min(line_len);
+
+    let (underline_len, continuation) = match end.line > start.line {
+        true => (line_len - start_col, "..."),
+        false => ((end.character as usize).min(line_len) - start_col, ""),
+    };
+    // keep tabs, so that the underline is aligned with the source line
+    let indent: String = line
+        .chars()
+        .take(start_col)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let underline = "^".repeat(underline_len.max(1));
+
+    format!(
+        "{color}{severity}{code}{reset}: {message}\n\
+         {gutter}--> {filename}:{line_number}:{column}\n\
+         {gutter} |\n\
+         {line_number} | {line}\n\
+         {gutter} | {indent}{color}{underline}{reset}{continuation}\n",
+        message = diag.message,
+        column = start.character + 1,
+    )
+}
+
+/// renders each of `diags` with [render], ordered by position,
+/// followed by the number of diagnostics of each severity.
+pub fn render_all(diags: &[Diagnostic], src: &str, filename: &str, opts: &RenderOptions) -> String {
+    let mut sorted = diags.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|diag| (diag.range.start.line, diag.range.start.character));
+
+    let mut rendered = sorted
+        .iter()
+        .map(|diag| render(diag, src, filename, opts) + "\n")
+        .collect::<String>();
+
+    let counts = ["error", "warning", "info", "hint"]
+        .into_iter()
+        .filter_map(|name| {
+            let count = diags
+                .iter()
+                .filter(|diag| severity_name(diag.severity) == name)
+                .count();
+            match count {
+                0 => None,
+                1 => Some(format!("1 {name}")),
+                _ if name == "info" => Some(format!("{count} {name}")),
+                _ => Some(format!("{count} {name}s")),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    match counts.is_empty() {
+        true => rendered.push_str("no diagnostics\n"),
+        false => rendered.push_str(&format!("{}\n", counts.join(", "))),
+    }
+
+    rendered
+}
+
+/// diagnostics without a severity are errors
+fn severity_name(severity: Option<DiagnosticSeverity>) -> &'static str {
+    match severity {
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        Some(DiagnosticSeverity::INFORMATION) => "info",
+        Some(DiagnosticSeverity::HINT) => "hint",
+        _ => "error",
+    }
+}
+
+fn severity_color(severity: Option<DiagnosticSeverity>) -> &'static str {
+    match severity_name(severity) {
+        "warning" => "\x1b[1;33m",
+        "info" => "\x1b[1;34m",
+        "hint" => "\x1b[1;36m",
+        _ => "\x1b[1;31m",
+    }
+}
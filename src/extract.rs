@@ -0,0 +1,172 @@
+//! information scanned from the bodies of elements, without parsing CVL expressions.
+
+use crate::util::{ByteSpan, Span};
+use crate::{Ast, CvlElement};
+
+/// the messages of the `assert <expr>, "message";` and `require <expr>, "message";` statements
+/// in the block of a rule or a function, or in the proof of an invariant, paired with the span
+/// of the string literal, quotes included. statements in nested blocks are found,
+/// while those in comments and string literals are not.
+/// escape sequences in a message are replaced by the escaped character.
+pub fn assert_messages(element: &CvlElement) -> Vec<(Span, String)> {
+    let block = match &element.ast {
+        Ast::Rule { block, .. } => block,
+        Ast::Function {
+            block: Some(block), ..
+        } => block,
+        Ast::Invariant {
+            proof: Some(proof), ..
+        } => proof,
+        _ => return Vec::new(),
+    };
+
+    // the block is the last part of the element, followed only by the closing brace
+    let Some(element_bytes) = element.element_span.to_byte_span(&element.src) else {
+        return Vec::new();
+    };
+    let element_text = &element.src[element_bytes.clone()];
+    let Some(block_start) = element_text.rfind(block.as_str()) else {
+        return Vec::new();
+    };
+    let block_start = element_bytes.start + block_start;
+    let char_start = element.src[..block_start].chars().count();
+
+    let chars: Vec<char> = block.chars().collect();
+    Scanner {
+        chars: &chars,
+        i: 0,
+    }
+    .messages()
+    .into_iter()
+    .map(|(span, message)| (span.start + char_start..span.end + char_start, message))
+    .collect()
+}
+
+struct Scanner<'a> {
+    chars: &'a [char],
+    i: usize,
+}
+
+impl Scanner<'_> {
+    fn messages(&mut self) -> Vec<(Span, String)> {
+        let mut messages = Vec::new();
+
+        while self.i < self.chars.len() {
+            if self.skip_comment_or_string().is_some() {
+                continue;
+            }
+
+            let starts_word = self.i == 0 || !is_identifier_char(self.chars[self.i - 1]);
+            if starts_word && (self.at_keyword("assert") || self.at_keyword("require")) {
+                if let Some(message) = self.statement_message() {
+                    messages.push(message);
+                }
+                continue;
+            }
+
+            self.i += 1;
+        }
+
+        messages
+    }
+
+    fn at_keyword(&mut self, keyword: &str) -> bool {
+        let end = self.i + keyword.len();
+        let matches = self.chars.len() > end
+            && self.chars[self.i..end].iter().copied().eq(keyword.chars())
+            && !is_identifier_char(self.chars[end]);
+        if matches {
+            self.i = end;
+        }
+        matches
+    }
+
+    /// scans the rest of a statement, up to its `;`. the message is the string literal
+    /// which makes up all of the statement after its last top-level comma.
+    fn statement_message(&mut self) -> Option<(Span, String)> {
+        let mut depth = 0usize;
+        let mut after_comma: Option<Vec<Option<(Span, String)>>> = None;
+
+        while self.i < self.chars.len() {
+            let c = self.chars[self.i];
+
+            if let Some(skipped) = self.skip_comment_or_string() {
+                if let (Some(string), Some(items)) = (skipped, after_comma.as_mut()) {
+                    items.push(Some(string));
+                }
+                continue;
+            }
+
+            self.i += 1;
+            match c {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' if depth == 0 => {
+                    // the end of an enclosing block: the statement is unterminated
+                    self.i -= 1;
+                    return None;
+                }
+                ')' | ']' | '}' => depth -= 1,
+                ',' if depth == 0 => {
+                    after_comma = Some(Vec::new());
+                    continue;
+                }
+                ';' if depth == 0 => break,
+                c if c.is_whitespace() => continue,
+                _ => (),
+            }
+
+            if let Some(items) = after_comma.as_mut() {
+                items.push(None);
+            }
+        }
+
+        match after_comma?.as_slice() {
+            [Some(message)] => Some(message.clone()),
+            _ => None,
+        }
+    }
+
+    /// skips a comment or a string literal at the current position.
+    /// returns `Some(None)` for a comment, and the span and value of a string literal.
+    fn skip_comment_or_string(&mut self) -> Option<Option<(Span, String)>> {
+        let start = self.i;
+        let next = self.chars.get(start + 1).copied();
+
+        match (self.chars[start], next) {
+            ('/', Some('/')) => {
+                while self.i < self.chars.len() && self.chars[self.i] != '\n' {
+                    self.i += 1;
+                }
+                Some(None)
+            }
+            ('/', Some('*')) => {
+                self.i += 2;
+                while self.i < self.chars.len()
+                    && !(self.chars[self.i] == '*' && self.chars.get(self.i + 1) == Some(&'/'))
+                {
+                    self.i += 1;
+                }
+                self.i = (self.i + 2).min(self.chars.len());
+                Some(None)
+            }
+            ('"', _) => {
+                let mut value = String::new();
+                self.i += 1;
+                while self.i < self.chars.len() && self.chars[self.i] != '"' {
+                    if self.chars[self.i] == '\\' && self.i + 1 < self.chars.len() {
+                        self.i += 1;
+                    }
+                    value.push(self.chars[self.i]);
+                    self.i += 1;
+                }
+                self.i = (self.i + 1).min(self.chars.len());
+                Some(Some((start..self.i, value)))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
@@ -0,0 +1,103 @@
+//! fingerprints of documentation, for detecting which elements were substantially changed
+//! since a baseline, without access to the history of the source.
+//!
+//! a fingerprint covers the kind and the description of each tag, in order.
+//! descriptions are normalized, so that these changes do not affect the fingerprint:
+//! - leading and trailing whitespace
+//! - the amount and kind of whitespace between words, including line breaks
+//!
+//! spans, comment style and tag provenance are ignored, as is anything outside the documentation.
+//! the hash is FNV-1a, so fingerprints are stable across platforms and compiler versions.
+
+use crate::CvlElement;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Fingerprint(pub u64);
+
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// the fingerprints of a collection of elements, keyed by [element_key]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BaselineFile {
+    pub fingerprints: BTreeMap<String, Fingerprint>,
+}
+
+/// the fingerprint of the documentation of `element`
+pub fn compute(element: &CvlElement) -> Fingerprint {
+    let mut hasher = Fnv1a::default();
+
+    for tag in &element.doc {
+        hasher.write(tag.kind.as_str().as_bytes());
+        hasher.write(&[0]);
+
+        for (i, word) in tag.description.split_whitespace().enumerate() {
+            if i > 0 {
+                hasher.write(b" ");
+            }
+            hasher.write(word.as_bytes());
+        }
+        hasher.write(&[0]);
+    }
+
+    Fingerprint(hasher.0)
+}
+
+/// identifies an element across versions of a file by its kind and name, as in `rule foo`.
+/// elements without a name, such as freeform comments, have no key.
+pub fn element_key(element: &CvlElement) -> Option<String> {
+    let name = element.element_name()?;
+    Some(format!("{} {name}", element.ast))
+}
+
+/// the fingerprints of the documented elements of `docs` which have a key.
+/// for repeated keys, the first element is kept.
+pub fn baseline(docs: &[CvlElement]) -> BaselineFile {
+    let mut fingerprints = BTreeMap::new();
+
+    for element in docs.iter().filter(|element| !element.doc.is_empty()) {
+        if let Some(key) = element_key(element) {
+            fingerprints.entry(key).or_insert_with(|| compute(element));
+        }
+    }
+
+    BaselineFile { fingerprints }
+}
+
+/// the documented elements of `docs` whose fingerprint differs from `baseline`,
+/// including those which are not in the baseline.
+pub fn changed<'a>(docs: &'a [CvlElement], baseline: &BaselineFile) -> Vec<&'a CvlElement> {
+    docs.iter()
+        .filter(|element| !element.doc.is_empty())
+        .filter(|element| {
+            let Some(key) = element_key(element) else {
+                return false;
+            };
+            baseline.fingerprints.get(&key) != Some(&compute(element))
+        })
+        .collect()
+}
+
+/// 64-bit FNV-1a
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf29ce484222325)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
@@ -0,0 +1,33 @@
+//! construction of elements from JSON, as produced by serializing them, such as test fixtures.
+
+use crate::CvlElement;
+use eyre::{bail, eyre};
+use serde_json::Value;
+
+/// the fields an element cannot be constructed without
+const REQUIRED_FIELDS: &[&str] = &["doc", "ast", "element_span"];
+
+/// the JSON must be an object with the fields of a serialized [CvlElement]. `doc_span` may be
+/// omitted or `null` for undocumented elements, and `doc_gaps`, `annotations` and the line and
+/// column ranges may be omitted.
+/// the source is not part of the JSON, so [CvlElement::src] is empty.
+impl TryFrom<Value> for CvlElement {
+    type Error = eyre::Report;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let Value::Object(fields) = &value else {
+            bail!("expected an element object, found {value}");
+        };
+
+        let missing = REQUIRED_FIELDS
+            .iter()
+            .filter(|field| !fields.contains_key(**field))
+            .map(|field| format!("`{field}`"))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            bail!("element is missing {}", missing.join(", "));
+        }
+
+        serde_json::from_value(value).map_err(|e| eyre!("invalid element: {e}"))
+    }
+}
@@ -1,21 +1,108 @@
+#[cfg(feature = "cache")]
+pub mod cache;
+#[cfg(feature = "lsp")]
+pub mod completion;
+pub mod coverage;
+#[cfg(feature = "lsp")]
 pub mod diagnostics;
+pub mod extract;
+pub mod fingerprint;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod links;
 pub mod parse;
+pub mod prelude;
+pub mod query;
+pub mod render;
+pub mod requirements;
+pub mod strip;
 pub mod util;
+pub mod workspace;
 
-use color_eyre::eyre::bail;
-use serde::Serialize;
+#[cfg(feature = "lsp")]
+pub use lsp_types;
+pub use ropey;
+
+use eyre::bail;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Debug, Display};
 use std::sync::Arc;
-use util::{ByteSpan, Span};
+use util::{ByteSpan, RangeConverter, SimpleRange, SourceMap, Span};
 
-#[derive(Clone, PartialEq, Eq, Serialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CvlElement {
     pub doc: Vec<DocumentationTag>,
     pub ast: Ast,
     pub element_span: Span,
     pub doc_span: Option<Span>,
+    /// [CvlElement::element_span] as lines and columns. serialized as an LSP range.
+    #[serde(default)]
+    pub element_range: SimpleRange,
+    /// [CvlElement::doc_span] as lines and columns. serialized as an LSP range.
+    #[serde(default)]
+    pub doc_range: Option<SimpleRange>,
+    /// the runs of blank lines between the documentation and the element.
+    /// this is only set under [crate::parse::builder::AttachmentPolicy::AttachWithWarning],
+    /// and for [Ast::Detached] documentation which the attachment policy did not attach,
+    /// where they are the runs before the next declaration.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub doc_gaps: Vec<Span>,
     #[serde(skip)]
     pub src: Arc<str>,
+    /// metadata attached by tools, such as a test status. it is never set by the parser,
+    /// and is ignored when comparing elements. it is serialized with its keys in order.
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// elements are compared by their documentation, [Ast], spans, gaps and source.
+/// [CvlElement::annotations] are ignored, as are [CvlElement::element_range] and
+/// [CvlElement::doc_range], which are derived from the spans and the source.
+impl PartialEq for CvlElement {
+    fn eq(&self, other: &Self) -> bool {
+        // destructured, so that a new field must be either compared or listed as ignored
+        let CvlElement {
+            doc,
+            ast,
+            element_span,
+            doc_span,
+            element_range: _,
+            doc_range: _,
+            doc_gaps,
+            src,
+            annotations: _,
+        } = self;
+
+        *doc == other.doc
+            && *ast == other.ast
+            && *element_span == other.element_span
+            && *doc_span == other.doc_span
+            && *doc_gaps == other.doc_gaps
+            && *src == other.src
+    }
+}
+
+impl Eq for CvlElement {}
+
+/// empty [Ast::Detached] documentation with no source, to be built with [CvlElement::with_tag],
+/// as in `CvlElement::default().with_tag(title).with_tag(notice)`
+impl Default for CvlElement {
+    fn default() -> Self {
+        CvlElement {
+            doc: Vec::new(),
+            ast: Ast::Detached,
+            element_span: 0..0,
+            doc_span: Some(0..0),
+            element_range: SimpleRange::default(),
+            doc_range: Some(SimpleRange::default()),
+            doc_gaps: Vec::new(),
+            src: Arc::from(""),
+            annotations: BTreeMap::new(),
+        }
+    }
 }
 
 impl Debug for CvlElement {
@@ -29,12 +116,17 @@ impl Debug for CvlElement {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Param {
     pub ty: String,
     pub name: String,
 }
 impl Param {
+    /// whether the parameter is of type `method`
+    pub fn is_method(&self) -> bool {
+        self.ty.trim() == "method"
+    }
+
     pub fn new<S1: ToString, S2: ToString>(ty: S1, name: S2) -> Param {
         Param {
             ty: ty.to_string(),
@@ -43,30 +135,107 @@ impl Param {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// the type of a ghost mapping, as in `mapping(address => mapping(uint256 => bool))`.
+/// nested mappings are flattened: the keys are in order from the outermost mapping
+/// to the innermost, and the value is that of the innermost mapping.
+/// types are kept as written, without whitespace.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MappingType {
+    /// keys without a name, as in `mapping(address => uint256)`, have an empty [Param::name]
+    pub keys: Vec<Param>,
+    /// the type of the values. a name given to the values is dropped.
+    pub value: String,
+}
+
+impl MappingType {
+    /// parses `mapping(K => V)`, with any whitespace between tokens.
+    /// keys and values may be named, as in `mapping(address owner => uint256 balance)`.
+    /// returns `None` for anything else, including types which are not mappings.
+    pub fn parse(ty: &str) -> Option<MappingType> {
+        parse::mapping::parse_mapping(ty)
+    }
+
+    /// the types of the keys, from the outermost mapping to the innermost
+    pub fn key_types(&self) -> impl Iterator<Item = &str> {
+        self.keys.iter().map(|key| key.ty.as_str())
+    }
+}
+
+/// the canonical text of the mapping, as in `mapping(address owner => mapping(uint256 => bool))`,
+/// which [MappingType::parse] parses back to the same type.
+impl Display for MappingType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for key in &self.keys {
+            match key.name.as_str() {
+                "" => write!(f, "mapping({} => ", key.ty)?,
+                name => write!(f, "mapping({} {name} => ", key.ty)?,
+            }
+        }
+        write!(f, "{}", self.value)?;
+        for _ in &self.keys {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum Ast {
+    /// the text of the comment, with comment markers and decoration removed.
+    /// interior newlines are preserved, and leading and trailing blank lines are not.
+    /// the comment as written is [CvlElement::source_text].
     FreeFormComment {
         text: String,
+        /// a separator made only of punctuation, such as `//// ======` or `/****/`.
+        /// see [crate::parse::builder::ParseConfig::detect_decorative].
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        decorative: bool,
+        /// the first element after the comment which is not a comment, however far it is.
+        /// it is only recorded under [crate::parse::builder::ParseConfig::record_following_element].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        following_element: Option<ElementRef>,
+        /// the comment is larger than [crate::parse::builder::ParseConfig::max_comment_bytes],
+        /// so it was not parsed as documentation, and `text` is only its beginning.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        truncated: bool,
     },
+    /// serialized with a `parametric` field for rules which are [Ast::is_parametric].
+    /// the field is ignored when deserializing.
+    #[serde(serialize_with = "serialize_rule")]
     Rule {
         name: String,
         params: Vec<Param>,
         filters: Option<String>,
+        /// `identifier "string"` pairs between the parameters and the block,
+        /// such as `description "..."`, in order of appearance
+        attributes: Vec<(String, String)>,
         block: String,
     },
     Invariant {
+        /// declared as `strong invariant`
+        strong: bool,
         name: String,
         params: Vec<Param>,
         invariant: String,
         filters: Option<String>,
+        /// `None` if the invariant has no proof block, `Some("")` if the block is empty.
+        /// the surrounding braces are not included.
         proof: Option<String>,
+        /// `identifier "string"` pairs following the expression or the filters
+        attributes: Vec<(String, String)>,
     },
     Function {
         name: String,
         params: Vec<Param>,
+        /// multiple return types are separated by `", "`, without the surrounding parentheses.
         returns: Option<String>,
-        block: String,
+        /// each of the return types, in order. empty if the function returns nothing.
+        /// see [Ast::return_types].
+        #[serde(default)]
+        return_types: Vec<String>,
+        /// `None` if the function is declared without a body, `Some("")` if the body is empty.
+        block: Option<String>,
     },
     Definition {
         name: String,
@@ -74,6 +243,12 @@ pub enum Ast {
         returns: String,
         definition: String,
     },
+    /// a macro declared as `define macro NAME(params) = body;`
+    CVLMacro {
+        name: String,
+        params: Vec<Param>,
+        body: String,
+    },
     GhostFunction {
         persistent: bool,
         name: String,
@@ -84,11 +259,23 @@ pub enum Ast {
     GhostMapping {
         persistent: bool,
         name: String,
+        /// the type of the ghost as parsed, such as `mapping( address = > uint )`,
+        /// which need not be a mapping
         mapping: String,
+        /// `mapping` parsed, if it is a mapping. `None` for ghost variables such as `ghost uint256 x;`.
+        #[serde(default)]
+        mapping_type: Option<MappingType>,
         axioms: Option<String>,
+        /// the expression of a ghost declared as `ghost uint256 x = 0;`.
+        /// it is `None` for ghosts with an axioms block, or without either.
+        initial_value: Option<String>,
     },
     Methods {
         block: String,
+        /// the documentation of each entry of the block, as parsed. it is only recorded under
+        /// [crate::parse::builder::ParseConfig::record_methods_coverage].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        coverage: Option<coverage::MethodsCoverage>,
     },
     Import {
         imported: String,
@@ -129,6 +316,51 @@ pub enum Ast {
         returns: Option<Param>,
         block: String,
     },
+    /// documentation of the entire file, written with `//!` or `/*!`.
+    /// it is not associated with the element that follows it.
+    FileDocumentation,
+    /// documentation which is associated with no element: documentation before a closing brace,
+    /// at the end of the file, or followed by other documentation under
+    /// [crate::parse::builder::ParseConfig::detach_superseded_documentation]. it is reported as
+    /// `no-associated-element`. its element span is that of the documentation.
+    Detached,
+    /// a documented declaration which is not recognized by the parser.
+    /// `raw_header` is everything up to the opening brace or semicolon, and `range` is its range.
+    Unknown {
+        first_token: String,
+        raw_header: String,
+        #[serde(default)]
+        range: SimpleRange,
+    },
+}
+
+/// the kind of a documented element, see [CvlElement::element_kind].
+/// unlike the [Display] of [Ast], ghost functions and ghost mappings are distinguished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ElementKind {
+    Rule,
+    Invariant,
+    Function,
+    Definition,
+    Macro,
+    GhostFunction,
+    GhostMapping,
+    Methods,
+    Import,
+    Using,
+    Use,
+    Hook,
+    Unknown,
+}
+
+/// identifies an element of the same source, see [Ast::FreeFormComment]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ElementRef {
+    pub kind: ElementKind,
+    /// `None` for elements without a name, such as `methods` blocks
+    pub name: Option<String>,
+    /// the [CvlElement::element_span] of the element
+    pub span: Span,
 }
 
 impl CvlElement {
@@ -145,6 +377,58 @@ impl CvlElement {
         from_title_tag.or_else(from_name)
     }
 
+    /// the name of the documented element, such as the name of a rule
+    pub fn element_name(&self) -> Option<&str> {
+        self.ast.name()
+    }
+
+    /// the kind of the documented element, or `None` for freeform comments and file documentation
+    pub fn element_kind(&self) -> Option<ElementKind> {
+        let kind = match &self.ast {
+            Ast::FreeFormComment { .. } | Ast::FileDocumentation | Ast::Detached => return None,
+            Ast::Rule { .. } => ElementKind::Rule,
+            Ast::Invariant { .. } => ElementKind::Invariant,
+            Ast::Function { .. } => ElementKind::Function,
+            Ast::Definition { .. } => ElementKind::Definition,
+            Ast::CVLMacro { .. } => ElementKind::Macro,
+            Ast::GhostFunction { .. } => ElementKind::GhostFunction,
+            Ast::GhostMapping { .. } => ElementKind::GhostMapping,
+            Ast::Methods { .. } => ElementKind::Methods,
+            Ast::Import { .. } => ElementKind::Import,
+            Ast::Using { .. } => ElementKind::Using,
+            Ast::UseRule { .. } | Ast::UseBuiltinRule { .. } | Ast::UseInvariant { .. } => {
+                ElementKind::Use
+            }
+            Ast::HookSload { .. }
+            | Ast::HookSstore { .. }
+            | Ast::HookCreate { .. }
+            | Ast::HookOpcode { .. } => ElementKind::Hook,
+            Ast::Unknown { .. } => ElementKind::Unknown,
+        };
+
+        Some(kind)
+    }
+
+    pub fn is_freeform(&self) -> bool {
+        matches!(self.ast, Ast::FreeFormComment { .. })
+    }
+
+    /// whether this is a freeform comment used as a separator, see [Ast::FreeFormComment]
+    pub fn is_decorative(&self) -> bool {
+        matches!(
+            self.ast,
+            Ast::FreeFormComment {
+                decorative: true,
+                ..
+            }
+        )
+    }
+
+    /// whether this is documentation, of an element or of the file, rather than a freeform comment
+    pub fn is_documentation(&self) -> bool {
+        !self.is_freeform()
+    }
+
     pub fn span(&self) -> Span {
         let start = if let Some(doc_span) = &self.doc_span {
             doc_span.start
@@ -157,16 +441,485 @@ impl CvlElement {
         start..end
     }
 
-    pub fn raw(&self) -> &str {
+    /// the source text of this element, exactly as it appears in the source file.
+    /// this starts at the documentation, if there is any, and ends at the end of the element,
+    /// with no surrounding whitespace.
+    pub fn source_text(&self) -> &str {
         self.span().byte_slice(&self.src).unwrap()
     }
+
+    /// checks that [CvlElement::source_text] is identical to the text of `src` at the span
+    /// of this element. this is useful when keeping elements around while `src` is edited.
+    pub fn verify_against(&self, src: &str) -> Result<(), RawMismatch> {
+        let span = self.span();
+        let expected = span
+            .byte_slice(src)
+            .ok_or_else(|| RawMismatch::OutOfBounds { span: span.clone() })?;
+        let found = self.source_text();
+
+        if expected == found {
+            Ok(())
+        } else {
+            Err(RawMismatch::Differs {
+                expected: expected.to_string(),
+                found: found.to_string(),
+            })
+        }
+    }
+
+    /// whether the documentation of this element is complete, according to the default
+    /// [coverage::CompletenessConfig]: it has a `@notice` or `@title` tag,
+    /// and a `@param` tag for each parameter.
+    pub fn has_complete_documentation(&self) -> bool {
+        coverage::CompletenessConfig::default().is_complete(self)
+    }
+
+    pub fn first_tag(&self) -> Option<&DocumentationTag> {
+        self.doc.first()
+    }
+
+    pub fn last_tag(&self) -> Option<&DocumentationTag> {
+        self.doc.last()
+    }
+
+    /// reorders the tags in the default [TagOrder]. see [CvlElement::sort_tags_with].
+    pub fn sort_tags_by_kind(self) -> CvlElement {
+        self.sort_tags_with(&TagOrder::default())
+    }
+
+    /// reorders the tags by kind, as listed in `order`. tags of the same kind keep their
+    /// relative order, and tags of kinds missing from `order` go last.
+    /// the spans of the tags are unchanged, so they are no longer in increasing order.
+    pub fn sort_tags_with(mut self, order: &TagOrder) -> CvlElement {
+        self.doc.sort_by_key(|tag| order.rank(&tag.kind));
+        self
+    }
+
+    /// the text of this element's documentation, for full-text search: the text of a freeform
+    /// comment, or the description of every tag in order. tag names are not included.
+    pub fn all_descriptions(&self) -> Vec<String> {
+        match &self.ast {
+            Ast::FreeFormComment { text, .. } => vec![text.clone()],
+            _ => self.doc.iter().map(|tag| tag.description.clone()).collect(),
+        }
+    }
+
+    /// replaces the documentation of this element with `new_comment_text`, reparsing only the comment.
+    /// this is cheaper than reparsing the file after an edit inside a comment.
+    ///
+    /// `new_comment_text` must be a single documentation comment, of the same kind (file
+    /// documentation or not) as the current one. the element itself is unchanged, but its span
+    /// is shifted by the change in length of the comment. [CvlElement::src] is updated,
+    /// while other elements of the same file keep the old source.
+    pub fn reparse_in_place(&mut self, new_comment_text: &str) -> eyre::Result<()> {
+        let Some(old_doc_span) = self.doc_span.clone() else {
+            bail!("element is undocumented");
+        };
+
+        let new_comment_text = new_comment_text.trim();
+        // slashed comments are always followed by a newline, which is included in their last tag
+        let (doc, is_file_doc) =
+            parse::builder::Builder::new(&format!("{new_comment_text}\n")).build_documentation()?;
+        if is_file_doc != matches!(self.ast, Ast::FileDocumentation) {
+            bail!("the kind of documentation comment cannot change");
+        }
+
+        let Some(old_byte_span) = old_doc_span.to_byte_span(&self.src) else {
+            bail!("documentation span is out of bounds");
+        };
+        let src = format!(
+            "{}{new_comment_text}{}",
+            &self.src[..old_byte_span.start],
+            &self.src[old_byte_span.end..]
+        );
+
+        let start = old_doc_span.start;
+        let new_doc_span = start..start + new_comment_text.chars().count();
+        let shift = |n: usize| n + new_doc_span.end - old_doc_span.end;
+
+        self.doc = doc
+            .into_iter()
+            .map(|tag| DocumentationTag {
+                span: tag.span.start + start..tag.span.end + start,
+                source_map: tag.source_map.shifted(old_byte_span.start),
+                ..tag
+            })
+            .collect();
+        self.element_span = match self.ast {
+            Ast::FileDocumentation => new_doc_span.clone(),
+            _ => shift(self.element_span.start)..shift(self.element_span.end),
+        };
+        for gap in &mut self.doc_gaps {
+            *gap = shift(gap.start)..shift(gap.end);
+        }
+        self.doc_span = Some(new_doc_span);
+        self.src = Arc::from(src);
+        self.set_ranges(&RangeConverter::new(ropey::Rope::from_str(&self.src)));
+
+        Ok(())
+    }
+
+    /// sets the line and column ranges from the spans, with `converter` of [CvlElement::src]
+    pub(crate) fn set_ranges(&mut self, converter: &RangeConverter) {
+        self.element_range = converter.to_clamped_range(self.element_span.clone());
+        self.doc_range = self
+            .doc_span
+            .clone()
+            .map(|span| converter.to_clamped_range(span));
+        for tag in &mut self.doc {
+            tag.range = Some(converter.to_clamped_range(tag.span.clone()));
+        }
+        if let Ast::Unknown {
+            raw_header, range, ..
+        } = &mut self.ast
+        {
+            // the header is the start of the element
+            let start = self.element_span.start;
+            *range = converter.to_clamped_range(start..start + raw_header.chars().count());
+        }
+    }
+
+    /// appends `tag` to the documentation of this element. freeform comments have text
+    /// rather than tags, so they become [Ast::Detached] documentation holding only the new tag,
+    /// spanning the comment. see [CvlElement::default] for building documentation from scratch.
+    pub fn with_tag(mut self, tag: DocumentationTag) -> CvlElement {
+        if let Ast::FreeFormComment { .. } = self.ast {
+            self.ast = Ast::Detached;
+            self.doc_span = Some(self.element_span.clone());
+            self.doc_range = Some(self.element_range);
+        }
+        self.doc.push(tag);
+        self
+    }
+
+    /// removes the tags of the given kinds, as in `element.strip_tags(&[TagKind::Dev])`
+    /// before publishing documentation. the spans and source text of the element are unchanged.
+    pub fn strip_tags(mut self, kinds: &[TagKind]) -> CvlElement {
+        self.doc.retain(|tag| !kinds.contains(&tag.kind));
+        self
+    }
+
+    /// keeps the documentation, attaching it to `new_element` instead.
+    /// the spans and source text of the element are unchanged.
+    ///
+    /// freeform comments and file documentation document no element, so the element
+    /// is returned unchanged as the error, as it is if `new_element` is one of those.
+    pub fn replace_associated(mut self, new_element: Ast) -> Result<CvlElement, Box<CvlElement>> {
+        let documents_nothing =
+            |ast: &Ast| matches!(ast, Ast::FreeFormComment { .. } | Ast::FileDocumentation);
+        if documents_nothing(&self.ast) || documents_nothing(&new_element) {
+            return Err(Box::new(self));
+        }
+
+        self.ast = new_element;
+        Ok(self)
+    }
+
+    /// for a `methods` block, pairs each `@param` tag with the entries which use the parameter
+    /// as a receiver, as in `@param token` and `function token.balanceOf(address) external`.
+    /// the wildcard receiver is documented as `@param _`.
+    pub fn methods_param_docs(&self) -> Option<Vec<(&DocumentationTag, Vec<&str>)>> {
+        let entries = self.ast.methods_entries()?;
+
+        let pairs = self
+            .doc
+            .iter()
+            .filter_map(|tag| {
+                tag.param_name()?;
+                let receiver = tag.param_path()[0];
+                let mentioning = entries
+                    .iter()
+                    .copied()
+                    .filter(|entry| util::mentions_receiver(entry, receiver))
+                    .collect();
+                Some((tag, mentioning))
+            })
+            .collect();
+
+        Some(pairs)
+    }
+
+    /// separates the documentation of this element from the element itself, as [Ast::Detached]
+    /// documentation spanning the comment, keeping the tags and their spans.
+    /// this is useful when the element is edited away.
+    /// an undocumented element becomes empty documentation, at the start of the element.
+    pub fn detach(self) -> CvlElement {
+        let (element_span, element_range) = match (&self.doc_span, self.doc_range) {
+            (Some(doc_span), Some(doc_range)) => (doc_span.clone(), doc_range),
+            _ => {
+                let start = self.element_span.start;
+                let range_start = self.element_range.start;
+                let range = SimpleRange {
+                    start: range_start,
+                    end: range_start,
+                };
+                (start..start, range)
+            }
+        };
+
+        CvlElement {
+            doc: self.doc,
+            ast: Ast::Detached,
+            doc_span: Some(element_span.clone()),
+            doc_range: Some(element_range),
+            element_span,
+            element_range,
+            doc_gaps: Vec::new(),
+            src: self.src,
+            annotations: self.annotations,
+        }
+    }
+
+    /// the documentation and the element, for destructuring both at once.
+    /// freeform comments have neither, and file documentation and detached documentation
+    /// have no element.
+    pub fn into_parts(self) -> (Option<Vec<DocumentationTag>>, Option<Ast>) {
+        match self.ast {
+            Ast::FreeFormComment { .. } => (None, None),
+            Ast::FileDocumentation | Ast::Detached => (Some(self.doc), None),
+            ast => (Some(self.doc), Some(ast)),
+        }
+    }
+
+    #[deprecated(since = "2.1.0", note = "renamed to `source_text`")]
+    pub fn raw(&self) -> &str {
+        self.source_text()
+    }
+
+    /// reconstructs the documentation of this element in a canonical style:
+    /// a `/** */` block with one tag per line for documentation (`/*! */` for file documentation),
+    /// or `////` lines for freeform comments.
+    /// returns an empty string for undocumented elements.
+    ///
+    /// the layout of the original comment is not kept: each tag starts a line of its own,
+    /// including a tag which started in the middle of a line, as in `some text @dev more`.
+    /// parsing the output and reconstructing it again gives the same string.
+    pub fn to_cvl_comment(&self) -> String {
+        if let Ast::FreeFormComment { text, .. } = &self.ast {
+            return text
+                .lines()
+                .map(|line| format!("//// {line}").trim_end().to_string())
+                .join("\n");
+        }
+
+        if self.doc.is_empty() {
+            return String::new();
+        }
+
+        let opening = match self.ast {
+            Ast::FileDocumentation => "/*!",
+            _ => "/**",
+        };
+        let mut lines = vec![opening.to_string()];
+
+        for tag in &self.doc {
+            let mut description = tag.description.lines();
+            let first_line = description.next().unwrap_or_default();
+            lines.push(format!(" * {} {first_line}", tag.kind.as_keyword()));
+
+            for line in description {
+                lines.push(format!(" * {line}"));
+            }
+        }
+
+        lines.push(" */".to_string());
+
+        lines
+            .into_iter()
+            .map(|line| line.trim_end().to_string())
+            .join("\n")
+    }
+
+    /// an anchor for this element, of the form `<kind>-<name>`.
+    /// freeform comments use the text of their first line instead, without any leading `#`.
+    /// this is not unique within a file, see [util::slugify_all].
+    pub fn slug(&self) -> Option<String> {
+        let slug = match &self.ast {
+            Ast::FreeFormComment { text, .. } => {
+                let header = text.lines().find(|line| !line.trim().is_empty())?;
+                util::slugify(header.trim_start_matches('#'))
+            }
+            ast => util::slugify(&format!("{ast}-{}", ast.name()?)),
+        };
+
+        Some(slug).filter(|slug| !slug.is_empty())
+    }
+
+    /// for a freeform comment whose first line is a Markdown header, as in `//// ## Invariants`,
+    /// the level of the header and its text.
+    pub fn freeform_header(&self) -> Option<(usize, &str)> {
+        let Ast::FreeFormComment { text, .. } = &self.ast else {
+            return None;
+        };
+
+        let first_line = text.lines().find(|line| !line.trim().is_empty())?.trim();
+        let level = first_line.chars().take_while(|&c| c == '#').count();
+        let header = &first_line[level..];
+
+        if level == 0 || level > 6 || !header.starts_with(char::is_whitespace) {
+            return None;
+        }
+        Some((level, header.trim()))
+    }
+
+    /// like [PartialEq], but does not compare the entire source file.
+    pub(crate) fn same_content(&self, other: &CvlElement) -> bool {
+        self.doc == other.doc && self.ast == other.ast && self.span() == other.span()
+    }
+
+    /// counts the documented elements of each kind, keyed by the [Display] of their [Ast].
+    /// freeform comments are counted under `"freeform"`, documentation with no associated
+    /// element ([Ast::Detached]) under `"detached"`, and file documentation under `"file"`.
+    /// undocumented elements are skipped.
+    pub fn count_by_kind(elements: &[CvlElement]) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+
+        for element in elements {
+            let key = match &element.ast {
+                Ast::FreeFormComment { .. } => "freeform".to_string(),
+                Ast::Detached => "detached".to_string(),
+                Ast::FileDocumentation => "file".to_string(),
+                _ if element.doc.is_empty() => continue,
+                ast => ast.to_string(),
+            };
+
+            *counts.entry(key).or_default() += 1;
+        }
+
+        counts
+    }
+}
+
+#[cfg(feature = "lsp")]
+impl CvlElement {
+    /// the tag at `position` in the source of this element, for hover text specific to a tag.
+    /// a tag contains the positions from the start of its [DocumentationTag::range] up to its end.
+    /// tags without a range, such as those which were not parsed from the source, are never found.
+    pub fn position_to_tag(&self, position: lsp_types::Position) -> Option<&DocumentationTag> {
+        let position = util::SimplePosition::from(position);
+
+        self.doc.iter().find(|tag| {
+            tag.range
+                .is_some_and(|range| range.start <= position && position < range.end)
+        })
+    }
+}
+
+/// see [CvlElement::verify_against]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RawMismatch {
+    /// the span of the element is not within the source
+    OutOfBounds { span: Span },
+    /// `expected` is the text of the source at the span of the element
+    Differs { expected: String, found: String },
+}
+
+impl Display for RawMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RawMismatch::OutOfBounds { span } => write!(f, "{span:?}: not in source bounds"),
+            RawMismatch::Differs { expected, found } => {
+                write!(f, "expected {expected:?}, but found {found:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RawMismatch {}
+
+/// see [DocumentationTag::validate]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagValidationError {
+    /// a `@param` tag whose description does not start with a parameter name followed by whitespace
+    MissingParamName,
+    /// a name given to [DocumentationTag::param] which is not parsed back as the parameter name,
+    /// such as an empty name or one containing whitespace
+    InvalidParamName(String),
+    /// a `@title` tag whose description spans several lines
+    MultiLineTitle,
+    /// a `@formula` tag with an empty description
+    EmptyFormula,
+}
+
+impl Display for TagValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TagValidationError::MissingParamName => {
+                write!(f, "@param tag is missing a parameter name")
+            }
+            TagValidationError::InvalidParamName(name) => {
+                write!(f, "{name:?} is not a valid parameter name")
+            }
+            TagValidationError::MultiLineTitle => write!(f, "@title tag spans several lines"),
+            TagValidationError::EmptyFormula => write!(f, "@formula tag is empty"),
+        }
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+impl std::error::Error for TagValidationError {}
+
+#[derive(Debug, Clone, Eq, Serialize, Deserialize)]
 pub struct DocumentationTag {
     pub kind: TagKind,
     pub description: String,
     pub span: Span,
+    /// [DocumentationTag::span] as lines and columns, serialized as an LSP range.
+    /// it is not compared, since it follows from the span. it is `None` for tags which were
+    /// not parsed from a source, and for those of [crate::parse::doc_comment_parser],
+    /// whose position in the source is unknown.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<SimpleRange>,
+    /// omitted from the serialized tag when [TagProvenance::Authored],
+    /// so consumers of authored documentation see no difference.
+    #[serde(default, skip_serializing_if = "TagProvenance::is_authored")]
+    pub provenance: TagProvenance,
+    /// see [DocumentationTag::source_map]. it is not serialized, and not compared.
+    #[serde(skip)]
+    pub source_map: SourceMap,
+}
+
+impl PartialEq for DocumentationTag {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+            && self.description == other.description
+            && self.span == other.span
+            && self.provenance == other.provenance
+    }
+}
+
+/// tags are ordered by kind, as in the default [TagOrder], then by their position in the source.
+impl Ord for DocumentationTag {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.kind
+            .cmp(&other.kind)
+            .then(self.span.start.cmp(&other.span.start))
+            .then(self.span.end.cmp(&other.span.end))
+            .then_with(|| self.description.cmp(&other.description))
+            .then_with(|| self.provenance.cmp(&other.provenance))
+    }
+}
+
+impl PartialOrd for DocumentationTag {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// where a tag came from: written by the author, or synthesized by tooling.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+pub enum TagProvenance {
+    #[default]
+    Authored,
+    /// copied from the documentation of another element, such as an inherited `@param`
+    Inherited { from: String },
+    /// created by a tool, such as an automatic `@title`
+    Generated { by: String },
+}
+
+impl TagProvenance {
+    pub fn is_authored(&self) -> bool {
+        matches!(self, TagProvenance::Authored)
+    }
 }
 
 impl DocumentationTag {
@@ -175,7 +928,78 @@ impl DocumentationTag {
             kind,
             description,
             span,
+            range: None,
+            provenance: TagProvenance::Authored,
+            source_map: SourceMap::default(),
+        }
+    }
+
+    pub fn with_provenance(self, provenance: TagProvenance) -> DocumentationTag {
+        DocumentationTag { provenance, ..self }
+    }
+
+    /// maps byte offsets in [DocumentationTag::description] to byte offsets in the parsed source,
+    /// for pointing at a word of the description in the comment it came from.
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    // constructors for tags created by tools rather than parsed.
+    // the description is in the canonical form the parser produces, and the span is empty.
+    // tags of kinds with constraints are checked by [DocumentationTag::validate].
+
+    pub fn title(description: &str) -> Result<DocumentationTag, TagValidationError> {
+        DocumentationTag::new(TagKind::Title, description.to_string(), Span::default()).validated()
+    }
+
+    pub fn notice(description: &str) -> DocumentationTag {
+        DocumentationTag::new(TagKind::Notice, description.to_string(), Span::default())
+    }
+
+    pub fn dev(description: &str) -> DocumentationTag {
+        DocumentationTag::new(TagKind::Dev, description.to_string(), Span::default())
+    }
+
+    /// a `@param` tag, whose description is the parameter name followed by `description`
+    pub fn param(name: &str, description: &str) -> Result<DocumentationTag, TagValidationError> {
+        let description = format!("{name} {description}").trim_end().to_string();
+        let tag =
+            DocumentationTag::new(TagKind::Param, description, Span::default()).validated()?;
+        if tag.param_name() != Some(name) {
+            return Err(TagValidationError::InvalidParamName(name.to_string()));
         }
+        Ok(tag)
+    }
+
+    pub fn ret(description: &str) -> DocumentationTag {
+        DocumentationTag::new(TagKind::Return, description.to_string(), Span::default())
+    }
+
+    pub fn formula(description: &str) -> Result<DocumentationTag, TagValidationError> {
+        DocumentationTag::new(TagKind::Formula, description.to_string(), Span::default())
+            .validated()
+    }
+
+    /// checks constraints specific to the kind of the tag:
+    /// a `@param` must name a parameter, a `@title` must be a single line,
+    /// and a `@formula` must not be empty.
+    pub fn validate(&self) -> Result<(), TagValidationError> {
+        match self.kind {
+            TagKind::Param if self.param_name().is_none() => {
+                Err(TagValidationError::MissingParamName)
+            }
+            TagKind::Title if self.description.trim().contains('\n') => {
+                Err(TagValidationError::MultiLineTitle)
+            }
+            TagKind::Formula if self.description.trim().is_empty() => {
+                Err(TagValidationError::EmptyFormula)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn validated(self) -> Result<DocumentationTag, TagValidationError> {
+        self.validate().map(|()| self)
     }
 
     pub fn tag_name_span(&self) -> Option<Span> {
@@ -188,19 +1012,203 @@ impl DocumentationTag {
         }
     }
 
+    /// the name of the parameter documented by a `@param`. the name may be preceded by its type
+    /// in backticks, as in `` @param `uint256` amount the amount ``.
     pub fn param_name(&self) -> Option<&str> {
+        self.param_parts().map(|(_, name, _)| name)
+    }
+
+    /// the type of the parameter, if given in backticks before or after the name of a `@param`,
+    /// as in `` @param amount `uint256` the amount ``. the backticks are not included.
+    pub fn param_type(&self) -> Option<&str> {
+        self.param_parts().and_then(|(ty, _, _)| ty)
+    }
+
+    /// the description of a `@param` following the parameter name and type
+    pub fn param_description(&self) -> Option<&str> {
+        self.param_parts().map(|(_, _, description)| description)
+    }
+
+    /// the type, name and description of a `@param`
+    fn param_parts(&self) -> Option<(Option<&str>, &str, &str)> {
+        if self.kind != TagKind::Param {
+            return None;
+        }
+
+        fn next_word(text: &str) -> Option<(&str, &str)> {
+            let (word, rest) = text
+                .trim_start()
+                .split_once(|c: char| c.is_ascii_whitespace())?;
+            Some((word, rest.trim_start()))
+        }
+        fn backticked(word: &str) -> Option<&str> {
+            let ty = word.strip_prefix('`')?.strip_suffix('`')?;
+            Some(ty).filter(|ty| !ty.is_empty() && !ty.contains('`'))
+        }
+
+        let (first, rest) = next_word(&self.description)?;
+        if let Some(ty) = backticked(first) {
+            let (name, description) = next_word(rest)?;
+            return Some((Some(ty), name, description.trim_end()));
+        }
+
+        match next_word(rest) {
+            Some((second, description)) if backticked(second).is_some() => {
+                Some((backticked(second), first, description.trim_end()))
+            }
+            _ => Some((None, first, rest.trim_end())),
+        }
+    }
+
+    /// the documented parameter name, split on dots.
+    /// for example, `@param args.amount the amount` gives `["args", "amount"]`.
+    pub fn param_path(&self) -> Vec<&str> {
+        self.param_name()
+            .map(|param_name| param_name.split('.').collect())
+            .unwrap_or_default()
+    }
+
+    /// the requirement IDs listed by a `@requirement` or `@satisfies` tag, separated by commas
+    /// or whitespace. for example, `@requirement REQ-12, REQ-13` gives `["REQ-12", "REQ-13"]`.
+    /// other tags have no requirement IDs.
+    pub fn requirement_ids(&self) -> Vec<&str> {
         match self.kind {
-            TagKind::Param => self
+            TagKind::Requirement | TagKind::Satisfies => self
                 .description
-                .trim_start()
-                .split_once(|c: char| c.is_ascii_whitespace())
-                .map(|(param_name, _)| param_name),
-            _ => None,
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|id| !id.is_empty())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// the backtick-delimited code spans in the description, along with their character span
+    /// relative to the start of the description.
+    /// as in Markdown, a span opened by a run of backticks is closed by a run of the same length,
+    /// so ``` `` a `b` `` ``` gives `` a `b` ``. unclosed backticks are ignored.
+    pub fn code_spans(&self) -> Vec<(Span, &str)> {
+        let chars = self.description.char_indices().collect_vec();
+        let run_len = |start: usize| chars[start..].iter().take_while(|(_, c)| *c == '`').count();
+        let byte_pos = |i: usize| chars.get(i).map_or(self.description.len(), |&(pos, _)| pos);
+
+        let mut spans = Vec::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            if chars[i].1 != '`' {
+                i += 1;
+                continue;
+            }
+
+            let opening_len = run_len(i);
+            let content_start = i + opening_len;
+
+            let mut j = content_start;
+            let closing_start = loop {
+                if j >= chars.len() {
+                    break None;
+                }
+                if chars[j].1 == '`' {
+                    let len = run_len(j);
+                    if len == opening_len {
+                        break Some(j);
+                    }
+                    j += len;
+                } else {
+                    j += 1;
+                }
+            };
+
+            let Some(closing_start) = closing_start else {
+                i = content_start;
+                continue;
+            };
+
+            let (mut start, mut end) = (content_start, closing_start);
+            let is_space = |k: usize| chars[k].1 == ' ';
+            // a single space of padding on both sides is stripped, to allow spans beginning with a backtick
+            if end - start >= 2
+                && is_space(start)
+                && is_space(end - 1)
+                && !(start..end).all(is_space)
+            {
+                start += 1;
+                end -= 1;
+            }
+
+            spans.push((
+                start..end,
+                &self.description[byte_pos(start)..byte_pos(end)],
+            ));
+            i = closing_start + opening_len;
+        }
+
+        spans
+    }
+
+    /// a [DocumentationTagRef] borrowing the description of this tag.
+    pub fn as_tag_ref(&self) -> DocumentationTagRef<'_> {
+        DocumentationTagRef {
+            kind: self.kind.clone(),
+            description: Cow::Borrowed(&self.description),
+            span: self.span.clone(),
+            range: self.range,
+            provenance: self.provenance.clone(),
+            source_map: self.source_map.clone(),
+        }
+    }
+}
+
+/// a [DocumentationTag] whose description may be borrowed from the parsed source.
+/// descriptions spanning a single line are borrowed, while those spanning several lines
+/// are owned, since the comment markers between the lines are removed.
+/// see [parse::builder::Builder::build_tag_refs].
+#[derive(Debug, Clone, Eq, Serialize)]
+pub struct DocumentationTagRef<'a> {
+    pub kind: TagKind,
+    pub description: Cow<'a, str>,
+    pub span: Span,
+    /// see [DocumentationTag::range]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<SimpleRange>,
+    #[serde(skip_serializing_if = "TagProvenance::is_authored")]
+    pub provenance: TagProvenance,
+    #[serde(skip)]
+    pub source_map: SourceMap,
+}
+
+impl PartialEq for DocumentationTagRef<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+            && self.description == other.description
+            && self.span == other.span
+            && self.provenance == other.provenance
+    }
+}
+
+impl DocumentationTagRef<'_> {
+    pub fn into_owned(self) -> DocumentationTag {
+        DocumentationTag {
+            kind: self.kind,
+            description: self.description.into_owned(),
+            span: self.span,
+            range: self.range,
+            provenance: self.provenance,
+            source_map: self.source_map,
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash, Default, Serialize)]
+impl From<DocumentationTagRef<'_>> for DocumentationTag {
+    fn from(tag: DocumentationTagRef<'_>) -> Self {
+        tag.into_owned()
+    }
+}
+
+/// tag kinds compare in the order of the default [TagOrder].
+/// more kinds may be added, so matches outside of this crate need a wildcard arm.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Default, Serialize)]
+#[non_exhaustive]
 pub enum TagKind {
     Title,
     #[default]
@@ -209,9 +1217,45 @@ pub enum TagKind {
     Param,
     Return,
     Formula,
+    /// the IDs of the requirements this element checks
+    Requirement,
+    /// the IDs of the requirements this element satisfies
+    Satisfies,
+}
+
+/// a canonical order of tag kinds, for formatters. see [CvlElement::sort_tags_with].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagOrder(pub Vec<TagKind>);
+
+impl TagOrder {
+    fn rank(&self, kind: &TagKind) -> usize {
+        self.0
+            .iter()
+            .position(|ordered| ordered == kind)
+            .unwrap_or(self.0.len())
+    }
+}
+
+impl Default for TagOrder {
+    /// `@title`, `@notice`, `@dev`, `@param`, `@return`, `@formula`, `@requirement`, `@satisfies`
+    fn default() -> Self {
+        TagOrder(TagKind::ALL.to_vec())
+    }
 }
 
 impl TagKind {
+    /// every tag kind which is recognized in documentation, in the default [TagOrder]
+    pub const ALL: &'static [TagKind] = &[
+        TagKind::Title,
+        TagKind::Notice,
+        TagKind::Dev,
+        TagKind::Param,
+        TagKind::Return,
+        TagKind::Formula,
+        TagKind::Requirement,
+        TagKind::Satisfies,
+    ];
+
     pub(crate) fn as_str(&self) -> &str {
         match self {
             TagKind::Title => "title",
@@ -220,9 +1264,16 @@ impl TagKind {
             TagKind::Param => "param",
             TagKind::Return => "return",
             TagKind::Formula => "formula",
+            TagKind::Requirement => "requirement",
+            TagKind::Satisfies => "satisfies",
         }
     }
 
+    /// the tag as written in a comment, such as `@notice`
+    pub fn as_keyword(&self) -> String {
+        format!("@{}", self.as_str())
+    }
+
     pub(crate) fn len(&self) -> usize {
         let len_without_ampersat = self.as_str().len();
         len_without_ampersat + 1
@@ -230,7 +1281,7 @@ impl TagKind {
 }
 
 impl TryFrom<&str> for TagKind {
-    type Error = color_eyre::Report;
+    type Error = eyre::Report;
 
     fn try_from(s: &str) -> Result<Self, Self::Error> {
         let s = s.strip_prefix('@').unwrap_or(s);
@@ -242,18 +1293,32 @@ impl TryFrom<&str> for TagKind {
             "param" => Ok(TagKind::Param),
             "return" => Ok(TagKind::Return),
             "formula" => Ok(TagKind::Formula),
+            "requirement" => Ok(TagKind::Requirement),
+            "satisfies" => Ok(TagKind::Satisfies),
             _ => bail!("unrecognized tag: {s}"),
         }
     }
 }
 
+/// accepts tag names with or without the `@`, as in `"notice"` or `"@notice"`,
+/// in any case, so that serialized tag kinds such as `"Notice"` are also accepted
+impl<'de> Deserialize<'de> for TagKind {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        TagKind::try_from(s.to_ascii_lowercase().as_str()).map_err(serde::de::Error::custom)
+    }
+}
+
 impl Display for Ast {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let kind = match self {
             Ast::Rule { .. } => "rule",
             Ast::Invariant { .. } => "invariant",
+            Ast::FileDocumentation => "file",
+            Ast::Detached => "detached",
             Ast::Function { .. } => "function",
             Ast::Definition { .. } => "definition",
+            Ast::CVLMacro { .. } => "macro",
             Ast::GhostFunction { .. } | Ast::GhostMapping { .. } => "ghost",
             Ast::Methods { .. } => "methods",
             Ast::FreeFormComment { .. } => "freeform comment",
@@ -264,21 +1329,129 @@ impl Display for Ast {
             | Ast::HookSstore { .. }
             | Ast::HookCreate { .. }
             | Ast::HookOpcode { .. } => "hook",
+            Ast::Unknown { .. } => "unknown",
         };
 
         write!(f, "{kind}")
     }
 }
 
+/// the fields of [Ast::Rule], followed by `parametric` if the rule [Ast::is_parametric]
+fn serialize_rule<S: serde::Serializer>(
+    name: &String,
+    params: &Vec<Param>,
+    filters: &Option<String>,
+    attributes: &Vec<(String, String)>,
+    block: &String,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    #[serde(rename = "Rule")]
+    struct SerializedRule<'a> {
+        name: &'a String,
+        params: &'a Vec<Param>,
+        filters: &'a Option<String>,
+        attributes: &'a Vec<(String, String)>,
+        block: &'a String,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        parametric: bool,
+    }
+
+    SerializedRule {
+        name,
+        params,
+        filters,
+        attributes,
+        block,
+        parametric: Ast::rule_is_parametric(params),
+    }
+    .serialize(serializer)
+}
+
 impl Ast {
+    /// parses `src`, a single declaration without documentation, into an [Ast]
+    /// whose [Display] is `kind`, such as `"rule"` or `"ghost"`.
+    pub fn from_cvl_snippet(kind: &str, src: &str) -> eyre::Result<Ast> {
+        let elements = parse::builder::Builder::new(src).build()?;
+
+        let element = match elements.as_slice() {
+            [element] => element,
+            [] => bail!("no declaration found"),
+            _ => bail!("expected a single declaration, found {}", elements.len()),
+        };
+        if !element.doc.is_empty() {
+            bail!("expected a declaration without documentation");
+        }
+
+        let ast = element.ast.clone();
+        match ast {
+            Ast::FreeFormComment { .. } | Ast::FileDocumentation | Ast::Detached => {
+                bail!("expected a declaration, found a comment")
+            }
+            _ if ast.to_string() != kind => bail!("expected {kind}, found {ast}"),
+            ast => Ok(ast),
+        }
+    }
+
+    pub(crate) fn supported_tags(&self) -> &[TagKind] {
+        use TagKind::*;
+        match self {
+            Ast::Rule { .. } => &[Title, Notice, Dev, Param, Formula, Requirement, Satisfies],
+            Ast::Invariant { .. } => &[Title, Notice, Dev, Param, Requirement, Satisfies],
+            Ast::Function { .. } => &[Notice, Dev, Param, Return],
+            Ast::Definition { .. } => &[Notice, Dev, Param, Return],
+            Ast::CVLMacro { .. } => &[Notice, Dev, Param],
+            Ast::GhostFunction { .. } | Ast::GhostMapping { .. } => &[Notice, Dev, Param, Return],
+            Ast::Methods { .. } => &[Notice, Dev],
+            Ast::FreeFormComment { .. } => &[Notice, Dev],
+            Ast::FileDocumentation => &[Title, Notice, Dev],
+            Ast::Import { .. }
+            | Ast::Using { .. }
+            | Ast::UseRule { .. }
+            | Ast::UseBuiltinRule { .. }
+            | Ast::UseInvariant { .. }
+            | Ast::HookSload { .. }
+            | Ast::HookSstore { .. }
+            | Ast::HookCreate { .. }
+            | Ast::HookOpcode { .. } => &[Dev],
+            // we can't tell what this is, so we accept anything
+            Ast::Unknown { .. } | Ast::Detached => TagKind::ALL,
+        }
+    }
+
+    /// whether the element can be invoked or checked: rules, invariants and functions.
+    /// ghosts, definitions, macros and `methods` blocks are not callable.
+    pub fn is_callable(&self) -> bool {
+        matches!(
+            self,
+            Ast::Rule { .. } | Ast::Invariant { .. } | Ast::Function { .. }
+        )
+    }
+
     pub fn name(&self) -> Option<&str> {
         match self {
             Ast::Rule { name, .. }
             | Ast::Invariant { name, .. }
             | Ast::Function { name, .. }
             | Ast::Definition { name, .. }
+            | Ast::CVLMacro { name, .. }
             | Ast::GhostFunction { name, .. }
             | Ast::GhostMapping { name, .. } => Some(name.as_str()),
+            Ast::Unknown {
+                first_token,
+                raw_header,
+                ..
+            } => {
+                // assume the name is the second token, as in most declarations
+                let after_first = raw_header.strip_prefix(first_token.as_str())?.trim_start();
+                let name_len = after_first
+                    .find(|c: char| !c.is_alphanumeric() && c != '_')
+                    .unwrap_or(after_first.len());
+
+                after_first
+                    .get(..name_len)
+                    .filter(|name| name.starts_with(|c: char| c.is_alphabetic() || c == '_'))
+            }
             _ => None,
         }
     }
@@ -288,22 +1461,52 @@ impl Ast {
             Ast::Rule { params, .. }
             | Ast::Invariant { params, .. }
             | Ast::Function { params, .. }
-            | Ast::Definition { params, .. } => Some(params),
+            | Ast::Definition { params, .. }
+            | Ast::CVLMacro { params, .. } => Some(params),
             _ => None,
         }
     }
 
+    /// whether this is a rule with a parameter of type `method`, which is checked for every method.
+    /// a parameter named `method` does not count. elements other than rules are never parametric.
+    pub fn is_parametric(&self) -> bool {
+        match self {
+            Ast::Rule { params, .. } => Ast::rule_is_parametric(params),
+            _ => false,
+        }
+    }
+
+    /// [Ast::is_parametric] of a rule with `params`, for serializing rules by their fields
+    fn rule_is_parametric(params: &[Param]) -> bool {
+        params.iter().any(Param::is_method)
+    }
+
+    /// the number of [Ast::params] with a name, or 0 for elements without parameters.
+    /// parsed parameters always have names, but a [Param] may be constructed without one.
+    /// the argument types of ghost functions are not parameters, and are never counted.
+    pub fn params_named_count(&self) -> usize {
+        self.params()
+            .unwrap_or_default()
+            .iter()
+            .filter(|param| !param.name.is_empty())
+            .count()
+    }
+
+    /// the body of the element, without the surrounding braces, except for the axioms block
+    /// of a ghost, which keeps its braces, as in `{ axiom c == 0; }`.
+    /// comments within the body are kept as written, and are never parsed as documentation,
+    /// even if they start with `///` or `////`.
     pub fn block(&self) -> Option<&str> {
         match self {
             Ast::Rule { block, .. }
-            | Ast::Function { block, .. }
-            | Ast::Methods { block }
+            | Ast::Methods { block, .. }
             | Ast::HookSload { block, .. }
             | Ast::HookSstore { block, .. }
             | Ast::HookCreate { block, .. }
             | Ast::HookOpcode { block, .. } => Some(block.as_str()),
 
             Ast::Invariant { proof: block, .. }
+            | Ast::Function { block, .. }
             | Ast::GhostFunction { axioms: block, .. }
             | Ast::GhostMapping { axioms: block, .. } => block.as_ref().map(String::as_str),
 
@@ -311,6 +1514,40 @@ impl Ast {
         }
     }
 
+    /// replaces the [Ast::block] with `f` of it, as when reformatting bodies.
+    /// elements without a block, including those declared without one, are returned unchanged.
+    pub fn map_block<F>(mut self, f: F) -> Ast
+    where
+        F: FnOnce(&str) -> String,
+    {
+        match &mut self {
+            Ast::Rule { block, .. }
+            | Ast::Methods { block, .. }
+            | Ast::HookSload { block, .. }
+            | Ast::HookSstore { block, .. }
+            | Ast::HookCreate { block, .. }
+            | Ast::HookOpcode { block, .. }
+            | Ast::Invariant {
+                proof: Some(block), ..
+            }
+            | Ast::Function {
+                block: Some(block), ..
+            }
+            | Ast::GhostFunction {
+                axioms: Some(block),
+                ..
+            }
+            | Ast::GhostMapping {
+                axioms: Some(block),
+                ..
+            } => *block = f(block),
+
+            _ => {}
+        }
+
+        self
+    }
+
     pub fn returns(&self) -> Option<&str> {
         match self {
             Ast::Function { returns, .. } => returns.as_deref(),
@@ -321,6 +1558,14 @@ impl Ast {
         }
     }
 
+    /// the types returned by this element, if any, with one entry for each returned value.
+    pub fn return_types(&self) -> Vec<&str> {
+        match self {
+            Ast::Function { return_types, .. } => return_types.iter().map(String::as_str).collect(),
+            _ => self.returns().into_iter().collect(),
+        }
+    }
+
     pub fn ty_list(&self) -> Option<&[String]> {
         match self {
             Ast::GhostFunction { ty_list, .. } => Some(ty_list),
@@ -337,6 +1582,17 @@ impl Ast {
         }
     }
 
+    /// the value of the first attribute with this name, as in `description "..."`
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        match self {
+            Ast::Rule { attributes, .. } | Ast::Invariant { attributes, .. } => attributes
+                .iter()
+                .find(|(attribute, _)| attribute == name)
+                .map(|(_, value)| value.as_str()),
+            _ => None,
+        }
+    }
+
     pub fn invariant(&self) -> Option<&str> {
         match self {
             Ast::Invariant { invariant, .. } => Some(invariant.as_str()),
@@ -351,10 +1607,50 @@ impl Ast {
         }
     }
 
+    /// the parsed type of a ghost mapping. `None` for other elements,
+    /// and for ghost variables which are not mappings, such as `ghost uint256 x;`.
+    pub fn mapping_type(&self) -> Option<&MappingType> {
+        match self {
+            Ast::GhostMapping { mapping_type, .. } => mapping_type.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn initial_value(&self) -> Option<&str> {
+        match self {
+            Ast::GhostMapping { initial_value, .. } => initial_value.as_deref(),
+            _ => None,
+        }
+    }
+
     pub fn definition(&self) -> Option<&str> {
         match self {
             Ast::Definition { definition, .. } => Some(definition.as_str()),
             _ => None,
         }
     }
+
+    pub fn macro_body(&self) -> Option<&str> {
+        match self {
+            Ast::CVLMacro { body, .. } => Some(body.as_str()),
+            _ => None,
+        }
+    }
+
+    /// the entries of a `methods` block, which end at each `;` outside of comments and brackets.
+    /// entries are trimmed, and empty entries are skipped. comments between entries are dropped,
+    /// but those within an entry are kept.
+    /// see [coverage::MethodsCoverage] for entries with their documentation.
+    pub fn methods_entries(&self) -> Option<Vec<&str>> {
+        match self {
+            Ast::Methods { block, .. } => {
+                let entries = coverage::scan_entries(block)
+                    .into_iter()
+                    .map(|(range, _)| &block[range])
+                    .collect();
+                Some(entries)
+            }
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,74 @@
+//! resolution of inline code references in documentation, such as `` `totalSupplyIsSum` ``,
+//! to the elements they name. exporters can use these to create hyperlinks.
+
+use crate::util::{is_cvl_identifier, Span};
+use crate::{CvlElement, DocumentationTag};
+use std::collections::HashMap;
+
+/// a code span in the documentation of an element. see [DocumentationTag::code_spans].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeReference<'a> {
+    pub element: &'a CvlElement,
+    pub tag: &'a DocumentationTag,
+    /// the character span of the reference, relative to the start of the tag's description
+    pub span: Span,
+    pub text: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocLink<'a> {
+    pub from: CodeReference<'a>,
+    pub to: &'a CvlElement,
+}
+
+/// all code spans in the documentation of `docs`.
+pub fn references(docs: &[CvlElement]) -> impl Iterator<Item = CodeReference<'_>> {
+    docs.iter().flat_map(|element| {
+        element.doc.iter().flat_map(move |tag| {
+            tag.code_spans()
+                .into_iter()
+                .map(move |(span, text)| CodeReference {
+                    element,
+                    tag,
+                    span,
+                    text,
+                })
+        })
+    })
+}
+
+/// links code spans in the documentation of `docs` to the elements of `index` with the same name.
+/// if more than one element of `index` has that name, the first one is linked.
+pub fn resolve<'a>(docs: &'a [CvlElement], index: &'a [CvlElement]) -> Vec<DocLink<'a>> {
+    let by_name = index_by_name(index);
+
+    references(docs)
+        .filter_map(|from| {
+            let to = *by_name.get(from.text)?;
+            Some(DocLink { from, to })
+        })
+        .collect()
+}
+
+/// code spans in the documentation of `docs` which are valid identifiers,
+/// but do not name any element of `index`.
+pub fn unresolved<'a>(docs: &'a [CvlElement], index: &[CvlElement]) -> Vec<CodeReference<'a>> {
+    let by_name = index_by_name(index);
+
+    references(docs)
+        .filter(|reference| is_cvl_identifier(reference.text))
+        .filter(|reference| !by_name.contains_key(reference.text))
+        .collect()
+}
+
+fn index_by_name(index: &[CvlElement]) -> HashMap<&str, &CvlElement> {
+    let mut by_name = HashMap::new();
+
+    for element in index {
+        if let Some(name) = element.element_name() {
+            by_name.entry(name).or_insert(element);
+        }
+    }
+
+    by_name
+}
@@ -1,17 +1,231 @@
+// chumsky's `Simple` error is inherently large, and is returned from every `select!`
+#![allow(clippy::result_large_err)]
+
 pub mod builder;
+pub mod bundle;
+pub mod file_parser;
+mod formula;
 mod helpers;
 mod lexer;
+pub(crate) mod mapping;
+pub mod stats;
 mod terminated_str;
 #[cfg(test)]
 mod tests;
+mod trace;
 pub mod types;
 
-use crate::util::Span;
+use crate::util::{count_lines, strip_bom, ByteSpan, SimplePosition, SimpleRange, SourceMap, Span};
+use crate::{Ast, CvlElement, DocumentationTag};
+use builder::Builder;
 use chumsky::prelude::*;
+use eyre::{bail, Result};
 use helpers::slot::slot_pattern;
 use helpers::*;
+use std::ops::Range;
+use std::sync::Arc;
+
+pub use formula::validate_formula;
 use types::{Intermediate, Style, Token};
 
+/// a parser of a single documentation comment, `///` lines or a `/** */` block,
+/// for composing with other chumsky grammars over characters.
+/// outputs the tags of the comment, with spans relative to the input of the composed parser.
+/// the tags have no [DocumentationTag::range], since lines are unknown to the parser.
+/// to parse entire files, along with the elements the comments document, use [Builder].
+pub fn doc_comment_parser() -> impl Parser<char, Vec<DocumentationTag>, Error = Simple<char>> {
+    let comment = lexer::cvldoc_slashed().or(lexer::cvldoc_starred());
+
+    comment.try_map(|comment, span: Span| {
+        let (mut doc, _) = Builder::new(&comment)
+            .build_documentation()
+            .map_err(|e| Simple::custom(span.clone(), e))?;
+
+        // the byte offset and the line of the comment are unknown when parsing chars,
+        // so no source map or range is kept
+        for tag in &mut doc {
+            tag.span = tag.span.start + span.start..tag.span.end + span.start;
+            tag.range = None;
+            tag.source_map = SourceMap::default();
+        }
+        Ok(doc)
+    })
+}
+
+/// finds the documentation or freeform comment enclosing the byte `offset`, by lexing `src`.
+/// returns the raw text of the comment, and its start and end byte offsets.
+/// trailing whitespace, such as the newline ending a slashed comment, is not included.
+pub fn extract_raw_comment(src: &str, offset: usize) -> Option<(String, usize, usize)> {
+    // comments within blocks are only found by lexing the blocks as well
+    let lexed = lexer::cvl_lexer().parse(src).ok()?;
+
+    lexed.into_iter().find_map(|(token, span)| {
+        if !matches!(
+            token,
+            Token::CvlDocSlashed
+                | Token::CvlDocStarred
+                | Token::FileDocSlashed
+                | Token::FileDocStarred
+                | Token::FreeFormSlashed
+                | Token::FreeFormStarred
+        ) {
+            return None;
+        }
+
+        let byte_span = span.to_byte_span(src)?;
+        let raw = src[byte_span.clone()].trim_end();
+        let end = byte_span.start + raw.len();
+
+        (byte_span.start..end)
+            .contains(&offset)
+            .then(|| (raw.to_string(), byte_span.start, end))
+    })
+}
+
+/// the plain `//` comments at the start of `src`, before any other content except whitespace,
+/// such as `// certora: --optimistic_loop`. these are tool directives rather than documentation.
+/// returns the character span of each comment, without the line break ending it,
+/// and its text following the `//`, trimmed. a byte order mark at the start of `src` is skipped,
+/// but counted in the spans.
+///
+/// only the lines up to the first line which is neither blank nor a plain comment are read,
+/// so the rest of the file is never scanned.
+pub fn directives(src: &str) -> Vec<(Span, String)> {
+    let stripped = strip_bom(src);
+    // the mark is a single character
+    let mut line_start = usize::from(stripped.len() < src.len());
+    let mut found = Vec::new();
+
+    for line in stripped.split('\n') {
+        let comment = line.trim();
+        if !comment.is_empty() {
+            // `///` and `//!` comments are documentation
+            let Some(text) = comment
+                .strip_prefix("//")
+                .filter(|text| !text.starts_with(['/', '!']))
+            else {
+                break;
+            };
+            let start = line_start + line.chars().take_while(|c| c.is_whitespace()).count();
+            found.push((
+                start..start + comment.chars().count(),
+                text.trim().to_string(),
+            ));
+        }
+        line_start += line.chars().count() + 1;
+    }
+
+    found
+}
+
+/// parses only the elements of `src` around `byte_range`, such as the region visible in an editor.
+///
+/// the range is first widened to the nearest boundaries between top-level declarations,
+/// which are found without parsing: the start moves back, and the end moves forward,
+/// to just after a top-level `;` or block, or to the start or end of `src`.
+/// so a range starting in the middle of a comment or a rule includes the entire rule,
+/// along with its documentation.
+///
+/// the spans of the elements are relative to all of `src`, which is also their [CvlElement::src].
+pub fn parse_range(src: &str, byte_range: Range<usize>) -> Result<Vec<CvlElement>> {
+    if src.get(byte_range.clone()).is_none() {
+        bail!("{byte_range:?}: not in source bounds");
+    }
+
+    let boundaries = lexer::declaration_boundaries(src);
+    let start = boundaries
+        .iter()
+        .rev()
+        .find(|&&boundary| boundary <= byte_range.start)
+        .copied()
+        .unwrap_or(0);
+    let end = boundaries
+        .iter()
+        .find(|&&boundary| boundary >= byte_range.end)
+        .copied()
+        .unwrap_or(src.len());
+
+    // the builder skips a byte order mark at the start of `src`
+    let parsed_start = end - strip_bom(&src[start..end]).len();
+    let mut elements = Builder::new(&src[start..end]).build()?;
+
+    let before = &src[..parsed_start];
+    let char_offset = before.chars().count();
+    let last_line = before.rsplit('\n').next().unwrap_or_default();
+    let start = SimplePosition {
+        line: (count_lines(before) - 1) as u32,
+        character: last_line.chars().count() as u32,
+    };
+    relocate(
+        &mut elements,
+        char_offset,
+        parsed_start,
+        start,
+        Arc::from(src),
+    );
+
+    Ok(elements)
+}
+
+/// moves elements parsed from a part of `src`, starting `char_offset` characters
+/// and `byte_offset` bytes into it, at `start`, to `src`: their spans, ranges and source maps
+/// are shifted, and `src` becomes their [CvlElement::src].
+pub(crate) fn relocate(
+    elements: &mut [CvlElement],
+    char_offset: usize,
+    byte_offset: usize,
+    start: SimplePosition,
+    src: Arc<str>,
+) {
+    let shift = |span: &mut Span| *span = span.start + char_offset..span.end + char_offset;
+    // only positions on the first line of the part move to another column
+    let shift_position = |pos: SimplePosition| SimplePosition {
+        line: pos.line + start.line,
+        character: match pos.line {
+            0 => pos.character + start.character,
+            _ => pos.character,
+        },
+    };
+    let shift_range = |range: &mut SimpleRange| {
+        *range = SimpleRange {
+            start: shift_position(range.start),
+            end: shift_position(range.end),
+        }
+    };
+
+    for element in elements {
+        if let Ast::FreeFormComment {
+            following_element: Some(following),
+            ..
+        } = &mut element.ast
+        {
+            shift(&mut following.span);
+        }
+        if let Ast::Unknown { range, .. } = &mut element.ast {
+            shift_range(range);
+        }
+        shift(&mut element.element_span);
+        shift_range(&mut element.element_range);
+        if let Some(doc_span) = &mut element.doc_span {
+            shift(doc_span);
+        }
+        if let Some(doc_range) = &mut element.doc_range {
+            shift_range(doc_range);
+        }
+        for gap in &mut element.doc_gaps {
+            shift(gap);
+        }
+        for tag in &mut element.doc {
+            shift(&mut tag.span);
+            if let Some(range) = &mut tag.range {
+                shift_range(range);
+            }
+            tag.source_map = std::mem::take(&mut tag.source_map).shifted(byte_offset);
+        }
+        element.src = Arc::clone(&src);
+    }
+}
+
 fn decl_parser() -> impl Parser<Token, Intermediate, Error = Simple<Token>> {
     let rule_decl = {
         let optional_params = named_param_list().or_not();
@@ -20,25 +234,29 @@ fn decl_parser() -> impl Parser<Token, Intermediate, Error = Simple<Token>> {
             .ignore_then(ident())
             .then(optional_params)
             .then(filtered_block().or_not())
+            .then(attribute().repeated())
             .then(code_block())
-            .map(|(((name, params), filters), block)| Intermediate::Rule {
-                name,
-                params,
-                filters,
-                block,
-            })
+            .map(
+                |((((name, params), filters), attributes), block)| Intermediate::Rule {
+                    name,
+                    params,
+                    filters,
+                    attributes,
+                    block,
+                },
+            )
             .labelled("rule declaration")
     };
     let function_decl = just(Token::Function)
         .ignore_then(function_ident())
         .then(named_param_list())
-        .then(returns_type().or_not())
-        .then(code_block())
+        .then(returns_type_list().or_not())
+        .then(optional_code_block())
         .map(
-            |(((name, params), returns), block)| Intermediate::Function {
+            |(((name, params), return_types), block)| Intermediate::Function {
                 name,
                 params,
-                returns,
+                return_types: return_types.unwrap_or_default(),
                 block,
             },
         )
@@ -56,36 +274,57 @@ fn decl_parser() -> impl Parser<Token, Intermediate, Error = Simple<Token>> {
         // (2) param filters block (optional)
         // (3) the invariant proof (optional)
 
-        struct Spans(Span, Option<Span>, Option<Span>);
+        // the invariant, filters and proof, followed by the attributes
+        struct Spans(Span, Option<Span>, Option<Span>, Vec<(String, String)>);
 
-        let single_invariant = single_expr()
-            .then(just(Token::Semicolon))
-            .map_with_span(|_, span| Spans(span, None, None));
+        let single_invariant = expr_with_attributes()
+            .then(just(Token::Semicolon).map_with_span(|_, span| span))
+            .map(|((inv, attributes), semicolon)| {
+                // the semicolon is part of the invariant, unless attributes come in between
+                let inv = match attributes.is_empty() {
+                    true => inv.start..semicolon.end,
+                    false => inv,
+                };
+                Spans(inv, None, None, attributes)
+            });
 
         let with_filtered_block = none_of(Token::Filtered)
             .then_ignore(just(Token::Filtered).rewind())
             .map_with_span(|_, span| span)
             .then(filtered_block())
+            .then(attribute().repeated())
             .then(code_block().or_not())
-            .map(|((inv, filtered), proof)| Spans(inv, Some(filtered), proof));
+            .map(|(((inv, filtered), attributes), proof)| {
+                Spans(inv, Some(filtered), proof, attributes)
+            });
 
-        let with_proof = single_expr()
+        let with_proof = expr_with_attributes()
             .or_not()
-            .map_with_span(|_, span| span)
+            .map_with_span(|expr, span| expr.unwrap_or((span, Vec::new())))
             .then(code_block())
-            .map(|(inv, proof)| Spans(inv, None, Some(proof)));
+            .map(|((inv, attributes), proof)| Spans(inv, None, Some(proof), attributes));
 
-        just(Token::Invariant)
-            .ignore_then(ident())
+        // `strong` is not a keyword, so it is still usable as an identifier elsewhere
+        let strong = select! { Token::Ident(ident) if ident == "strong" => () }
+            .or_not()
+            .map(|strong| strong.is_some());
+
+        strong
+            .then_ignore(just(Token::Invariant))
+            .then(ident())
             .then(named_param_list())
             .then(choice((single_invariant, with_filtered_block, with_proof)))
             .map(
-                |((name, params), Spans(invariant, filters, proof))| Intermediate::Invariant {
-                    name,
-                    params,
-                    invariant,
-                    filters,
-                    proof,
+                |(((strong, name), params), Spans(invariant, filters, proof, attributes))| {
+                    Intermediate::Invariant {
+                        strong,
+                        name,
+                        params,
+                        invariant,
+                        filters,
+                        proof,
+                        attributes,
+                    }
                 },
             )
             .labelled("invariant declaration")
@@ -97,26 +336,39 @@ fn decl_parser() -> impl Parser<Token, Intermediate, Error = Simple<Token>> {
 
         let opening_tokens = persistent_ghost.or(regular_ghost);
 
+        let initial_value = just(Token::Equals)
+            .ignore_then(
+                none_of(Token::Semicolon)
+                    .repeated()
+                    .at_least(1)
+                    .map_with_span(|_, span| span),
+            )
+            .then_ignore(just(Token::Semicolon));
+
         let with_mapping = opening_tokens
             .clone()
             .then(ty())
             .then(ident())
-            .then(optional_code_block())
-            .map(
-                |(((persistent, mapping), name), axioms)| Intermediate::GhostMapping {
+            .then(choice((
+                initial_value.map(|initial_value| (None, Some(initial_value))),
+                axioms_block().map(|axioms| (axioms, None)),
+            )))
+            .map(|(((persistent, mapping), name), (axioms, initial_value))| {
+                Intermediate::GhostMapping {
                     persistent,
                     mapping,
                     name,
                     axioms,
-                },
-            )
+                    initial_value,
+                }
+            })
             .labelled("ghost declaration (with mapping)");
 
         let without_mapping = opening_tokens
             .then(ident())
             .then(unnamed_param_list())
             .then(returns_type())
-            .then(optional_code_block())
+            .then(axioms_block())
             .map(
                 |((((persistent, name), ty_list), returns), axioms)| Intermediate::GhostFunction {
                     persistent,
@@ -155,6 +407,27 @@ fn decl_parser() -> impl Parser<Token, Intermediate, Error = Simple<Token>> {
             .labelled("definition declaration")
     };
 
+    let macro_decl = {
+        let body = none_of(Token::Semicolon)
+            .repeated()
+            .at_least(1)
+            .map_with_span(|_, span| span)
+            .then_ignore(just(Token::Semicolon));
+
+        // neither `define` nor `macro` are keywords, so they are still usable as identifiers elsewhere
+        let define = select! { Token::Ident(ident) if ident == "define" => () };
+        let macro_kw = select! { Token::Ident(ident) if ident == "macro" => () };
+
+        define
+            .ignore_then(macro_kw)
+            .ignore_then(ident())
+            .then(named_param_list())
+            .then_ignore(just(Token::Equals))
+            .then(body)
+            .map(|((name, params), body)| Intermediate::Macro { name, params, body })
+            .labelled("macro declaration")
+    };
+
     let hook_decl = {
         let sload = just(Token::Sload)
             .ignore_then(named_param())
@@ -260,6 +533,7 @@ fn decl_parser() -> impl Parser<Token, Intermediate, Error = Simple<Token>> {
         ghost_decl,
         hook_decl,
         definition_decl,
+        macro_decl,
         import_stmt,
         use_stmt,
         using_stmt,
@@ -281,11 +555,39 @@ fn cvl_parser() -> impl Parser<Token, Vec<(Intermediate, Span)>, Error = Simple<
     .map_with_span(Intermediate::Documentation)
     .labelled("documentation");
 
+    let file_doc = select! {
+        Token::FileDocSlashed => Style::Slashed,
+        Token::FileDocStarred => Style::Starred,
+    }
+    .map_with_span(Intermediate::FileDocumentation)
+    .labelled("file documentation");
+
     let decl = decl_parser();
 
+    // something that looks like a declaration, but isn't one we recognize.
+    // it's only kept if it is documented, see [builder::Builder].
+    let unknown = none_of([
+        Token::CodeBlock,
+        Token::CurlyOpen,
+        Token::CurlyClose,
+        Token::Semicolon,
+    ])
+    .map_with_span(|_, span| span)
+    .then_ignore(none_of(UNKNOWN_HEADER_ENDERS).repeated())
+    .map_with_span(|first_token, header| (first_token, header))
+    .then_ignore(choice((
+        code_block().ignored(),
+        just(Token::Semicolon).ignored(),
+    )))
+    .map(|(first_token, header)| Intermediate::Unknown {
+        first_token,
+        header,
+    })
+    .labelled("unknown declaration");
+
     let failure = any().to(Intermediate::ParseError);
 
-    choice((freeform, cvl_doc, decl, failure))
+    choice((freeform, cvl_doc, file_doc, decl, unknown, failure))
         .recover_with(skip_until(SYNC_TOKENS, |_| Intermediate::ParseError))
         .map_with_span(|intermediate, span| (intermediate, span))
         .repeated()
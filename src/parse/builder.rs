@@ -1,23 +1,36 @@
-use super::terminated_str::TerminatedStr;
+use super::mapping::parse_mapping;
+use super::terminated_str::{join_lines, source_map_of, TerminatedStr};
+use super::trace::{self, Candidate, LineCursor};
 use super::types::Token;
-use super::{cvl_parser, lexer::cvl_lexer, Intermediate, Span, Style};
-use crate::util::ByteSpan;
-use crate::{Ast, CvlElement, DocumentationTag, TagKind};
+use super::{
+    cvl_parser,
+    lexer::{self, lex_with_blocks},
+    Intermediate, Span, Style,
+};
+use crate::coverage::MethodsCoverage;
+use crate::util::{count_lines, strip_bom, ByteSpan, RangeConverter, SimpleRange};
+use crate::{
+    Ast, CvlElement, DocumentationTag, DocumentationTagRef, ElementRef, TagKind, TagProvenance,
+};
 use chumsky::{Parser, Stream};
-use color_eyre::eyre::{bail, eyre};
-use color_eyre::Result;
 use core::panic;
+use eyre::{bail, eyre, Result};
+use itertools::Itertools;
+use ropey::Rope;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 struct DocumentationBuilder<'src> {
+    src: &'src str,
     kind: TagKind,
     desc: Vec<TerminatedStr<'src>>,
     span: Span,
 }
 
 impl<'src> DocumentationBuilder<'src> {
-    fn new(entire_span: Span) -> DocumentationBuilder<'src> {
+    fn new(src: &'src str, entire_span: Span) -> DocumentationBuilder<'src> {
         DocumentationBuilder {
+            src,
             kind: TagKind::default(),
             desc: Vec::new(),
             span: entire_span,
@@ -25,24 +38,64 @@ impl<'src> DocumentationBuilder<'src> {
     }
 }
 
-impl DocumentationTag {
-    fn from_spanned_iter<'src>(
+impl<'src> DocumentationTagRef<'src> {
+    /// `src` is the source the lines are slices of.
+    /// under `continuations`, a line indented by at least two more spaces than the line
+    /// which started the current tag continues its description, unless it starts with a tag.
+    /// it is kept on its own line, along with the indentation beyond that of the tag line,
+    /// and tags in its middle are not split from it.
+    fn from_spanned_iter(
+        src: &'src str,
         input: impl IntoIterator<Item = (TerminatedStr<'src>, Span)>,
         entire_span: Span,
-    ) -> Vec<DocumentationTag> {
+        aliases: &TagAliases,
+        continuations: bool,
+    ) -> Vec<DocumentationTagRef<'src>> {
         let mut tags = Vec::new();
 
-        let mut builder = DocumentationBuilder::new(entire_span);
+        let mut builder = DocumentationBuilder::new(src, entire_span);
+        let mut tag_indent = None;
 
-        for (mut line, line_span) in input {
-            if let Some(new_tag) = Builder::tag_from_content(line.content) {
+        let lines = input.into_iter().flat_map(|(mut line, line_span)| {
+            let indent = indentation(src, line.content);
+            let extra_indent = tag_indent.and_then(|tag_indent| indent.checked_sub(tag_indent));
+
+            let starts_with_tag = Builder::tag_from_content(line.content, aliases).is_some();
+
+            if continuations
+                && !line.content.is_empty()
+                && !starts_with_tag
+                && extra_indent >= Some(2)
+            {
+                let start = line.content.as_ptr() as usize - src.as_ptr() as usize;
+                let extra_indent = extra_indent.unwrap_or_default();
+                line.content = &src[start - extra_indent..start + line.content.len()];
+                return vec![(line, line_span)];
+            }
+
+            let split_points = Builder::inline_tag_starts(line.content, aliases);
+            let starts_tag = starts_with_tag || !split_points.is_empty();
+            if !line.content.is_empty() && (starts_tag || tag_indent.is_none()) {
+                tag_indent = Some(indent);
+            }
+
+            line.split_at_points(&split_points)
+                .into_iter()
+                .map(|part| (part, line_span.clone()))
+                .collect()
+        });
+
+        for (mut line, line_span) in lines {
+            if let Some((new_tag, tag_len)) = Builder::tag_from_content(line.content, aliases) {
                 if builder.previous_tag_still_in_progress() {
                     tags.push(builder.build_current());
                 }
 
-                if let Some(after_tag) = line.content.get(new_tag.len() + 1..) {
-                    line.content = after_tag;
-                }
+                // the tag is followed by whitespace, or ends the line
+                let after_tag = &line.content[tag_len..];
+                line.content = after_tag
+                    .strip_prefix(|c: char| c.is_ascii_whitespace())
+                    .unwrap_or(after_tag);
 
                 builder.kind = new_tag;
 
@@ -63,6 +116,16 @@ impl DocumentationTag {
     }
 }
 
+/// the number of spaces and tabs before `content`, which is a slice of a line of `src`
+fn indentation(src: &str, content: &str) -> usize {
+    let start = content.as_ptr() as usize - src.as_ptr() as usize;
+    src[..start]
+        .chars()
+        .rev()
+        .take_while(|&c| c == ' ' || c == '\t')
+        .count()
+}
+
 impl<'a> DocumentationBuilder<'a> {
     fn previous_tag_still_in_progress(&self) -> bool {
         !self.desc.is_empty()
@@ -72,33 +135,211 @@ impl<'a> DocumentationBuilder<'a> {
         self.desc.push(line);
     }
 
-    fn build_current(&mut self) -> DocumentationTag {
+    fn build_current(&mut self) -> DocumentationTagRef<'a> {
         let desc = std::mem::take(&mut self.desc);
+        let mut source_map = source_map_of(&desc, self.src);
+        let description = join_lines(desc);
+        source_map.truncate(description.len());
 
-        DocumentationTag {
+        DocumentationTagRef {
             kind: self.kind.clone(),
-            description: String::from_iter(desc),
+            description,
             span: self.span.clone(),
+            range: None,
+            provenance: TagProvenance::Authored,
+            source_map,
+        }
+    }
+}
+
+/// whether the text of a freeform comment is only a separator.
+/// empty text is decorative, since the comment was made entirely of comment markers.
+fn is_decorative(text: &str) -> bool {
+    let is_box_drawing = |c: char| matches!(c, '\u{2500}'..='\u{259f}');
+    text.chars().all(|c| {
+        c.is_whitespace()
+            || c.is_ascii_punctuation()
+            || is_box_drawing(c)
+            || matches!(c, '\u{2010}'..='\u{2015}')
+    })
+}
+
+/// sets the following element of each freeform comment, skipping comments and file documentation
+fn link_following_elements(elements: &mut [CvlElement]) {
+    let mut following = None;
+
+    for element in elements.iter_mut().rev() {
+        if let Some(kind) = element.element_kind() {
+            following = Some(ElementRef {
+                kind,
+                name: element.element_name().map(str::to_string),
+                span: element.element_span.clone(),
+            });
+        } else if let Ast::FreeFormComment {
+            following_element, ..
+        } = &mut element.ast
+        {
+            following_element.clone_from(&following);
         }
     }
 }
 
 enum DocOrAst {
     Doc(Vec<DocumentationTag>),
+    FileDoc(Vec<DocumentationTag>),
     Ast(Ast),
 }
 
-pub struct Builder<'src>(&'src str);
+/// how documentation is associated with a declaration that follows it after blank lines.
+/// comments that are not documentation are skipped, and never count as blank lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AttachmentPolicy {
+    /// documentation is attached regardless of the blank lines in between
+    #[default]
+    Permissive,
+    /// documentation is only attached if no blank line separates it from the declaration
+    Adjacent,
+    /// documentation is attached if at most this many blank lines separate it from the declaration.
+    /// the blank lines are kept in [CvlElement::doc_gaps], and reported as a `doc-element-gap` diagnostic.
+    AttachWithWarning(usize),
+}
+
+/// options of [Builder]. the default parses as the Certora Prover documents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseConfig {
+    pub attachment: AttachmentPolicy,
+    /// parse `////` comments which contain tags as documentation, rather than freeform comments
+    pub slash_tags_as_documentation: bool,
+    /// mark freeform comments made only of punctuation, whitespace and box-drawing characters,
+    /// such as `//// ======` or `/*****/`, as decorative. see [Ast::FreeFormComment].
+    pub detect_decorative: bool,
+    /// record the element following each freeform comment, for anchoring the comment to it.
+    /// see [Ast::FreeFormComment].
+    pub record_following_element: bool,
+    /// comments larger than this many bytes are kept as truncated freeform comments,
+    /// rather than parsed, and are reported as `comment-too-large`
+    pub max_comment_bytes: usize,
+    /// whether the source starts outside of any comment. when parsing part of a file,
+    /// which may start inside a block comment, set this to `false`: text up to a `*/`
+    /// which closes no `/*` is then skipped. see [Builder::skipped_start].
+    pub assume_clean_start: bool,
+    /// keep documentation which is directly followed by other documentation as [Ast::Detached],
+    /// rather than dropping it
+    pub detach_superseded_documentation: bool,
+    /// record which entries of each `methods` block are documented. see [Ast::Methods].
+    pub record_methods_coverage: bool,
+}
+
+impl Default for ParseConfig {
+    fn default() -> Self {
+        ParseConfig {
+            attachment: AttachmentPolicy::default(),
+            slash_tags_as_documentation: false,
+            detect_decorative: true,
+            record_following_element: false,
+            max_comment_bytes: 64 * 1024,
+            assume_clean_start: true,
+            detach_superseded_documentation: false,
+            record_methods_coverage: false,
+        }
+    }
+}
+
+/// user-defined tag names, without the `@`, and the tags they stand for
+type TagAliases = HashMap<String, TagKind>;
+
+pub struct Builder<'src>(&'src str, ParseConfig, TagAliases);
 
 impl<'src> Builder<'src> {
+    /// a builder of `src`, without its byte order mark if it has one, see [strip_bom].
+    /// spans of the built elements are relative to `src` without the mark.
     pub fn new(src: &'src str) -> Self {
-        Builder(src)
+        Builder(strip_bom(src), ParseConfig::default(), TagAliases::new())
+    }
+
+    pub fn with_config(self, config: ParseConfig) -> Self {
+        Builder(self.0, config, self.2)
+    }
+
+    /// parses each key of `aliases` as a tag of the kind its value names,
+    /// as in `@precondition` for `@notice`. names may be given with or without the `@`.
+    /// fails if a value is not a recognized tag, or if a key is a recognized tag or is not a single word.
+    pub fn with_tag_aliases(self, aliases: &HashMap<String, String>) -> Result<Self> {
+        let mut tag_aliases = self.2;
+
+        for (alias, target) in aliases {
+            let name = alias.strip_prefix('@').unwrap_or(alias);
+            if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '@') {
+                bail!("invalid tag alias: {alias:?}");
+            }
+            if TagKind::try_from(name).is_ok() {
+                bail!("cannot alias the built-in tag @{name}");
+            }
+
+            let kind = TagKind::try_from(target.as_str())
+                .map_err(|_| eyre!("alias @{name} is for an unrecognized tag: {target}"))?;
+            tag_aliases.insert(name.to_string(), kind);
+        }
+
+        Ok(Builder(self.0, self.1, tag_aliases))
+    }
+
+    pub fn with_attachment_policy(self, policy: AttachmentPolicy) -> Self {
+        let config = ParseConfig {
+            attachment: policy,
+            ..self.1
+        };
+        Builder(self.0, config, self.2)
+    }
+
+    pub(crate) fn src(&self) -> &'src str {
+        self.0
+    }
+
+    /// the span of the start of the source which is skipped, as the end of a block comment
+    /// which started before the source. always `None` under [ParseConfig::assume_clean_start].
+    /// a `*/` within a comment or a string which starts in the source does not end the skipped text.
+    pub fn skipped_start(&self) -> Option<Span> {
+        if self.1.assume_clean_start {
+            return None;
+        }
+
+        let chars: Vec<(usize, char)> = self.0.char_indices().collect();
+        let starts_with = |i: usize, pattern: &str| self.0[chars[i].0..].starts_with(pattern);
+        let mut i = 0;
+        while i < chars.len() {
+            if starts_with(i, "*/") {
+                return Some(0..i + 2);
+            }
+            if let Some(after) = lexer::skip_comment_or_string(&chars, i) {
+                i = after;
+            } else if starts_with(i, "/*") || starts_with(i, "\"") {
+                // an unterminated comment or string holds the rest of the source
+                return None;
+            } else {
+                i += 1;
+            }
+        }
+
+        None
     }
 
     pub fn lex(&self) -> Result<Vec<(Token, Span)>> {
-        let mut lexed = cvl_lexer()
-            .parse(self.0)
-            .map_err(|_| eyre!("lexing failed"))?;
+        // the skipped text is blanked, rather than removed, so that spans are unchanged
+        let blanked;
+        let src = match self.skipped_start() {
+            Some(skipped) => {
+                let blank = |c: char| if c == '\n' { c } else { ' ' };
+                let start = self.0.chars().take(skipped.end).map(blank);
+                blanked = start
+                    .chain(self.0.chars().skip(skipped.end))
+                    .collect::<String>();
+                &blanked
+            }
+            None => self.0,
+        };
+
+        let mut lexed = lex_with_blocks(src).map_err(|_| eyre!("lexing failed"))?;
         lexed.retain(|(tok, _)| !matches!(tok, Token::SingleLineComment | Token::MultiLineComment));
 
         Ok(lexed)
@@ -120,6 +361,36 @@ impl<'src> Builder<'src> {
         self.output_cvl_elements(parsed)
     }
 
+    /// parses a source consisting of a single documentation comment, without an element.
+    /// returns its tags, and whether it is file documentation.
+    pub(crate) fn build_documentation(&self) -> Result<(Vec<DocumentationTag>, bool)> {
+        let lexed = self.lex()?;
+        let [(token, span)] = lexed.as_slice() else {
+            bail!(
+                "expected a single documentation comment, found {} tokens",
+                lexed.len()
+            );
+        };
+
+        let intermediate = match token {
+            Token::CvlDocSlashed => Intermediate::Documentation(Style::Slashed, span.clone()),
+            Token::CvlDocStarred => Intermediate::Documentation(Style::Starred, span.clone()),
+            Token::FileDocSlashed => Intermediate::FileDocumentation(Style::Slashed, span.clone()),
+            Token::FileDocStarred => Intermediate::FileDocumentation(Style::Starred, span.clone()),
+            _ => bail!("expected a documentation comment, found {token}"),
+        };
+
+        match self.process_intermediate((intermediate, span.clone()))? {
+            (DocOrAst::Doc(doc), _) => Ok((doc, false)),
+            (DocOrAst::FileDoc(doc), _) => Ok((doc, true)),
+            // documentation is only kept as a freeform comment if it is too large
+            (DocOrAst::Ast(_), _) => bail!(
+                "documentation is larger than the maximum of {} bytes",
+                self.1.max_comment_bytes
+            ),
+        }
+    }
+
     const fn chars_to_trim<'a>(style: Style) -> &'a [char] {
         match style {
             Style::Slashed => &['/'],
@@ -127,8 +398,84 @@ impl<'src> Builder<'src> {
         }
     }
 
+    /// the tags of each documentation comment in the source, along with the span of the comment.
+    /// unlike [Builder::build], no elements are built, and single-line descriptions
+    /// are borrowed from the source rather than copied.
+    pub fn build_tag_refs(&self) -> Result<Vec<(Vec<DocumentationTagRef<'src>>, Span)>> {
+        let lexed = self.lex()?;
+        let converter = self.range_converter();
+
+        let docs = lexed
+            .into_iter()
+            .filter_map(|(token, span)| {
+                let (style, file_doc) = match token {
+                    Token::CvlDocSlashed => (Style::Slashed, false),
+                    Token::CvlDocStarred => (Style::Starred, false),
+                    Token::FileDocSlashed => (Style::Slashed, true),
+                    Token::FileDocStarred => (Style::Starred, true),
+                    _ => return None,
+                };
+
+                let mut tags = self.tag_refs(style, span.clone(), file_doc);
+                for tag in &mut tags {
+                    tag.range = Some(converter.to_clamped_range(tag.span.clone()));
+                }
+                Some((tags, span))
+            })
+            .collect();
+
+        Ok(docs)
+    }
+
+    fn tag_refs(&self, style: Style, span: Span, file_doc: bool) -> Vec<DocumentationTagRef<'src>> {
+        let continuations = matches!(style, Style::Slashed);
+        let input = self.slice(span.clone());
+        let mut body = ContentLines::new(input, span.clone(), Builder::chars_to_trim(style));
+        if file_doc {
+            body = body.with_inner_marker();
+        }
+
+        let tags =
+            DocumentationTagRef::from_spanned_iter(self.0, body, span, &self.2, continuations);
+        tags.iter().for_each(trace::tag);
+        tags
+    }
+
+    /// a freeform comment of the first [ParseConfig::max_comment_bytes] of the comment
+    fn truncated_comment(&self, style: Style, span: Span) -> Ast {
+        let input = self.slice(span.clone());
+        let mut end = self.1.max_comment_bytes.min(input.len());
+        while !input.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        let text = ContentLines::new(&input[..end], span, Builder::chars_to_trim(style))
+            .map(|(ter_line, _span)| ter_line)
+            .collect::<String>();
+
+        Ast::FreeFormComment {
+            text,
+            decorative: false,
+            following_element: None,
+            truncated: true,
+        }
+    }
+
+    /// whether any line of the comment starts a tag, at its start or in the middle
+    fn has_tags(&self, style: Style, span: Span) -> bool {
+        let input = self.slice(span.clone());
+        ContentLines::new(input, span, Builder::chars_to_trim(style)).any(|(line, _)| {
+            Builder::tag_from_content(line.content, &self.2).is_some()
+                || !Builder::inline_tag_starts(line.content, &self.2).is_empty()
+        })
+    }
+
+    fn range_converter(&self) -> RangeConverter {
+        RangeConverter::new(Rope::from_str(self.0))
+    }
+
     //this panics, because a failure is an unrecoverable logic error
-    fn slice(&self, s: impl Into<Span>) -> &str {
+    fn slice(&self, s: impl Into<Span>) -> &'src str {
         let span: Span = s.into();
         span.byte_slice(self.0)
             .unwrap_or_else(|| panic!("{:?}: not in source bounds", span))
@@ -138,13 +485,33 @@ impl<'src> Builder<'src> {
         self.slice(s).to_owned()
     }
 
-    fn tag_from_content(content: &str) -> Option<TagKind> {
+    /// byte offsets of tags which start in the middle of `content`, as in `@formula x > 0 @param x`.
+    /// a tag must start a word, so `a@notice` is not split.
+    fn inline_tag_starts(content: &str, aliases: &TagAliases) -> Vec<usize> {
+        content
+            .char_indices()
+            .tuple_windows()
+            .filter(|((_, before), (i, c))| {
+                *c == '@'
+                    && before.is_ascii_whitespace()
+                    && Builder::tag_from_content(&content[*i..], aliases).is_some()
+            })
+            .map(|(_, (i, _))| i)
+            .collect()
+    }
+
+    /// the tag at the start of `content`, and its length in bytes
+    fn tag_from_content(content: &str, aliases: &TagAliases) -> Option<(TagKind, usize)> {
         if content.starts_with('@') {
             let tag_end = content
                 .find(|c: char| c.is_ascii_whitespace())
                 .unwrap_or(content.len());
 
-            content[..tag_end].try_into().ok()
+            let tag = &content[..tag_end];
+            let kind = TagKind::try_from(tag)
+                .ok()
+                .or_else(|| aliases.get(&tag[1..]).cloned())?;
+            Some((kind, tag_end))
         } else {
             None
         }
@@ -159,10 +526,47 @@ impl<'src> Builder<'src> {
         let mut elements = Vec::new();
         let mut current_doc: Option<Vec<DocumentationTag>> = None;
         let mut current_doc_span: Option<Span> = None;
+        // where the current documentation is inserted if it is detached, since freeform
+        // comments between the documentation and its element are pushed before it
+        let mut current_doc_index = 0;
+        let mut current_candidate: Option<Candidate> = None;
+        let mut line_cursor = LineCursor::new(self.0);
 
         for parse_result in parsing_results {
-            let Ok((doc_or_ast, span)) = self.process_intermediate(parse_result) else {
-                continue;
+            let result_span = parse_result.1.clone();
+
+            let candidate = match &parse_result.0 {
+                Intermediate::Documentation(style, span)
+                | Intermediate::FreeFormComment(style, span) => {
+                    Some(Candidate::new(style, &mut line_cursor, span))
+                }
+                _ => None,
+            };
+            let processed = match &candidate {
+                Some(candidate) => candidate.in_scope(|| self.process_intermediate(parse_result)),
+                None => self.process_intermediate(parse_result),
+            };
+
+            let (doc_or_ast, span) = match processed {
+                Ok(processed) => processed,
+                Err(error) => {
+                    trace::builder_failed(&error);
+                    // documentation must never be associated across a closing brace
+                    // parse errors at the end of the input span past the source
+                    let text = result_span.byte_slice(self.0).unwrap_or_default();
+                    if text.trim_start().starts_with('}') {
+                        if let Some(candidate) = current_candidate.take() {
+                            candidate.association_failed("}");
+                        }
+                        if let (Some(doc), Some(doc_span)) =
+                            (current_doc.take(), current_doc_span.take())
+                        {
+                            let detached = self.detached(doc, doc_span, Vec::new(), &src_ref);
+                            elements.insert(current_doc_index, detached);
+                        }
+                    }
+                    continue;
+                }
             };
 
             match doc_or_ast {
@@ -173,9 +577,14 @@ impl<'src> Builder<'src> {
                         ast,
                         element_span: span,
                         doc_span: None,
+                        element_range: SimpleRange::default(),
+                        doc_range: None,
+                        doc_gaps: Vec::new(),
                         src: Arc::clone(&src_ref),
+                        annotations: BTreeMap::new(),
                     });
                 }
+                DocOrAst::Ast(Ast::Unknown { .. }) if current_doc.is_none() => continue,
                 DocOrAst::Ast(ast) => {
                     let (doc, doc_span) = match (current_doc.take(), current_doc_span.take()) {
                         (Some(doc), Some(doc_span)) => (doc, Some(doc_span)),
@@ -184,12 +593,72 @@ impl<'src> Builder<'src> {
                         (None, Some(_)) => panic!("got doc_span without doc"),
                     };
 
+                    let doc_gaps = match &doc_span {
+                        Some(doc_span) => self.blank_lines_between(doc_span.end, span.start),
+                        None => Vec::new(),
+                    };
+                    let blank_lines: usize = doc_gaps
+                        .iter()
+                        .map(|gap| count_lines(self.slice(gap.clone())) - 1)
+                        .sum();
+
+                    let rejected = match self.1.attachment {
+                        AttachmentPolicy::Permissive => false,
+                        AttachmentPolicy::Adjacent => blank_lines > 0,
+                        AttachmentPolicy::AttachWithWarning(max) => blank_lines > max,
+                    };
+                    let (doc, doc_span, doc_gaps) = match (rejected, doc_span) {
+                        // the documentation is kept, along with the gap that detached it
+                        (true, Some(doc_span)) => {
+                            let detached = self.detached(doc, doc_span, doc_gaps, &src_ref);
+                            elements.insert(current_doc_index, detached);
+                            (Vec::new(), None, Vec::new())
+                        }
+                        (_, doc_span) => match self.1.attachment {
+                            AttachmentPolicy::AttachWithWarning(_) => (doc, doc_span, doc_gaps),
+                            _ => (doc, doc_span, Vec::new()),
+                        },
+                    };
+
+                    if let Some(candidate) = current_candidate.take() {
+                        match &ast {
+                            Ast::Unknown { first_token, .. } => {
+                                candidate.association_failed(first_token)
+                            }
+                            _ if doc_span.is_none() => {
+                                candidate.association_failed(self.next_token(&span))
+                            }
+                            ast => candidate.associated(ast),
+                        }
+                    }
+
+                    if doc.is_empty() && matches!(ast, Ast::Unknown { .. }) {
+                        continue;
+                    }
+
                     elements.push(CvlElement {
                         doc,
                         ast,
                         element_span: span,
                         doc_span,
+                        element_range: SimpleRange::default(),
+                        doc_range: None,
+                        doc_gaps,
+                        src: Arc::clone(&src_ref),
+                        annotations: BTreeMap::new(),
+                    });
+                }
+                DocOrAst::FileDoc(doc) => {
+                    elements.push(CvlElement {
+                        doc,
+                        ast: Ast::FileDocumentation,
+                        element_span: span.clone(),
+                        doc_span: Some(span),
+                        element_range: SimpleRange::default(),
+                        doc_range: None,
+                        doc_gaps: Vec::new(),
                         src: Arc::clone(&src_ref),
+                        annotations: BTreeMap::new(),
                     });
                 }
                 DocOrAst::Doc(doc) => {
@@ -197,54 +666,196 @@ impl<'src> Builder<'src> {
                     //     current_doc.is_none(),
                     //     "documentation followed by documentation"
                     // );
+                    if let Some(replaced) = current_candidate.replace(candidate.unwrap()) {
+                        replaced.association_failed(self.next_token(&span));
+                    }
+                    if let (Some(replaced), Some(replaced_span)) =
+                        (current_doc.take(), current_doc_span.take())
+                    {
+                        if self.1.detach_superseded_documentation {
+                            let detached =
+                                self.detached(replaced, replaced_span, Vec::new(), &src_ref);
+                            elements.insert(current_doc_index, detached);
+                        }
+                    }
                     current_doc = Some(doc);
                     current_doc_span = Some(span);
+                    current_doc_index = elements.len();
                     continue;
                 }
             }
         }
 
+        if let Some(candidate) = current_candidate {
+            candidate.association_failed("<end of input>");
+        }
+        if let (Some(doc), Some(doc_span)) = (current_doc, current_doc_span) {
+            let detached = self.detached(doc, doc_span, Vec::new(), &src_ref);
+            elements.insert(current_doc_index, detached);
+        }
+
+        if self.1.record_following_element {
+            link_following_elements(&mut elements);
+        }
+
+        let converter = self.range_converter();
+        for element in &mut elements {
+            element.set_ranges(&converter);
+        }
+
+        if cfg!(debug_assertions) {
+            for element in &elements {
+                if let Err(mismatch) = element.verify_against(self.0) {
+                    panic!("source text does not match the source: {mismatch}");
+                }
+                let text = element.source_text();
+                assert_eq!(text, text.trim(), "source text has surrounding whitespace");
+            }
+        }
+
         Ok(elements)
     }
 
+    /// documentation which is associated with no element, see [Ast::Detached]
+    fn detached(
+        &self,
+        doc: Vec<DocumentationTag>,
+        doc_span: Span,
+        doc_gaps: Vec<Span>,
+        src: &Arc<str>,
+    ) -> CvlElement {
+        CvlElement {
+            doc,
+            ast: Ast::Detached,
+            element_span: doc_span.clone(),
+            doc_span: Some(doc_span),
+            element_range: SimpleRange::default(),
+            doc_range: None,
+            doc_gaps,
+            src: Arc::clone(src),
+            annotations: BTreeMap::new(),
+        }
+    }
+
+    /// the first word of the text at `span`, for reporting what followed documentation
+    fn next_token(&self, span: &Span) -> &'src str {
+        let text = self.slice(span.clone());
+        text.split_whitespace().next().unwrap_or(text)
+    }
+
+    /// the runs of blank lines from `from` to `to`, each spanning entire lines.
+    /// the line `from` is on, and the line `to` is on, are never blank.
+    /// lines within a comment are not blank, so they end a run.
+    fn blank_lines_between(&self, from: usize, to: usize) -> Vec<Span> {
+        let text = self.slice(from..to);
+        let mut runs: Vec<Span> = Vec::new();
+
+        // the rest of the line where the documentation ends
+        let Some(first_newline) = text.find('\n') else {
+            return runs;
+        };
+        let from_line_start = from > 0 && self.slice(from - 1..from) == "\n";
+        let (mut pos, rest) = if from_line_start {
+            (from, text)
+        } else {
+            (
+                from + text[..=first_newline].chars().count(),
+                &text[first_newline + 1..],
+            )
+        };
+
+        let mut in_comment = false;
+        // the last line is the start of the line of the declaration
+        for line in rest
+            .split_inclusive('\n')
+            .filter(|line| line.ends_with('\n'))
+        {
+            let line_len = line.chars().count();
+            let is_blank = !in_comment && line.trim().is_empty();
+
+            if let Some(opened) = line.rfind("/*") {
+                in_comment = !line[opened..].contains("*/");
+            } else if line.contains("*/") {
+                in_comment = false;
+            }
+
+            if is_blank {
+                match runs.last_mut() {
+                    Some(run) if run.end == pos => run.end = pos + line_len,
+                    _ => runs.push(pos..pos + line_len),
+                }
+            }
+            pos += line_len;
+        }
+
+        runs
+    }
+
     fn process_intermediate(
         &self,
         (intermediate, span): (Intermediate, Span),
     ) -> Result<(DocOrAst, Span)> {
         let process_result = match intermediate {
+            Intermediate::FreeFormComment(style, span)
+            | Intermediate::Documentation(style, span)
+            | Intermediate::FileDocumentation(style, span)
+                if self.slice(span.clone()).len() > self.1.max_comment_bytes =>
+            {
+                DocOrAst::Ast(self.truncated_comment(style, span))
+            }
+            Intermediate::FreeFormComment(Style::Slashed, span)
+                if self.1.slash_tags_as_documentation
+                    && self.has_tags(Style::Slashed, span.clone()) =>
+            {
+                let doc = self.tag_refs(Style::Slashed, span, false);
+                DocOrAst::Doc(doc.into_iter().map(DocumentationTag::from).collect())
+            }
             Intermediate::FreeFormComment(style, span) => {
                 let input = self.slice(span.clone());
                 let text = ContentLines::new(input, span, Builder::chars_to_trim(style))
                     .map(|(ter_line, _span)| ter_line)
-                    .collect();
-
-                let ast = Ast::FreeFormComment { text };
+                    .collect::<String>();
+
+                let decorative = self.1.detect_decorative && is_decorative(&text);
+                let ast = Ast::FreeFormComment {
+                    text,
+                    decorative,
+                    following_element: None,
+                    truncated: false,
+                };
                 DocOrAst::Ast(ast)
             }
             Intermediate::Documentation(style, span) => {
-                let input = self.slice(span.clone());
-                let body = ContentLines::new(input, span.clone(), Builder::chars_to_trim(style));
-
-                let doc = DocumentationTag::from_spanned_iter(body, span);
-                DocOrAst::Doc(doc)
+                let doc = self.tag_refs(style, span, false);
+                DocOrAst::Doc(doc.into_iter().map(DocumentationTag::from).collect())
+            }
+            Intermediate::FileDocumentation(style, span) => {
+                let doc = self.tag_refs(style, span, true);
+                DocOrAst::FileDoc(doc.into_iter().map(DocumentationTag::from).collect())
             }
             Intermediate::Methods(block) => {
                 let block = self.trimmed_block_slice(block).to_string();
 
-                let ast = Ast::Methods { block };
+                let coverage = self
+                    .1
+                    .record_methods_coverage
+                    .then(|| MethodsCoverage::of_block(&block));
+                let ast = Ast::Methods { block, coverage };
                 DocOrAst::Ast(ast)
             }
             Intermediate::Function {
                 name,
                 params,
-                returns,
+                return_types,
                 block,
             } => {
-                let block = self.trimmed_block_slice(block).to_string();
+                let block = block.map(|c| self.trimmed_block_slice(c).to_string());
+                let returns = Some(return_types.join(", ")).filter(|returns| !returns.is_empty());
                 let ast = Ast::Function {
                     name,
                     params,
                     returns,
+                    return_types,
                     block,
                 };
 
@@ -255,13 +866,18 @@ impl<'src> Builder<'src> {
                 mapping,
                 name,
                 axioms,
+                initial_value,
             } => {
                 let axioms = axioms.map(|c| self.owned_slice(c));
+                let initial_value = initial_value.map(|c| self.owned_slice(c));
+                let mapping_type = parse_mapping(&mapping);
                 let ast = Ast::GhostMapping {
                     persistent,
                     name,
                     mapping,
+                    mapping_type,
                     axioms,
+                    initial_value,
                 };
 
                 DocOrAst::Ast(ast)
@@ -288,6 +904,7 @@ impl<'src> Builder<'src> {
                 name,
                 params,
                 filters,
+                attributes,
                 block,
             } => {
                 let block = self.trimmed_block_slice(block).to_string();
@@ -298,6 +915,7 @@ impl<'src> Builder<'src> {
                     name,
                     params,
                     filters,
+                    attributes,
                     block,
                 };
 
@@ -320,23 +938,33 @@ impl<'src> Builder<'src> {
 
                 DocOrAst::Ast(ast)
             }
+            Intermediate::Macro { name, params, body } => {
+                let body = self.owned_slice(body);
+
+                let ast = Ast::CVLMacro { name, params, body };
+                DocOrAst::Ast(ast)
+            }
             Intermediate::Invariant {
+                strong,
                 name,
                 params,
                 invariant,
                 filters,
                 proof,
+                attributes,
             } => {
                 let invariant = self.owned_slice(invariant);
                 let filters = filters.map(|c| self.owned_slice(c));
                 let proof = proof.map(|c| self.trimmed_block_slice(c).to_string());
 
                 let ast = Ast::Invariant {
+                    strong,
                     name,
                     params,
                     invariant,
                     filters,
                     proof,
+                    attributes,
                 };
 
                 DocOrAst::Ast(ast)
@@ -362,6 +990,20 @@ impl<'src> Builder<'src> {
                 contract_name,
                 spec_name,
             }),
+            Intermediate::Unknown {
+                first_token,
+                header,
+            } => {
+                let first_token = self.owned_slice(first_token);
+                let raw_header = self.slice(header).trim().to_string();
+                let ast = Ast::Unknown {
+                    first_token,
+                    raw_header,
+                    range: SimpleRange::default(),
+                };
+
+                DocOrAst::Ast(ast)
+            }
             Intermediate::ParseError => bail!("parse errors are not parsed"),
             Intermediate::HookSload {
                 loaded,
@@ -421,7 +1063,19 @@ impl<'src> Builder<'src> {
             }
         };
 
-        Ok((process_result, span))
+        Ok((process_result, self.trim_span_end(span)))
+    }
+
+    /// slashed comments end with a newline, which is not part of the element
+    fn trim_span_end(&self, span: Span) -> Span {
+        let trailing_whitespace = self
+            .slice(span.clone())
+            .chars()
+            .rev()
+            .take_while(|c| c.is_whitespace())
+            .count();
+
+        span.start..span.end - trailing_whitespace
     }
 
     fn trimmed_block_slice(&self, s: impl Into<Span>) -> &str {
@@ -437,6 +1091,7 @@ pub struct ContentLines<'src, 'trim> {
     input: &'src str,
     span: Span,
     chars_to_trim: &'trim [char],
+    inner_marker: bool,
 }
 
 impl<'src, 'trim> ContentLines<'src, 'trim> {
@@ -449,6 +1104,15 @@ impl<'src, 'trim> ContentLines<'src, 'trim> {
             input,
             span,
             chars_to_trim,
+            inner_marker: false,
+        }
+    }
+
+    /// also strip the `//!` or `/*!` marker of file documentation from the start of each line
+    pub fn with_inner_marker(self) -> Self {
+        ContentLines {
+            inner_marker: true,
+            ..self
         }
     }
 
@@ -483,13 +1147,22 @@ impl<'a, 'b> Iterator for ContentLines<'a, 'b> {
         {
             //potentially skip first and last lines for multiline starred comments
             let trimmed = line.trim();
-            if trimmed == "/**" || trimmed == "*/" {
+            if trimmed == "/**" || trimmed == "/*!" || trimmed == "*/" {
                 return self.next();
             }
         }
 
         let mut terminated = TerminatedStr::from(line);
 
+        if self.inner_marker {
+            // only the marker is stripped, so that a `!` in the text itself is kept
+            let content = terminated.content.trim_start();
+            terminated.content = content
+                .strip_prefix("//!")
+                .or_else(|| content.strip_prefix("/*!"))
+                .unwrap_or(content);
+        }
+
         let should_trim = |ch| self.chars_to_trim.contains(&ch) || ch.is_ascii_whitespace();
         terminated.content = terminated.content.trim_matches(should_trim);
 
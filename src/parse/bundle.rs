@@ -0,0 +1,106 @@
+//! parsing of bundles: several spec files concatenated into one string,
+//! each starting with a marker line such as `// ===== file: foo.spec =====`.
+
+use super::builder::Builder;
+use crate::CvlElement;
+use eyre::Result;
+use std::fmt::Display;
+
+/// the format of the line starting each file of a bundle.
+/// the file name is everything between `prefix` and `suffix`, trimmed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleMarker {
+    pub prefix: String,
+    pub suffix: String,
+}
+
+impl Default for BundleMarker {
+    fn default() -> Self {
+        BundleMarker {
+            prefix: "// ===== file:".to_string(),
+            suffix: "=====".to_string(),
+        }
+    }
+}
+
+impl BundleMarker {
+    /// `None` if `line` is not a marker, `Some(None)` if it is a marker without a file name.
+    fn file_name<'a>(&self, line: &'a str) -> Option<Option<&'a str>> {
+        let after_prefix = line.trim().strip_prefix(self.prefix.as_str())?;
+        let file_name = after_prefix
+            .strip_suffix(self.suffix.as_str())
+            .map(str::trim)
+            .filter(|file_name| !file_name.is_empty());
+
+        Some(file_name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MalformedMarker {
+    /// zero-based line number of the marker within the bundle
+    pub line: usize,
+    pub text: String,
+}
+
+impl Display for MalformedMarker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "malformed file marker at line {}: {}",
+            self.line, self.text
+        )
+    }
+}
+
+impl std::error::Error for MalformedMarker {}
+
+#[derive(Debug, Clone, Default)]
+pub struct Bundle {
+    /// the name and elements of each file, in order. the spans of the elements
+    /// are relative to the start of their file, which is the line after its marker.
+    /// text before the first marker, if any, is a file with an empty name.
+    pub files: Vec<(String, Vec<CvlElement>)>,
+    /// if set, the file before this marker extends to the end of the bundle.
+    pub malformed_marker: Option<MalformedMarker>,
+}
+
+pub fn parse_bundle(src: &str, marker: &BundleMarker) -> Result<Bundle> {
+    let mut bundle = Bundle::default();
+    let mut file_name = String::new();
+    let mut file_start = 0;
+    let mut offset = 0;
+
+    let mut push_file = |file_name: String, text: &str| -> Result<()> {
+        if !file_name.is_empty() || !text.trim().is_empty() {
+            let elements = Builder::new(text).build()?;
+            bundle.files.push((file_name, elements));
+        }
+        Ok(())
+    };
+
+    for (line_number, line) in src.split_inclusive('\n').enumerate() {
+        let line_start = offset;
+        offset += line.len();
+
+        match marker.file_name(line) {
+            None => continue,
+            Some(Some(next_file_name)) => {
+                push_file(file_name, &src[file_start..line_start])?;
+                file_name = next_file_name.to_string();
+                file_start = offset;
+            }
+            Some(None) => {
+                bundle.malformed_marker = Some(MalformedMarker {
+                    line: line_number,
+                    text: line.trim_end().to_string(),
+                });
+                break;
+            }
+        }
+    }
+
+    push_file(file_name, &src[file_start..])?;
+
+    Ok(bundle)
+}
@@ -0,0 +1,196 @@
+//! parsing of spec files line by line, yielding elements before the entire file is read.
+
+use super::builder::Builder;
+use super::relocate;
+use crate::util::{count_lines, strip_bom, SimplePosition, Span};
+use crate::CvlElement;
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::sync::Arc;
+
+/// parses the elements of a spec file from a reader, yielding them as they are completed.
+///
+/// lines are buffered until the end of a top-level declaration, which is a line ending in `}` or `;`
+/// outside of any braces. the buffered chunk is then parsed on its own, and the spans of its elements
+/// are shifted to be relative to the entire input, as with [super::parse_range].
+/// the [CvlElement::src] of each element is the input read so far, through the end of its chunk.
+/// chunks which fail to parse are skipped, see [FileParser::parse_errors].
+///
+/// iteration stops at the end of the input, or at the first I/O error, see [FileParser::error].
+pub struct FileParser<R: BufRead> {
+    reader: R,
+    chunk: String,
+    /// the input before `chunk`
+    read: String,
+    /// the number of characters of `read`
+    read_chars: usize,
+    /// the number of line breaks in `read`
+    read_lines: u32,
+    parse_errors: Vec<(Span, eyre::Report)>,
+    /// whether the chunk ends a declaration, unless the next line continues it
+    chunk_may_end: bool,
+    depth: usize,
+    in_block_comment: bool,
+    ready: VecDeque<CvlElement>,
+    error: Option<io::Error>,
+    done: bool,
+    /// whether no line was read yet, so a byte order mark may follow
+    at_start: bool,
+}
+
+impl<R: BufRead> FileParser<R> {
+    pub fn new(reader: R) -> FileParser<R> {
+        FileParser {
+            reader,
+            chunk: String::new(),
+            read: String::new(),
+            read_chars: 0,
+            read_lines: 0,
+            parse_errors: Vec::new(),
+            chunk_may_end: false,
+            depth: 0,
+            in_block_comment: false,
+            ready: VecDeque::new(),
+            error: None,
+            done: false,
+            at_start: true,
+        }
+    }
+
+    /// the I/O error that stopped iteration, if any
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    /// the chunks which could not be parsed, with their spans in the input.
+    /// their elements are not yielded.
+    pub fn parse_errors(&self) -> &[(Span, eyre::Report)] {
+        &self.parse_errors
+    }
+
+    fn flush(&mut self) {
+        let chunk = std::mem::take(&mut self.chunk);
+        self.chunk_may_end = false;
+
+        let byte_offset = self.read.len();
+        let char_offset = self.read_chars;
+        // chunks are made of entire lines
+        let start = SimplePosition {
+            line: self.read_lines,
+            character: 0,
+        };
+        self.read.push_str(&chunk);
+        self.read_chars += chunk.chars().count();
+        self.read_lines += (count_lines(&chunk) - 1) as u32;
+
+        if chunk.trim().is_empty() {
+            return;
+        }
+        let mut elements = match Builder::new(&chunk).build() {
+            Ok(elements) => elements,
+            Err(error) => {
+                self.parse_errors
+                    .push((char_offset..self.read_chars, error));
+                return;
+            }
+        };
+
+        let src = Arc::from(self.read.as_str());
+        relocate(&mut elements, char_offset, byte_offset, start, src);
+        self.ready.extend(elements);
+    }
+
+    /// updates the brace depth, ignoring braces in comments and strings.
+    /// returns whether the line ends a declaration.
+    fn scan_line(&mut self, line: &str) -> bool {
+        let mut chars = line.chars().peekable();
+        let mut in_string = false;
+        let mut last_code_char = None;
+
+        while let Some(ch) = chars.next() {
+            if self.in_block_comment {
+                if ch == '*' && chars.peek() == Some(&'/') {
+                    chars.next();
+                    self.in_block_comment = false;
+                }
+                continue;
+            }
+            if in_string {
+                in_string = ch != '"';
+                continue;
+            }
+
+            match ch {
+                '/' if chars.peek() == Some(&'/') => break,
+                '/' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    self.in_block_comment = true;
+                    continue;
+                }
+                '"' => in_string = true,
+                '{' => self.depth += 1,
+                '}' => self.depth = self.depth.saturating_sub(1),
+                _ => {}
+            }
+
+            if !ch.is_whitespace() {
+                last_code_char = Some(ch);
+            }
+        }
+
+        self.depth == 0 && matches!(last_code_char, Some('}' | ';'))
+    }
+
+    /// a declaration may continue with a block or a filter on the next line, as in
+    /// `rule foo(method f) filtered { ... }` followed by the body of the rule.
+    fn continues_declaration(line: &str) -> bool {
+        let line = line.trim_start();
+        line.starts_with('{') || line.starts_with("filtered")
+    }
+}
+
+impl<R: BufRead> Iterator for FileParser<R> {
+    type Item = CvlElement;
+
+    fn next(&mut self) -> Option<CvlElement> {
+        loop {
+            if let Some(element) = self.ready.pop_front() {
+                return Some(element);
+            }
+            if self.done {
+                return None;
+            }
+
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => {
+                    self.done = true;
+                    self.flush();
+                    continue;
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    self.done = true;
+                    self.error = Some(error);
+                    continue;
+                }
+            }
+
+            if self.at_start {
+                self.at_start = false;
+                line = strip_bom(&line).to_string();
+            }
+
+            let is_blank = line.trim().is_empty();
+            if self.chunk_may_end && !is_blank && !FileParser::<R>::continues_declaration(&line) {
+                self.flush();
+            }
+
+            let ends_declaration = self.scan_line(&line);
+            if !is_blank {
+                self.chunk_may_end = ends_declaration;
+            }
+            self.chunk.push_str(&line);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! lightweight checking of the expressions in `@formula` tags.
+//! this is not a CVL parser: it only catches structural typos.
+
+/// binary and unary operators, longest first so that `<=>` is not lexed as `<=` and `>`
+const OPERATORS: &[&str] = &[
+    "<=>", "=>", "&&", "||", "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "!", "^",
+    "&", "|", "?", ":", "=",
+];
+
+const QUANTIFIERS: &[&str] = &["forall", "exists"];
+
+/// suffixes of a method call, as in `f@withrevert(e)`
+const CALL_MODIFIERS: &[&str] = &["@withrevert", "@norevert", "@dontsummarize"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// an identifier, a number, or a string literal
+    Word,
+    CallModifier,
+    Operator,
+    Open(char),
+    Close(char),
+    Dot,
+    Comma,
+}
+
+struct Lexeme<'a> {
+    kind: Kind,
+    text: &'a str,
+    /// character offset in the formula
+    pos: usize,
+}
+
+/// checks that `formula` is well-formed: brackets are balanced, no operator is missing
+/// its right-hand side, and quantifiers are of the form `forall uint x. <expression>`.
+/// call modifiers such as `f@withrevert(e)` and string literals are accepted.
+/// the error describes the first issue found, with its character offset in the formula.
+pub fn validate_formula(formula: &str) -> Result<(), String> {
+    let lexemes = lex(formula)?;
+    if lexemes.is_empty() {
+        return Err("formula is empty".to_string());
+    }
+
+    let mut open_brackets: Vec<&Lexeme> = Vec::new();
+
+    for (i, lexeme) in lexemes.iter().enumerate() {
+        match lexeme.kind {
+            Kind::Open(_) => open_brackets.push(lexeme),
+            Kind::Close(close) => match open_brackets.pop() {
+                Some(Lexeme {
+                    kind: Kind::Open(open),
+                    ..
+                }) if matching(*open) == close => (),
+                Some(open) => {
+                    return Err(format!(
+                        "`{}` at {} is closed by `{close}` at {}",
+                        open.text, open.pos, lexeme.pos
+                    ))
+                }
+                None => return Err(format!("unmatched `{close}` at {}", lexeme.pos)),
+            },
+            Kind::Operator if ends_expression(lexemes.get(i + 1)) => {
+                return Err(format!(
+                    "operator `{}` at {} is missing its right-hand side",
+                    lexeme.text, lexeme.pos
+                ))
+            }
+            Kind::Word if QUANTIFIERS.contains(&lexeme.text) => {
+                check_quantifier(&lexemes[i..])?;
+            }
+            _ => (),
+        }
+    }
+
+    match open_brackets.pop() {
+        Some(open) => Err(format!("unclosed `{}` at {}", open.text, open.pos)),
+        None => Ok(()),
+    }
+}
+
+/// `lexemes` starts with the quantifier
+fn check_quantifier(lexemes: &[Lexeme]) -> Result<(), String> {
+    let quantifier = &lexemes[0];
+    let variables = lexemes[1..]
+        .iter()
+        .take_while(|lexeme| lexeme.kind == Kind::Word || matches!(lexeme.text, "[" | "]"))
+        .count();
+
+    if variables == 0 {
+        return Err(format!(
+            "`{}` at {} must be followed by a variable declaration",
+            quantifier.text, quantifier.pos
+        ));
+    }
+
+    match lexemes.get(1 + variables) {
+        Some(dot) if dot.kind == Kind::Dot => {
+            if ends_expression(lexemes.get(2 + variables)) {
+                Err(format!(
+                    "`{}` at {} has no body",
+                    quantifier.text, quantifier.pos
+                ))
+            } else {
+                Ok(())
+            }
+        }
+        _ => Err(format!(
+            "expected `.` after the variables of `{}` at {}",
+            quantifier.text, quantifier.pos
+        )),
+    }
+}
+
+/// whether an expression cannot continue with `next`
+fn ends_expression(next: Option<&Lexeme>) -> bool {
+    next.is_none_or(|next| matches!(next.kind, Kind::Close(_) | Kind::Comma))
+}
+
+fn matching(open: char) -> char {
+    match open {
+        '(' => ')',
+        '[' => ']',
+        _ => '}',
+    }
+}
+
+/// the length in bytes of the string literal which `rest` starts with, including its quotes.
+/// a quote escaped with a backslash does not end the string.
+fn string_len(rest: &str, pos: usize) -> Result<usize, String> {
+    let mut escaped = false;
+    for (i, c) in rest.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Ok(i + 1),
+            _ => (),
+        }
+    }
+    Err(format!("unterminated string at {pos}"))
+}
+
+/// whether `rest` starts with `word`, which is not followed by more of the same identifier
+fn starts_with_word(rest: &str, word: &str) -> bool {
+    rest.strip_prefix(word).is_some_and(|after| {
+        !after.starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '$')
+    })
+}
+
+fn lex(formula: &str) -> Result<Vec<Lexeme<'_>>, String> {
+    let mut lexemes = Vec::new();
+    let mut chars = formula.char_indices().enumerate();
+
+    while let Some((pos, (start, c))) = chars.next() {
+        let rest = &formula[start..];
+
+        let (kind, len) = if c.is_whitespace() {
+            continue;
+        } else if c.is_alphanumeric() || c == '_' || c == '$' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+                .unwrap_or(rest.len());
+            (Kind::Word, len)
+        } else if c == '"' {
+            (Kind::Word, string_len(rest, pos)?)
+        } else if let Some(modifier) = CALL_MODIFIERS
+            .iter()
+            .find(|modifier| starts_with_word(rest, modifier))
+        {
+            (Kind::CallModifier, modifier.len())
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            (Kind::Operator, op.len())
+        } else {
+            let kind = match c {
+                '(' | '[' | '{' => Kind::Open(c),
+                ')' | ']' | '}' => Kind::Close(c),
+                '.' => Kind::Dot,
+                ',' => Kind::Comma,
+                _ => return Err(format!("unexpected `{c}` at {pos}")),
+            };
+            (kind, c.len_utf8())
+        };
+
+        let text = &rest[..len];
+        for _ in 1..text.chars().count() {
+            chars.next();
+        }
+        lexemes.push(Lexeme { kind, text, pos });
+    }
+
+    Ok(lexemes)
+}
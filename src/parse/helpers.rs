@@ -6,11 +6,13 @@ use chumsky::prelude::*;
 use itertools::Itertools;
 use std::iter;
 
-pub const SYNC_TOKENS: [Token; 10] = [
+pub const SYNC_TOKENS: [Token; 12] = [
     Token::FreeFormSlashed,
     Token::FreeFormStarred,
     Token::CvlDocSlashed,
     Token::CvlDocStarred,
+    Token::FileDocSlashed,
+    Token::FileDocStarred,
     Token::Ghost,
     Token::Definition,
     Token::Rule,
@@ -19,6 +21,31 @@ pub const SYNC_TOKENS: [Token; 10] = [
     Token::Function,
 ];
 
+/// tokens that end the header of an unrecognized declaration
+pub const UNKNOWN_HEADER_ENDERS: [Token; 21] = [
+    Token::CodeBlock,
+    Token::CurlyOpen,
+    Token::CurlyClose,
+    Token::Semicolon,
+    Token::FreeFormSlashed,
+    Token::FreeFormStarred,
+    Token::CvlDocSlashed,
+    Token::CvlDocStarred,
+    Token::FileDocSlashed,
+    Token::FileDocStarred,
+    Token::Ghost,
+    Token::Persistent,
+    Token::Definition,
+    Token::Rule,
+    Token::Invariant,
+    Token::Methods,
+    Token::Function,
+    Token::Hook,
+    Token::Import,
+    Token::Use,
+    Token::Using,
+];
+
 pub(super) fn newline<'src>() -> impl Parser<char, &'src str, Error = Simple<char>> {
     static NEWLINE: &[&str; 2] = &["\r\n", "\n"];
     let newline_parsers = NEWLINE.map(just);
@@ -95,12 +122,63 @@ pub(super) fn call() -> impl Parser<Token, String, Error = Simple<Token>> {
         .map(|(lhs, rhs)| format!("{lhs}.{rhs}"))
 }
 
-pub(super) fn single_expr() -> impl Parser<Token, (), Error = Simple<Token>> {
+/// an expression, followed by `identifier "string"` attributes.
+/// the span is that of the expression, without the attributes.
+pub(super) fn expr_with_attributes(
+) -> impl Parser<Token, (Span, Vec<(String, String)>), Error = Simple<Token>> {
     // this is a massive over-approximation of an expression,
     // but (assuming correct code) it's good enough for invariants as of CVL2
-    let expression_enders = [Token::Semicolon, Token::CurlyOpen];
+    let expression_enders = [Token::Semicolon, Token::CodeBlock, Token::CurlyOpen];
+    let item = attribute()
+        .map(Some)
+        .or(none_of(expression_enders).to(None))
+        .map_with_span(|attribute, span| (attribute, span));
+
+    item.repeated().at_least(1).try_map(|items, span| {
+        let expr_len = items
+            .iter()
+            .rposition(|(attribute, _)| attribute.is_none())
+            .map(|last| last + 1)
+            .ok_or_else(|| Simple::custom(span, "expected an expression"))?;
+
+        let expr_span = items[0].1.start..items[expr_len - 1].1.end;
+        let attributes = items
+            .into_iter()
+            .skip(expr_len)
+            .filter_map(|(attribute, _)| attribute)
+            .collect();
+
+        Ok((expr_span, attributes))
+    })
+}
+
+/// an `identifier "string"` pair in a declaration header, such as `description "..."`.
+/// escaped characters in the string are unescaped.
+pub(super) fn attribute() -> impl Parser<Token, (String, String), Error = Simple<Token>> {
+    let name = select! {
+        Token::Ident(name) => name,
+        Token::Description => "description".to_string(),
+    };
+    let value = string().map(|value| unescape(&value));
+
+    name.then(value).labelled("attribute")
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some(escaped @ ('"' | '\\'))) => {
+                unescaped.push(escaped);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
 
-    none_of(expression_enders).repeated().at_least(1).ignored()
+    unescaped
 }
 
 pub(super) fn unnamed_param_list() -> impl Parser<Token, Vec<String>, Error = Simple<Token>> {
@@ -125,13 +203,20 @@ pub(super) fn num() -> impl Parser<Token, String, Error = Simple<Token>> {
 }
 
 pub(super) fn code_block() -> impl Parser<Token, Span, Error = Simple<Token>> {
-    balanced(Token::CurlyOpen, Token::CurlyClose).map_with_span(|_, span| span)
+    just(Token::CodeBlock).map_with_span(|_, span| span)
 }
 
 pub(super) fn optional_code_block() -> impl Parser<Token, Option<Span>, Error = Simple<Token>> {
     choice((code_block().map(Some), semicolon_ender()))
 }
 
+/// like [optional_code_block], also accepting a redundant semicolon after the block,
+/// which is included in the span of the declaration
+pub(super) fn axioms_block() -> impl Parser<Token, Option<Span>, Error = Simple<Token>> {
+    let block = code_block().then_ignore(just(Token::Semicolon).or_not());
+    choice((block.map(Some), semicolon_ender()))
+}
+
 pub(super) fn filtered_block() -> impl Parser<Token, Span, Error = Simple<Token>> {
     just(Token::Filtered).ignore_then(code_block())
 }
@@ -143,3 +228,13 @@ pub(super) fn semicolon_ender() -> impl Parser<Token, Option<Span>, Error = Simp
 pub(super) fn returns_type() -> impl Parser<Token, String, Error = Simple<Token>> {
     just(Token::Returns).ignore_then(ty())
 }
+
+/// a single return type, or a parenthesized list of them
+pub(super) fn returns_type_list() -> impl Parser<Token, Vec<String>, Error = Simple<Token>> {
+    let parenthesized = ty()
+        .separated_by(just(Token::Comma))
+        .at_least(1)
+        .delimited_by(just(Token::RoundOpen), just(Token::RoundClose));
+
+    just(Token::Returns).ignore_then(parenthesized.or(ty().map(|ty| vec![ty])))
+}
@@ -3,18 +3,41 @@ use crate::parse::types::Token;
 use crate::util::Span;
 use chumsky::prelude::*;
 
-pub fn cvl_lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> {
-    let cvldoc_slashed_line = just("///")
+/// consecutive `///` lines, as written
+pub(super) fn cvldoc_slashed() -> impl Parser<char, String, Error = Simple<char>> {
+    let line = just("///")
         .then_ignore(none_of('/').rewind())
-        .then(take_until(newline_or_end()));
-    let cvldoc_slashed = cvldoc_slashed_line
+        .then(take_until(newline_or_end()))
+        .map(|(start, (content, end))| {
+            let content: String = content.into_iter().collect();
+            format!("{start}{content}{end}")
+        });
+
+    line.repeated().at_least(1).map(|lines| lines.concat())
+}
+
+/// a `/** */` comment, as written
+pub(super) fn cvldoc_starred() -> impl Parser<char, String, Error = Simple<char>> {
+    just("/**")
+        .then_ignore(none_of("*/").rewind())
+        .then(take_until(just("*/")))
+        .map(|(start, (content, end))| {
+            let content: String = content.into_iter().collect();
+            format!("{start}{content}{end}")
+        })
+}
+
+pub fn cvl_lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> {
+    let cvldoc_slashed = cvldoc_slashed().to(Token::CvlDocSlashed);
+    let cvldoc_starred = cvldoc_starred().to(Token::CvlDocStarred);
+    let filedoc_slashed_line = just("//!").then(take_until(newline_or_end()));
+    let filedoc_slashed = filedoc_slashed_line
         .repeated()
         .at_least(1)
-        .to(Token::CvlDocSlashed);
-    let cvldoc_starred = just("/**")
-        .then_ignore(none_of("*/").rewind())
+        .to(Token::FileDocSlashed);
+    let filedoc_starred = just("/*!")
         .then(take_until(just("*/")))
-        .to(Token::CvlDocStarred);
+        .to(Token::FileDocStarred);
     let freeform_slashed_line = just("////").then(take_until(newline_or_end()));
     let freeform_slashed = freeform_slashed_line
         .repeated()
@@ -89,12 +112,19 @@ pub fn cvl_lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>
         choice((decimal, hex)).map(Token::Number)
     };
 
-    let string = none_of('"')
-        .repeated()
-        .at_least(1)
-        .collect()
-        .map(Token::String)
-        .delimited_by(just('"'), just('"'));
+    // escapes are kept as written
+    let string = {
+        let escaped = just('\\').chain(any());
+        let plain = none_of("\\\"").map(|c| vec![c]);
+
+        escaped
+            .or(plain)
+            .repeated()
+            .flatten()
+            .collect()
+            .map(Token::String)
+            .delimited_by(just('"'), just('"'))
+    };
 
     let keyword_or_ident = text::ident().map(|ident: String| match ident.as_str() {
         "ghost" => Token::Ghost,
@@ -149,6 +179,8 @@ pub fn cvl_lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>
         freeform_slashed,
         freeform_starred_alternative,
         freeform_starred,
+        filedoc_slashed,
+        filedoc_starred,
         comment,
         num,
         sigil,
@@ -160,3 +192,176 @@ pub fn cvl_lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>
     .padded()
     .repeated()
 }
+
+/// lexes `src` like [cvl_lexer], except that each balanced `{...}` block is captured
+/// as a single [Token::CodeBlock], instead of being lexed token by token.
+/// blocks are found in a single linear pass, skipping braces within strings and comments.
+/// an unbalanced `{` is lexed as usual, as is everything following it.
+pub fn lex_with_blocks(src: &str) -> Result<Vec<(Token, Span)>, Vec<Simple<char>>> {
+    let chars: Vec<(usize, char)> = src.char_indices().collect();
+    let byte_at = |i: usize| chars.get(i).map(|&(byte, _)| byte).unwrap_or(src.len());
+
+    let lexer = cvl_lexer();
+    let lex_segment = |start: usize, end: usize| {
+        let segment = lexer.parse(&src[byte_at(start)..byte_at(end)])?;
+        let shifted = segment
+            .into_iter()
+            .map(move |(token, span)| (token, span.start + start..span.end + start));
+        Ok::<_, Vec<Simple<char>>>(shifted)
+    };
+
+    let mut lexed = Vec::new();
+
+    let mut segment_start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        if let Some(after) = skip_comment_or_string(&chars, i) {
+            i = after;
+        } else if chars[i].1 == '{' {
+            let Some(close) = matching_close(&chars, i) else {
+                break;
+            };
+            lexed.extend(lex_segment(segment_start, i)?);
+            lexed.push((Token::CodeBlock, i..close + 1));
+            segment_start = close + 1;
+            i = close + 1;
+        } else {
+            i += 1;
+        }
+    }
+    lexed.extend(lex_segment(segment_start, chars.len())?);
+
+    Ok(lexed)
+}
+
+/// the index of the `}` closing the block opened at `open`, if there is one
+fn matching_close(chars: &[(usize, char)], open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut i = open;
+
+    while i < chars.len() {
+        if let Some(after) = skip_comment_or_string(chars, i) {
+            i = after;
+            continue;
+        }
+        match chars[i].1 {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/// if a comment or a string starts at `i`, returns the index following its end.
+/// a quote escaped with a backslash does not end a string.
+/// comments are ended the same way as in [cvl_lexer]: `/**`, `/*!` and `/***` comments end at the first `*/`,
+/// while other `/*` comments may be nested.
+pub(super) fn skip_comment_or_string(chars: &[(usize, char)], i: usize) -> Option<usize> {
+    let starts_with = |at: usize, pattern: &str| {
+        pattern
+            .chars()
+            .enumerate()
+            .all(|(offset, ch)| chars.get(at + offset).map(|&(_, c)| c) == Some(ch))
+    };
+    let find_from =
+        |from: usize, pattern: &str| (from..chars.len()).find(|&at| starts_with(at, pattern));
+
+    if starts_with(i, "//") {
+        let end = find_from(i, "\n").unwrap_or(chars.len());
+        Some(end)
+    } else if starts_with(i, "/**") || starts_with(i, "/*!") {
+        find_from(i + 2, "*/").map(|end| end + 2)
+    } else if starts_with(i, "/*") {
+        let mut depth = 0usize;
+        let mut at = i;
+        while at < chars.len() {
+            if starts_with(at, "/*") {
+                depth += 1;
+                at += 2;
+            } else if starts_with(at, "*/") {
+                depth -= 1;
+                at += 2;
+                if depth == 0 {
+                    return Some(at);
+                }
+            } else {
+                at += 1;
+            }
+        }
+        None
+    } else if starts_with(i, "\"") {
+        let mut at = i + 1;
+        while at < chars.len() {
+            match chars[at].1 {
+                '\\' => at += 2,
+                '"' => return Some(at + 1),
+                _ => at += 1,
+            }
+        }
+        None
+    } else {
+        None
+    }
+}
+
+/// byte offsets in `src` between top-level declarations: the start and end of the source,
+/// and the position following each top-level `;`, and each top-level block which is not
+/// followed by another block, as the filters of a rule are followed by its body.
+/// braces and semicolons within strings and comments are skipped, as in [lex_with_blocks].
+pub fn declaration_boundaries(src: &str) -> Vec<usize> {
+    let chars: Vec<(usize, char)> = src.char_indices().collect();
+    let byte_at = |i: usize| chars.get(i).map(|&(byte, _)| byte).unwrap_or(src.len());
+
+    // the next character which is not whitespace, or part of a comment
+    let next_significant = |mut i: usize| {
+        while i < chars.len() {
+            if chars[i].1.is_whitespace() {
+                i += 1;
+            } else if let Some(after) =
+                skip_comment_or_string(&chars, i).filter(|_| chars[i].1 == '/')
+            {
+                i = after;
+            } else {
+                return Some(chars[i].1);
+            }
+        }
+        None
+    };
+
+    let mut boundaries = vec![0];
+    let mut i = 0;
+    while i < chars.len() {
+        if let Some(after) = skip_comment_or_string(&chars, i) {
+            i = after;
+            continue;
+        }
+        match chars[i].1 {
+            ';' => {
+                i += 1;
+                boundaries.push(byte_at(i));
+            }
+            '{' => {
+                let Some(close) = matching_close(&chars, i) else {
+                    break;
+                };
+                i = close + 1;
+                if next_significant(i) != Some('{') {
+                    boundaries.push(byte_at(i));
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    boundaries.push(src.len());
+
+    boundaries.dedup();
+    boundaries
+}
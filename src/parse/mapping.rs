@@ -0,0 +1,78 @@
+//! parsing of the types of ghost mappings, see [MappingType].
+
+use crate::{MappingType, Param};
+
+/// the keys of `ty` from the outermost mapping to the innermost, and the type of the values.
+/// returns `None` if `ty` is not a mapping.
+pub(crate) fn parse_mapping(ty: &str) -> Option<MappingType> {
+    let ty = normalize_type(ty);
+    let mut keys = Vec::new();
+    let mut rest = ty.as_str();
+
+    while let Some(inner) = rest
+        .strip_prefix("mapping(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let (key, value) = split_top_level_arrow(inner)?;
+        let (key_ty, key_name) = split_name(key);
+        if key_ty.is_empty() {
+            return None;
+        }
+        keys.push(Param::new(key_ty, key_name.unwrap_or_default()));
+        rest = split_name(value).0;
+    }
+
+    if keys.is_empty() || rest.is_empty() {
+        return None;
+    }
+    Some(MappingType {
+        keys,
+        value: rest.to_string(),
+    })
+}
+
+/// removes whitespace from `ty`, except for a single space between two words,
+/// as between a type and its name
+pub(crate) fn normalize_type(ty: &str) -> String {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+    let mut normalized = String::with_capacity(ty.len());
+    let mut after_whitespace = false;
+
+    for c in ty.trim().chars() {
+        if c.is_whitespace() {
+            after_whitespace = true;
+            continue;
+        }
+        if after_whitespace && normalized.ends_with(is_word_char) && is_word_char(c) {
+            normalized.push(' ');
+        }
+        after_whitespace = false;
+        normalized.push(c);
+    }
+
+    normalized
+}
+
+/// splits a normalized `ty name` into the type and the name, if there is a name
+fn split_name(ty: &str) -> (&str, Option<&str>) {
+    match ty.rsplit_once(' ') {
+        Some((ty, name)) if name.chars().all(|c| c.is_alphanumeric() || c == '_') => {
+            (ty, Some(name))
+        }
+        _ => (ty, None),
+    }
+}
+
+/// splits at the first `=>` outside of parentheses and brackets
+fn split_top_level_arrow(s: &str) -> Option<(&str, &str)> {
+    let mut depth = 0usize;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.checked_sub(1)?,
+            '=' if depth == 0 && s[i..].starts_with("=>") => return Some((&s[..i], &s[i + 2..])),
+            _ => (),
+        }
+    }
+    None
+}
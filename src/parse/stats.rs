@@ -0,0 +1,90 @@
+//! opt-in statistics of a parse, for logging and finding pathological specs.
+
+use super::builder::Builder;
+use crate::{Ast, CvlElement};
+use eyre::Result;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ParseStats {
+    /// time spent parsing, not including the computation of these statistics
+    pub duration: Duration,
+    /// length of the source, in bytes
+    pub bytes: usize,
+    /// number of elements
+    pub docs: usize,
+    /// number of freeform comments
+    pub freeform: usize,
+    /// number of documented elements, not including freeform comments
+    pub documentation: usize,
+    /// number of diagnostics of all elements. always zero without the `lsp` feature.
+    pub diagnostics: usize,
+    /// length of the longest documentation or freeform comment, in characters
+    pub largest_block_len: usize,
+}
+
+impl ParseStats {
+    fn of(src: &str, elements: &[CvlElement], duration: Duration) -> ParseStats {
+        let freeform = elements
+            .iter()
+            .filter(|element| element.is_freeform())
+            .count();
+        let documentation = elements
+            .iter()
+            .filter(|element| element.is_documentation())
+            .filter(|element| !element.doc.is_empty())
+            .count();
+
+        let largest_block_len = elements
+            .iter()
+            .filter_map(|element| match element.ast {
+                Ast::FreeFormComment { .. } => Some(element.element_span.len()),
+                _ => element.doc_span.as_ref().map(|span| span.len()),
+            })
+            .max()
+            .unwrap_or_default();
+
+        ParseStats {
+            duration,
+            bytes: src.len(),
+            docs: elements.len(),
+            freeform,
+            documentation,
+            diagnostics: count_diagnostics(src, elements),
+            largest_block_len,
+        }
+    }
+}
+
+#[cfg(feature = "lsp")]
+fn count_diagnostics(src: &str, elements: &[CvlElement]) -> usize {
+    use crate::util::RangeConverter;
+    use ropey::Rope;
+
+    let converter = RangeConverter::new(Rope::from_str(src));
+
+    elements
+        .iter()
+        .map(|element| element.enumerate_diagnostics(converter.clone()).len())
+        .sum()
+}
+
+#[cfg(not(feature = "lsp"))]
+fn count_diagnostics(_src: &str, _elements: &[CvlElement]) -> usize {
+    0
+}
+
+impl<'src> Builder<'src> {
+    /// like [Builder::build], also returning statistics of the parse.
+    pub fn build_with_stats(self) -> Result<(Vec<CvlElement>, ParseStats)> {
+        let src = self.src();
+
+        let start = Instant::now();
+        let elements = self.build()?;
+        let duration = start.elapsed();
+
+        let stats = ParseStats::of(src, &elements, duration);
+        Ok((elements, stats))
+    }
+}
@@ -1,3 +1,7 @@
+use crate::util::SourceMap;
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Clone, Debug)]
 pub enum Terminator {
@@ -23,6 +27,33 @@ pub struct TerminatedStr<'a> {
     ter: Terminator,
 }
 
+impl<'a> TerminatedStr<'a> {
+    /// splits the content before each of `split_points`, which are byte offsets into the content.
+    /// only the last part keeps the terminator.
+    pub(super) fn split_at_points(self, split_points: &[usize]) -> Vec<TerminatedStr<'a>> {
+        let mut parts = Vec::new();
+        let mut rest = self.content;
+        let mut consumed = 0;
+
+        for &point in split_points {
+            let (before, after) = rest.split_at(point - consumed);
+            parts.push(TerminatedStr {
+                content: before.trim_end(),
+                ter: Terminator::EOF,
+            });
+            rest = after;
+            consumed = point;
+        }
+
+        parts.push(TerminatedStr {
+            content: rest,
+            ter: self.ter,
+        });
+
+        parts
+    }
+}
+
 impl<'a> From<&'a str> for TerminatedStr<'a> {
     fn from(line: &'a str) -> Self {
         use Terminator::*;
@@ -40,11 +71,9 @@ impl<'a> From<&'a str> for TerminatedStr<'a> {
     }
 }
 
-impl ToString for TerminatedStr<'_> {
-    fn to_string(&self) -> String {
-        let line_chars = self.content.chars();
-        let ter_chars = self.ter.as_str().chars();
-        line_chars.chain(ter_chars).collect()
+impl Display for TerminatedStr<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.content, self.ter.as_str())
     }
 }
 
@@ -67,3 +96,37 @@ impl<'a> FromIterator<TerminatedStr<'a>> for String {
         joined
     }
 }
+
+/// joins the lines like [String::from_iter], but borrows the content
+/// if it is all on a single line, after ignoring leading empty lines and trailing blank lines.
+pub(super) fn join_lines<'a>(lines: Vec<TerminatedStr<'a>>) -> Cow<'a, str> {
+    let is_blank = |line: &TerminatedStr| {
+        line.content
+            .trim_start_matches(|c: char| c.is_ascii_whitespace())
+            .is_empty()
+    };
+
+    let mut rest = lines.iter().skip_while(|line| line.content.is_empty());
+    match rest.next() {
+        Some(first) if !is_blank(first) && rest.all(is_blank) => Cow::Borrowed(
+            first
+                .content
+                .trim_end_matches(|c: char| c.is_ascii_whitespace()),
+        ),
+        _ => Cow::Owned(String::from_iter(lines)),
+    }
+}
+
+/// the [SourceMap] of the description [join_lines] makes of `lines`, which are slices of `src`
+pub(super) fn source_map_of(lines: &[TerminatedStr], src: &str) -> SourceMap {
+    let mut map = SourceMap::default();
+    let mut cleaned = 0;
+
+    for line in lines.iter().skip_while(|line| line.content.is_empty()) {
+        let original = line.content.as_ptr() as usize - src.as_ptr() as usize;
+        map.push(cleaned, original, line.content.len());
+        cleaned += line.content.len() + line.ter.as_str().len();
+    }
+
+    map
+}
@@ -1,12 +1,13 @@
 mod cvl2;
 
 use super::builder::Builder;
-use super::Token;
+use super::{extract_raw_comment, parse_range, validate_formula, Token};
+use crate::coverage::MethodsCoverage;
+use crate::util::{diff_docs, slugify_all};
 use crate::CvlElement;
-use crate::{Ast, Param, TagKind};
+use crate::{Ast, Param, RawMismatch, TagKind};
 use assert_matches::assert_matches;
-use color_eyre::eyre::{bail, Context};
-use color_eyre::Report;
+use eyre::{bail, Context, Report};
 use indoc::indoc;
 use itertools::Itertools;
 use std::iter::Iterator;
@@ -58,33 +59,60 @@ fn free_form_comments() {
     assert_eq!(
         parsed[0].ast,
         Ast::FreeFormComment {
-            text: "# Section example".to_string()
+            text: "# Section example".to_string(),
+            decorative: false,
+            following_element: None,
+            truncated: false,
         }
     );
     assert_eq!(
         parsed[1].ast,
         Ast::FreeFormComment {
-            text: "# Centered example".to_string()
+            text: "# Centered example".to_string(),
+            decorative: false,
+            following_element: None,
+            truncated: false,
         },
     );
     assert_eq!(
         parsed[2].ast,
         Ast::FreeFormComment {
-            text: "# Thick centered example".to_string()
+            text: "# Thick centered example".to_string(),
+            decorative: false,
+            following_element: None,
+            truncated: false,
         },
     );
     assert_eq!(
         parsed[3].ast,
         Ast::FreeFormComment {
-            text: "# Thick example".to_string()
+            text: "# Thick example".to_string(),
+            decorative: false,
+            following_element: None,
+            truncated: false,
         },
     );
     assert_eq!(
         parsed[4].ast,
         Ast::FreeFormComment {
-            text: "# Multiline example\nAdditional detail\nand more info".to_string()
+            text: "# Multiline example\nAdditional detail\nand more info".to_string(),
+            decorative: false,
+            following_element: None,
+            truncated: false,
         },
     );
+
+    let source_texts = parsed.iter().map(CvlElement::source_text).collect_vec();
+    assert_eq!(
+        source_texts,
+        [
+            "/**** # Section example *************************/",
+            "/*************** # Centered example **************/",
+            "/********************************/\n/*** # Thick centered example */\n/********************************/",
+            "/////////////////////////////////////////\n//// # Thick example                   ////\n/////////////////////////////////////////",
+            "/***\n * # Multiline example\n * Additional detail\n * and more info\n */",
+        ]
+    );
 }
 
 // fn range_from((s_line, s_character): (u32, u32), (e_line, e_character): (u32, u32)) -> Range {
@@ -389,10 +417,14 @@ fn multiline_slashed_freeform_concatenates_to_a_single_comment() {
     let element = &parsed[0];
 
     let expected = "## Verification of ERC1155Burnable\n\n`ERC1155Burnable` extends the `ERC1155` functionality by wrapping the internal\nmethods `_burn` and `_burnBatch` in the public methods `burn` and `burnBatch`,\nadding a requirement that the caller of either method be the account holding\nthe tokens or approved to act on that account's behalf.\n\n### Assumptions and Simplifications\n\n- No changes made using the harness\n\n### Properties";
-    let Ast::FreeFormComment { text } = &element.ast else {
+    let Ast::FreeFormComment { text, .. } = &element.ast else {
         panic!("should have been parsed as documentation");
     };
     assert_eq!(text, expected);
+    assert!(element
+        .source_text()
+        .starts_with("//// ## Verification of ERC1155Burnable\n////\n"));
+    assert!(element.source_text().ends_with("//// ### Properties"));
 }
 
 #[test]
@@ -417,7 +449,7 @@ fn crlf() {
 
     let parsed = Builder::new(&src_with_crlf_encoding).build().unwrap();
 
-    let Ast::FreeFormComment { text } = &parsed[0].ast else {
+    let Ast::FreeFormComment { text, .. } = &parsed[0].ast else {
         panic!()
     };
 
@@ -425,6 +457,56 @@ fn crlf() {
         text,
         "# testing the natspec parser.\r\nThis is made by Gabriel  it contains all\r\nknown tags"
     );
+    assert_eq!(
+        parsed[0].source_text(),
+        "/***\r\n * # testing the natspec parser.\r\n * This is made by Gabriel  it contains all\r\n * known tags\r\n ***/"
+    );
+}
+
+#[test]
+fn byte_order_mark() {
+    let src = indoc! {"
+        /// @notice documented
+        rule r() { assert true; }
+    "};
+    let with_bom = format!("\u{feff}{src}");
+
+    let parsed = Builder::new(&with_bom).build().unwrap();
+    assert_eq!(parsed, Builder::new(src).build().unwrap());
+    assert_eq!(parsed[0].doc_span, Some(0..22));
+    assert!(parsed[0]
+        .source_text()
+        .starts_with("/// @notice documented"));
+
+    let stripped = crate::strip::remove_docs(&with_bom).unwrap();
+    assert_eq!(
+        stripped,
+        format!("\u{feff}{}", crate::strip::remove_docs(src).unwrap())
+    );
+
+    let in_range = parse_range(&with_bom, 4..5).unwrap();
+    assert_eq!(in_range[0].source_text(), parsed[0].source_text());
+
+    let directives = super::directives("\u{feff}// certora: --optimistic_loop\nrule r() { }");
+    assert_eq!(
+        directives,
+        [(1..30, "certora: --optimistic_loop".to_string())]
+    );
+
+    let file_parser = super::file_parser::FileParser::new(with_bom.as_bytes());
+    assert_eq!(file_parser.collect_vec(), parsed);
+
+    let mut workspace = crate::workspace::Workspace::new(|_: &str| None);
+    workspace.load("r.spec", &with_bom).unwrap();
+    assert_eq!(workspace.files()[0].src, src);
+    assert_eq!(workspace.docs(), parsed);
+
+    #[cfg(feature = "cache")]
+    {
+        let cache = crate::cache::ParseCache::new(1);
+        assert_eq!(*cache.parse("r.spec", &with_bom).unwrap(), parsed);
+        assert!(cache.contains("r.spec"));
+    }
 }
 
 #[test]
@@ -461,10 +543,14 @@ fn freeform_stars_without_text() {
 
     let parsed = Builder::new(src).build().unwrap();
 
-    let Ast::FreeFormComment { text } = &parsed[0].ast else {
+    let Ast::FreeFormComment { text, .. } = &parsed[0].ast else {
         panic!()
     };
     assert!(text.is_empty());
+    assert_eq!(
+        parsed[0].source_text(),
+        "/******************************************************************************/"
+    );
 }
 
 #[test]
@@ -513,7 +599,7 @@ fn span_contains_both_doc_and_associated_element() {
         }
     "#};
 
-    assert_eq!(parse_exactly_one(src).unwrap().raw(), src.trim());
+    assert_eq!(parse_exactly_one(src).unwrap().source_text(), src.trim());
 }
 
 #[test]
@@ -530,7 +616,10 @@ fn raw_capture_for_multi_line_doc() {
         }
     "#};
 
-    assert!(parse_exactly_one(src).unwrap().raw().starts_with("/**"));
+    assert!(parse_exactly_one(src)
+        .unwrap()
+        .source_text()
+        .starts_with("/**"));
 }
 
 #[test]
@@ -581,21 +670,21 @@ fn variable_char_lengths() {
     let parsed = Builder::new(src).build().unwrap();
     assert_eq!(parsed.len(), 3);
 
-    let Ast::FreeFormComment { text } = &parsed[0].ast else {
+    let Ast::FreeFormComment { text, .. } = &parsed[0].ast else {
         panic!();
     };
     assert_eq!(text, "🔥🔥🔥💯 frfr");
 
-    let Ast::FreeFormComment { text } = &parsed[2].ast else {
+    let Ast::FreeFormComment { text, .. } = &parsed[2].ast else {
         panic!();
     };
     assert_eq!(text, "Text");
 
-    assert_eq!(parsed[0].raw(), "/***\n🔥🔥🔥💯 frfr\n*/");
-    assert_eq!(parsed[1].raw(), "methods {\n    𝇇_𝇇\n}");
+    assert_eq!(parsed[0].source_text(), "/***\n🔥🔥🔥💯 frfr\n*/");
+    assert_eq!(parsed[1].source_text(), "methods {\n    𝇇_𝇇\n}");
     assert_eq!(
-        parsed[2].raw(),
-        "//////////////\n//// Text ////\n//////////////\n"
+        parsed[2].source_text(),
+        "//////////////\n//// Text ////\n//////////////"
     );
 }
 
@@ -605,3 +694,426 @@ fn repeated_iterations_caused_by_improper_recovery() {
 
     let _ = Builder::new(src).build();
 }
+
+#[test]
+fn count_by_kind() {
+    let src = indoc! {"
+        //! @title the file
+
+        //// # Section
+
+        /// @notice documented
+        rule foo() { }
+
+        rule undocumented() { }
+
+        /// @notice documented ghost
+        ghost mapping(uint => uint) g;
+
+        /// @notice another documented ghost
+        persistent ghost bool b;
+
+        /// @notice documents nothing
+    "};
+
+    let parsed = Builder::new(src).build().unwrap();
+    let counts = CvlElement::count_by_kind(&parsed);
+
+    assert_eq!(counts.len(), 5);
+    assert_eq!(counts["file"], 1);
+    assert_eq!(counts["freeform"], 1);
+    assert_eq!(counts["rule"], 1);
+    assert_eq!(counts["ghost"], 2);
+    assert_eq!(counts["detached"], 1);
+}
+
+#[test]
+fn diff_docs_by_start_position() {
+    let old_src = indoc! {"
+        /// @notice unchanged
+        rule unchanged() { }
+
+        /// @notice will be edited
+        rule edited() { }
+
+        /// @notice will be removed
+        rule removed() { }
+    "};
+    let new_src = indoc! {"
+        /// @notice unchanged
+        rule unchanged() { }
+
+        /// @notice has been edited
+        rule edited() { }
+
+        rule added() { }
+    "};
+
+    let old = Builder::new(old_src).build().unwrap();
+    let new = Builder::new(new_src).build().unwrap();
+    let diff = diff_docs(&old, &new);
+
+    let [(old_edited, new_edited)] = diff.changed.as_slice() else {
+        panic!("expected exactly one changed element")
+    };
+    assert_eq!(old_edited.doc[0].description, "will be edited");
+    assert_eq!(new_edited.doc[0].description, "has been edited");
+
+    let added = diff.added.iter().exactly_one().unwrap();
+    assert_eq!(added.ast.name(), Some("added"));
+
+    let removed = diff.removed.iter().exactly_one().unwrap();
+    assert_eq!(removed.ast.name(), Some("removed"));
+}
+
+#[test]
+fn to_cvl_comment_is_canonical_and_idempotent() {
+    let src = indoc! {"
+        /// some notice
+        ///    @param x the first
+        /// line of x
+        ///
+        /// still x
+        /// @return nothing
+        function foo(uint x) { }
+    "};
+    let expected = indoc! {"
+        /**
+         * @notice some notice
+         * @param x the first
+         * line of x
+         *
+         * still x
+         * @return nothing
+         */"};
+
+    let comment = parse_exactly_one(src).unwrap().to_cvl_comment();
+    assert_eq!(comment, expected);
+
+    let reparsed = parse_exactly_one(&format!("{comment}\nfunction foo(uint x) {{ }}")).unwrap();
+    assert_eq!(reparsed.to_cvl_comment(), comment);
+
+    let freeform_src = indoc! {"
+        /***
+         * # Section
+         *
+         * with details
+         */
+    "};
+    let comment = parse_exactly_one(freeform_src).unwrap().to_cvl_comment();
+    assert_eq!(comment, "//// # Section\n////\n//// with details");
+    assert_eq!(
+        parse_exactly_one(&comment).unwrap().to_cvl_comment(),
+        comment
+    );
+
+    // tags in the middle of a line are moved to lines of their own
+    let inline_src = indoc! {"
+        /** @notice text @dev details
+         *  on two lines @param x the value */
+        function foo(uint x) { }
+    "};
+    let expected = indoc! {"
+        /**
+         * @notice text
+         * @dev details
+         * on two lines
+         * @param x the value
+         */"};
+    let comment = parse_exactly_one(inline_src).unwrap().to_cvl_comment();
+    assert_eq!(comment, expected);
+    let reparsed = parse_exactly_one(&format!("{comment}\nfunction foo(uint x) {{ }}")).unwrap();
+    assert_eq!(reparsed.to_cvl_comment(), comment);
+
+    let undocumented = parse_exactly_one("rule foo() { }").unwrap();
+    assert!(undocumented.to_cvl_comment().is_empty());
+}
+
+#[test]
+fn slugs() {
+    let src = indoc! {"
+        //// ## Transfer Rules
+
+        rule transferPreservesTotal() { }
+
+        invariant transferPreservesTotal() true;
+
+        rule transferPreservesTotal() { }
+
+        rule transferPreservesTotal_2() { }
+
+        /*** # Über Regeln! */
+
+        methods { }
+    "};
+
+    let parsed = Builder::new(src).build().unwrap();
+    let slugs = slugify_all(&parsed);
+    let slug_of = |i: usize| {
+        slugs
+            .get(&(&parsed[i] as *const CvlElement))
+            .map(String::as_str)
+    };
+
+    assert_eq!(slug_of(0), Some("transfer-rules"));
+    assert_eq!(slug_of(1), Some("rule-transferpreservestotal"));
+    assert_eq!(slug_of(2), Some("invariant-transferpreservestotal"));
+    assert_eq!(slug_of(3), Some("rule-transferpreservestotal-2"));
+    assert_eq!(
+        parsed[4].slug().as_deref(),
+        Some("rule-transferpreservestotal-2")
+    );
+    assert_eq!(slug_of(4), Some("rule-transferpreservestotal-2-2"));
+    assert_eq!(slug_of(5), Some("über-regeln"));
+    assert_eq!(slug_of(6), None);
+}
+
+#[test]
+fn extract_raw_comment_at_offset() {
+    let src = indoc! {"
+        //// # 🔥 Section
+        ////   continued
+
+        /**
+         * @notice a rule
+         */
+        rule foo() { }
+    "};
+
+    let freeform = "//// # 🔥 Section\n////   continued";
+    let doc = "/**\n * @notice a rule\n */";
+    let doc_start = src.find("/**").unwrap();
+
+    assert_eq!(
+        extract_raw_comment(src, 0),
+        Some((freeform.to_string(), 0, freeform.len()))
+    );
+    assert_eq!(
+        extract_raw_comment(src, freeform.len() - 1),
+        Some((freeform.to_string(), 0, freeform.len()))
+    );
+    assert_eq!(extract_raw_comment(src, freeform.len()), None);
+
+    let notice_offset = src.find("@notice").unwrap();
+    assert_eq!(
+        extract_raw_comment(src, notice_offset),
+        Some((doc.to_string(), doc_start, doc_start + doc.len()))
+    );
+
+    let rule_offset = src.find("rule foo").unwrap();
+    assert_eq!(extract_raw_comment(src, rule_offset), None);
+}
+
+#[test]
+fn methods_coverage() {
+    use crate::parse::builder::ParseConfig;
+    use crate::render::{to_html, HtmlOptions};
+
+    let src = indoc! {"
+        methods {
+            /// @notice balance of an account
+            /// in the underlying token
+            function balanceOf(address) external returns (uint) envfree;
+
+            // this is not documentation
+            function _.transfer(address, uint) external => DISPATCHER(true);
+
+            /** price is arbitrary */
+            function price() external returns (uint) => NONDET;
+            function totalSupply() external returns (uint) => NONDET;
+        }
+    "};
+
+    let element = parse_exactly_one(src).unwrap();
+    let coverage = MethodsCoverage::of(&element).unwrap();
+
+    let documented = coverage
+        .documented
+        .iter()
+        .map(|entry| (entry.text.as_str(), entry.doc.as_deref().unwrap()))
+        .collect_vec();
+    assert_eq!(
+        documented,
+        [
+            (
+                "function balanceOf(address) external returns (uint) envfree",
+                "@notice balance of an account\nin the underlying token"
+            ),
+            (
+                "function price() external returns (uint) => NONDET",
+                "price is arbitrary"
+            ),
+        ]
+    );
+
+    let undocumented_summaries = coverage
+        .undocumented_summaries()
+        .map(|entry| entry.text.as_str())
+        .collect_vec();
+    assert_eq!(
+        undocumented_summaries,
+        [
+            "function _.transfer(address, uint) external => DISPATCHER(true)",
+            "function totalSupply() external returns (uint) => NONDET",
+        ]
+    );
+
+    let rule = parse_exactly_one("rule foo() { }").unwrap();
+    assert!(MethodsCoverage::of(&rule).is_none());
+
+    // the coverage is only part of the output when it is recorded
+    assert_matches!(element.ast, Ast::Methods { coverage: None, .. });
+    let recording = ParseConfig {
+        record_methods_coverage: true,
+        ..Default::default()
+    };
+    let recorded = Builder::new(src).with_config(recording).build().unwrap();
+    let Ast::Methods {
+        coverage: Some(recorded_coverage),
+        ..
+    } = &recorded[0].ast
+    else {
+        panic!("expected recorded coverage");
+    };
+    assert_eq!(recorded_coverage, &coverage);
+
+    let opts = HtmlOptions {
+        include_undocumented: true,
+        ..Default::default()
+    };
+    let html = to_html(&recorded, &opts);
+    assert!(html.contains(indoc! {r#"
+        <table class="methods-coverage">
+        <tr><th>Entry</th><th>Documentation</th></tr>
+        <tr><td><code>function balanceOf(address) external returns (uint) envfree</code></td><td>@notice balance of an account
+        in the underlying token</td></tr>
+        "#}));
+    assert!(html.contains(
+        r#"<tr class="undocumented-summary"><td><code>function totalSupply() external returns (uint) =&gt; NONDET</code></td><td></td></tr>"#
+    ));
+    assert!(!to_html(&[element], &opts).contains("<table"));
+}
+
+#[test]
+fn source_text_matches_source() {
+    let src = indoc! {"
+        //// # Section
+        ////
+
+        /// @notice slashed
+        rule foo() { }
+
+        /**
+         * @notice starred
+         */
+        invariant bar() true;
+
+        /*** ends at EOF */"};
+
+    let parsed = Builder::new(src).build().unwrap();
+    assert_eq!(parsed.len(), 4);
+
+    for element in &parsed {
+        assert!(element.verify_against(src).is_ok());
+        assert_eq!(element.source_text(), element.source_text().trim());
+    }
+
+    assert_eq!(parsed[0].source_text(), "//// # Section\n////");
+
+    let edited = src.replace("foo", "baz");
+    assert_matches!(
+        parsed[1].verify_against(&edited),
+        Err(RawMismatch::Differs { expected, .. }) if expected.ends_with("rule baz() { }")
+    );
+    assert_matches!(
+        parsed[3].verify_against("too short"),
+        Err(RawMismatch::OutOfBounds { .. })
+    );
+}
+
+#[test]
+fn parse_range_snaps_to_declaration_boundaries() {
+    let src = indoc! {r#"
+        /// @notice first
+        rule first() { assert true; }
+
+        /**
+         * @notice second
+         * @param x some number
+         */
+        rule second(uint x) filtered { f -> true } {
+            require x > 0; // not a boundary }
+            assert true;
+        }
+
+        /// @notice third
+        definition third() returns bool = true;
+    "#};
+    let full = Builder::new(src).build().unwrap();
+
+    let mid_comment = src.find("@param").unwrap();
+    let parsed = parse_range(src, mid_comment..mid_comment + 1).unwrap();
+    assert_eq!(parsed, [full[1].clone()]);
+    assert_eq!(parsed[0].source_text(), full[1].source_text());
+    assert_eq!(parsed[0].doc[1].source_map(), full[1].doc[1].source_map());
+
+    let mid_rule = src.find("require").unwrap();
+    let after_filters = src.find("{\n    require").unwrap();
+    let parsed = parse_range(src, mid_rule..after_filters + 20).unwrap();
+    assert_eq!(parsed, [full[1].clone()]);
+
+    let until_third = src.find("definition").unwrap() + 1;
+    let parsed = parse_range(src, mid_rule..until_third).unwrap();
+    assert_eq!(parsed, full[1..]);
+
+    assert!(parse_range(src, 0..src.len() + 1).is_err());
+}
+
+#[test]
+fn formula_validation() {
+    let valid = [
+        "balanceOf(a) <= totalSupply()",
+        "forall address a. balanceOf(a) <= totalSupply()",
+        "exists uint256[] xs. xs.length > 0 && (xs[0] == 1 || xs[0] == 2)",
+        "e.msg.sender != 0 => !lastReverted",
+        "x > 0 ? y : z",
+        "hello@withrevert(world)",
+        "f@norevert(e, args) => !lastReverted",
+        "g@dontsummarize(e) == 0",
+        r#"name() == "a \"quoted\" (name""#,
+    ];
+    for formula in valid {
+        assert_eq!(validate_formula(formula), Ok(()), "{formula}");
+    }
+
+    let invalid = [
+        ("", "formula is empty"),
+        ("(a && b", "unclosed `(` at 0"),
+        ("a && b)", "unmatched `)` at 6"),
+        ("f(a]", "`(` at 1 is closed by `]` at 3"),
+        ("a &&", "operator `&&` at 2 is missing its right-hand side"),
+        (
+            "(a ||) && b",
+            "operator `||` at 3 is missing its right-hand side",
+        ),
+        (
+            "forall . x > 0",
+            "`forall` at 0 must be followed by a variable declaration",
+        ),
+        (
+            "forall uint x x > 0",
+            "expected `.` after the variables of `forall` at 0",
+        ),
+        ("exists uint x.", "`exists` at 0 has no body"),
+        ("a # b", "unexpected `#` at 2"),
+        ("f@withrevertx(e)", "unexpected `@` at 1"),
+        (r#"name() == "unterminated"#, "unterminated string at 10"),
+    ];
+    for (formula, message) in invalid {
+        assert_eq!(
+            validate_formula(formula),
+            Err(message.to_string()),
+            "{formula}"
+        );
+    }
+}
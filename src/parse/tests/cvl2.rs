@@ -1,8 +1,12 @@
 use super::*;
+use crate::DocumentationTag;
 use indoc::formatdoc;
 use itertools::Itertools;
 use std::iter;
 
+#[cfg(feature = "lsp")]
+use {crate::util::RangeConverter, lsp_types::NumberOrString, ropey::Rope};
+
 #[rustfmt::skip]
 #[test]
 /// we currently allow any code inside a `methods` block
@@ -19,7 +23,7 @@ fn methods_entries_need_function_and_semicolon() {
         "};
 
         let parsed = parse_exactly_one(&block).unwrap();
-        assert_matches!(parsed.ast, Ast::Methods { block } if block == function_decl);
+        assert_matches!(parsed.ast, Ast::Methods { block, .. } if block == function_decl);
     }
 }
 
@@ -523,7 +527,7 @@ fn unrecognized_tags() {
         /// @illegal this tag does not exist
         /// @dev this tag does exist
         /// @another_illegal this tag does not exist
-        ///      @still_illegal whitespace should be trimmed
+        ///      @still_illegal indentation beyond that of the tag is kept
         /// @formula hello@withrevert(world)
         function foo(int bar) { }
     "};
@@ -541,7 +545,7 @@ fn unrecognized_tags() {
     assert_eq!(tag1.description, "@illegal this tag does not exist");
 
     assert_matches!(tag2.kind, TagKind::Dev);
-    assert_eq!(tag2.description, "this tag does exist\n@another_illegal this tag does not exist\n@still_illegal whitespace should be trimmed");
+    assert_eq!(tag2.description, "this tag does exist\n@another_illegal this tag does not exist\n     @still_illegal indentation beyond that of the tag is kept");
 
     assert_matches!(tag3.kind, TagKind::Formula);
     assert_eq!(tag3.description, "hello@withrevert(world)"); // @withrevert should not parse to a new tag
@@ -574,6 +578,36 @@ fn persistent_ghosts() {
     check_ghost(&parsed[1], "non_pers", false);
 }
 
+#[test]
+fn ghost_initial_value() {
+    let src = indoc! {"
+        /// @notice starts at zero
+        ghost uint256 counter = 0;
+
+        ghost mathint sum {
+            init_state axiom sum == 0;
+        }
+
+        persistent ghost bool flag = f(1, 2) && true;
+        ghost address owner;
+    "};
+
+    let parsed = Builder::new(src).build().unwrap();
+    assert_eq!(parsed.len(), 4);
+
+    assert_eq!(parsed[0].ast.name(), Some("counter"));
+    assert_eq!(parsed[0].ast.initial_value(), Some("0"));
+    assert_eq!(parsed[0].ast.block(), None);
+    assert_eq!(parsed[0].doc.len(), 1);
+
+    assert_eq!(parsed[1].ast.initial_value(), None);
+    assert!(parsed[1].ast.block().is_some());
+
+    assert_eq!(parsed[2].ast.initial_value(), Some("f(1, 2) && true"));
+    assert_eq!(parsed[3].ast.initial_value(), None);
+    assert_eq!(parsed[3].ast.block(), None);
+}
+
 #[test]
 /// this shouldn't trip up the definition end detection:
 /// the semicolon should be detected even if it's not whitespace-separated from the previous token.
@@ -607,3 +641,4093 @@ fn definition_does_not_stop() {
     assert_matches!(&parsed[1].ast, Ast::Definition { definition, ..} if definition == "x+y");
     assert_matches!(&parsed[2].ast, Ast::Invariant { .. });
 }
+
+#[test]
+#[cfg(feature = "lsp")]
+fn invariant_empty_proof_block_is_distinct_from_no_block() {
+    let with_semicolon = "invariant foo() bar();";
+    let with_empty_block = "invariant foo() bar() {}";
+    let with_whitespace_block = "invariant foo() bar() {  \n   }";
+
+    let proof_of = |src| {
+        let parsed = parse_exactly_one(src).unwrap();
+        let Ast::Invariant { proof, .. } = parsed.ast else {
+            panic!()
+        };
+        proof
+    };
+
+    assert_eq!(proof_of(with_semicolon), None);
+    assert_eq!(proof_of(with_empty_block).as_deref(), Some(""));
+    assert_eq!(proof_of(with_whitespace_block).as_deref(), Some(""));
+
+    let has_empty_proof_diagnostic = |src: &str| {
+        let element = parse_exactly_one(src).unwrap();
+        let converter = RangeConverter::new(Rope::from_str(src));
+        let empty_proof_code = NumberOrString::String("empty-proof-block".to_string());
+
+        element
+            .enumerate_diagnostics(converter)
+            .iter()
+            .any(|diag| diag.code.as_ref() == Some(&empty_proof_code))
+    };
+
+    assert!(!has_empty_proof_diagnostic(with_semicolon));
+    assert!(has_empty_proof_diagnostic(with_empty_block));
+    assert!(has_empty_proof_diagnostic(with_whitespace_block));
+    assert!(!has_empty_proof_diagnostic(
+        "invariant foo() bar() { preserved { require true; } }"
+    ));
+}
+
+#[test]
+fn multi_line_definition() {
+    let src = indoc! {"
+        /// @notice whether `a` is listed at `i`
+        definition isListed(address a, uint i) returns bool =
+            0 <= i &&
+            i < shadowLenArray() &&
+                shadowArray(i) == a;
+
+        rule foo() { }
+    "};
+
+    let parsed = Builder::new(src).build().unwrap();
+    assert_eq!(parsed.len(), 2);
+
+    let Ast::Definition { definition, .. } = &parsed[0].ast else {
+        panic!()
+    };
+    assert_eq!(
+        definition,
+        "0 <= i &&\n    i < shadowLenArray() &&\n        shadowArray(i) == a"
+    );
+    assert_matches!(parsed[1].ast, Ast::Rule { .. });
+}
+
+#[test]
+fn macro_definition() {
+    let src = indoc! {"
+        /// @notice the balance of `a`, capped at `cap`
+        /// @param a the account
+        /// @param cap the cap
+        define macro CAPPED_BALANCE(address a, uint cap) =
+            balanceOf(a) > cap ? cap : balanceOf(a);
+
+        rule foo() { }
+    "};
+
+    let parsed = Builder::new(src).build().unwrap();
+    assert_eq!(parsed.len(), 2);
+
+    let element = &parsed[0];
+    assert_eq!(element.ast.to_string(), "macro");
+    assert_eq!(element.ast.name(), Some("CAPPED_BALANCE"));
+    assert_eq!(
+        element.ast.params().unwrap(),
+        [
+            Param::new("address".to_string(), "a".to_string()),
+            Param::new("uint".to_string(), "cap".to_string())
+        ]
+    );
+    assert_eq!(
+        element.ast.macro_body(),
+        Some("balanceOf(a) > cap ? cap : balanceOf(a)")
+    );
+    assert_eq!(element.doc.len(), 3);
+    assert_matches!(parsed[1].ast, Ast::Rule { .. });
+}
+
+#[test]
+fn function_return_types() {
+    let returns_of = |src| parse_exactly_one(src).unwrap().ast;
+
+    let none = returns_of("function f(address a) { }");
+    assert_eq!(none.returns(), None);
+    assert!(none.return_types().is_empty());
+
+    let single = returns_of("function f(address a) returns uint256 { return 1; }");
+    assert_eq!(single.returns(), Some("uint256"));
+    assert_eq!(single.return_types(), ["uint256"]);
+
+    let parenthesized = returns_of(
+        "function balanceOfHarness(address a) returns (uint256) { return balanceOf(a); }",
+    );
+    assert_eq!(parenthesized.returns(), Some("uint256"));
+    assert_eq!(parenthesized.return_types(), ["uint256"]);
+
+    let two = returns_of("function f() returns (uint256, bool) { return (1, true); }");
+    assert_eq!(two.returns(), Some("uint256, bool"));
+    assert_eq!(two.return_types(), ["uint256", "bool"]);
+
+    let Ast::Function { return_types, .. } = &two else {
+        panic!("expected a function");
+    };
+    assert_eq!(return_types, &["uint256", "bool"]);
+}
+
+#[test]
+fn documented_unknown_declarations_are_kept() {
+    use crate::util::{SimplePosition, SimpleRange};
+
+    let src = indoc! {"
+        /// @notice a construct from the future
+        lemma positive(uint x) x > 0 {
+            assert true;
+        }
+
+        /// @dev so is this one
+        macro FOO = 3;
+
+        undocumented things are still ignored { }
+
+        rule foo() { }
+    "};
+
+    let parsed = Builder::new(src).build().unwrap();
+    assert_eq!(parsed.len(), 3);
+
+    assert_eq!(
+        parsed[0].ast,
+        Ast::Unknown {
+            first_token: "lemma".to_string(),
+            raw_header: "lemma positive(uint x) x > 0".to_string(),
+            range: SimpleRange {
+                start: SimplePosition {
+                    line: 1,
+                    character: 0
+                },
+                end: SimplePosition {
+                    line: 1,
+                    character: 28
+                },
+            },
+        }
+    );
+    assert_eq!(parsed[0].ast.name(), Some("positive"));
+    assert_eq!(parsed[0].doc[0].description, "a construct from the future");
+
+    assert_eq!(
+        parsed[1].ast,
+        Ast::Unknown {
+            first_token: "macro".to_string(),
+            raw_header: "macro FOO = 3".to_string(),
+            range: SimpleRange {
+                start: SimplePosition {
+                    line: 6,
+                    character: 0
+                },
+                end: SimplePosition {
+                    line: 6,
+                    character: 13
+                },
+            },
+        }
+    );
+    assert_eq!(parsed[1].ast.name(), Some("FOO"));
+
+    assert!(parsed[2].doc.is_empty());
+    assert_eq!(parsed[2].ast.name(), Some("foo"));
+}
+
+#[test]
+#[cfg(feature = "lsp")]
+fn dotted_param_names() {
+    let src = indoc! {"
+        /// @param args.amount the transfer amount
+        /// @param args.to the recipient
+        /// @param other.field this parameter does not exist
+        function transfer(TransferArgs args) { }
+    "};
+
+    let element = parse_exactly_one(src).unwrap();
+
+    assert_eq!(element.doc[0].param_name(), Some("args.amount"));
+    assert_eq!(element.doc[0].param_path(), ["args", "amount"]);
+
+    let converter = RangeConverter::new(Rope::from_str(src));
+    let diagnostics = element.enumerate_diagnostics(converter);
+    let diagnostic = diagnostics.iter().exactly_one().unwrap();
+    assert_eq!(diagnostic.message, "no such parameter: other.field");
+    assert_eq!(diagnostic.range.start.line, 2);
+}
+
+#[test]
+fn function_without_body() {
+    let body_less = parse_exactly_one("function foo(uint x) returns uint;").unwrap();
+    let Ast::Function { block, .. } = &body_less.ast else {
+        panic!()
+    };
+    assert_eq!(*block, None);
+    assert_eq!(body_less.ast.block(), None);
+
+    let empty_body = parse_exactly_one("function foo(uint x) returns uint { }").unwrap();
+    let Ast::Function { block, .. } = &empty_body.ast else {
+        panic!()
+    };
+    assert_eq!(block.as_deref(), Some(""));
+    assert_eq!(empty_body.ast.block(), Some(""));
+}
+
+#[test]
+fn methods_entries() {
+    let src = indoc! {"
+        methods {
+            function balanceOf(address) external returns (uint) envfree;   function totalSupply() external returns (uint) envfree;
+
+            function _.transfer(address, uint) external => DISPATCHER(true);
+            ;
+        }
+    "};
+
+    let methods = parse_exactly_one(src).unwrap();
+    assert_eq!(
+        methods.ast.methods_entries().unwrap(),
+        [
+            "function balanceOf(address) external returns (uint) envfree",
+            "function totalSupply() external returns (uint) envfree",
+            "function _.transfer(address, uint) external => DISPATCHER(true)",
+        ]
+    );
+
+    // entries may span lines, and only a `;` outside of comments and brackets ends them
+    let src = indoc! {"
+        methods {
+            /// @notice documented; with a semicolon
+            function transferFrom(address, address, uint)
+                external
+                returns (bool); // trailing; comment
+            function _.hook() external => CVL_HOOK[a; b];
+        }
+    "};
+    let methods = parse_exactly_one(src).unwrap();
+    assert_eq!(
+        methods.ast.methods_entries().unwrap(),
+        [
+            "function transferFrom(address, address, uint)\n        external\n        returns (bool)",
+            "function _.hook() external => CVL_HOOK[a; b]",
+        ]
+    );
+
+    let rule = parse_exactly_one("rule foo() { }").unwrap();
+    assert_eq!(rule.ast.methods_entries(), None);
+}
+
+#[test]
+#[cfg(feature = "lsp")]
+fn policy_lints() {
+    use crate::diagnostics::LintConfig;
+
+    let src = indoc! {"
+        /// @notice checks the sum
+        /// @dev see the invariant
+        rule sumIsCorrect() { assert true; }
+
+        /** @title no notice here */
+        rule nonView(method f) filtered { f -> !f.isView } { assert true; }
+
+        rule undocumented() { assert true; }
+    "};
+
+    let elements = Builder::new(src).build().unwrap();
+    let [documented_rule, filtered_rule, undocumented_rule] = elements.as_slice() else {
+        panic!("expected 3 elements, got {}", elements.len());
+    };
+
+    let codes = |element: &CvlElement, config: &LintConfig| {
+        let converter = RangeConverter::new(Rope::from_str(src));
+        element
+            .enumerate_diagnostics_with(converter, config)
+            .into_iter()
+            .filter_map(|diag| match diag.code {
+                Some(NumberOrString::String(code)) => Some((code, diag.range)),
+                _ => None,
+            })
+            .collect_vec()
+    };
+
+    // off by default
+    for element in &elements {
+        assert!(codes(element, &LintConfig::default()).is_empty());
+    }
+
+    let kinds = vec!["rule".to_string()];
+    let config = LintConfig {
+        require_title: kinds.clone(),
+        require_notice: kinds.clone(),
+        require_dev_for_filtered: kinds,
+        ..Default::default()
+    };
+
+    let documented_rule_codes = codes(documented_rule, &config);
+    let (code, range) = documented_rule_codes.iter().exactly_one().unwrap();
+    assert_eq!(code, "require-title");
+    assert_eq!((range.start.line, range.start.character), (0, 0));
+    assert_eq!((range.end.line, range.end.character), (0, 26));
+
+    let filtered_rule_codes = codes(filtered_rule, &config)
+        .into_iter()
+        .map(|(code, range)| (code, range.start.line))
+        .collect_vec();
+    assert_eq!(
+        filtered_rule_codes,
+        [
+            ("require-notice".to_string(), 4),
+            ("require-dev-for-filtered".to_string(), 4),
+        ]
+    );
+
+    // undocumented elements are reported at their name
+    let undocumented_codes = codes(undocumented_rule, &config);
+    assert_eq!(undocumented_codes.len(), 2);
+    let (_, range) = &undocumented_codes[0];
+    assert_eq!((range.start.line, range.start.character), (7, 5));
+    assert_eq!((range.end.line, range.end.character), (7, 17));
+}
+
+#[test]
+fn code_spans() {
+    let tag = DocumentationTag::new(
+        TagKind::Notice,
+        "see `totalSupplyIsSum` and ``a `nested` span``, but not ` unclosed".to_string(),
+        0..0,
+    );
+
+    assert_eq!(
+        tag.code_spans(),
+        [(5..21, "totalSupplyIsSum"), (29..44, "a `nested` span")]
+    );
+
+    let padded = DocumentationTag::new(TagKind::Dev, "`` `x` `` and ```".to_string(), 0..0);
+    assert_eq!(padded.code_spans(), [(3..6, "`x`")]);
+}
+
+#[test]
+fn resolve_links() {
+    let src = indoc! {"
+        /// @notice see `totalSupplyIsSum`, `missing` and `x > 0`
+        rule sumIsCorrect() { assert true; }
+
+        invariant totalSupplyIsSum() true;
+    "};
+
+    let elements = Builder::new(src).build().unwrap();
+
+    let links = crate::links::resolve(&elements, &elements);
+    let link = links.iter().exactly_one().unwrap();
+    assert_eq!(link.from.element.ast.name(), Some("sumIsCorrect"));
+    assert_eq!(link.from.text, "totalSupplyIsSum");
+    assert_eq!(link.to.ast.name(), Some("totalSupplyIsSum"));
+
+    let unresolved = crate::links::unresolved(&elements, &elements);
+    let reference = unresolved.iter().exactly_one().unwrap();
+    assert_eq!(reference.text, "missing");
+}
+
+#[test]
+fn title_after_other_tags() {
+    let src = indoc! {"
+        /**
+         * @notice the total supply is the sum of all balances
+         * @title Total supply
+         * @dev checked after every method
+         */
+        rule totalSupplyIsSum() { assert true; }
+    "};
+
+    let element = parse_exactly_one(src).unwrap();
+    let kinds = element.doc.iter().map(|tag| tag.kind.clone()).collect_vec();
+
+    assert_eq!(kinds, [TagKind::Notice, TagKind::Title, TagKind::Dev]);
+    assert_eq!(element.title().as_deref(), Some("Total supply"));
+}
+
+#[test]
+fn complete_documentation() {
+    use crate::coverage::CompletenessConfig;
+
+    let src = indoc! {"
+        /// @title Transfer
+        /// @param amount the amount
+        /// @param to the recipient
+        rule complete(uint amount, address to) { assert true; }
+
+        /// @notice missing a parameter
+        /// @param amount the amount
+        rule missingParam(uint amount, address to) { assert true; }
+
+        /// @param amount the amount
+        rule missingSummary(uint amount) { assert true; }
+
+        /// @notice no return tag
+        function foo() returns uint { return 0; }
+
+        rule undocumented() { assert true; }
+
+        //// ## a freeform comment
+    "};
+
+    let elements = Builder::new(src).build().unwrap();
+    let complete = elements
+        .iter()
+        .map(CvlElement::has_complete_documentation)
+        .collect_vec();
+    assert_eq!(complete, [true, false, false, true, false, true]);
+
+    let strict = CompletenessConfig {
+        require_return: true,
+        ..Default::default()
+    };
+    assert!(!strict.is_complete(&elements[3]));
+
+    let lenient = CompletenessConfig {
+        require_summary: false,
+        require_all_params: false,
+        require_return: false,
+        require_method_params: false,
+    };
+    assert!(elements[..4]
+        .iter()
+        .all(|element| lenient.is_complete(element)));
+    assert!(!lenient.is_complete(&elements[4]));
+}
+
+#[test]
+fn bundles() {
+    use crate::parse::bundle::{parse_bundle, BundleMarker};
+
+    let src = indoc! {"
+        // ===== file: first.spec =====
+        /// @notice the first rule
+        rule first() { assert true; }
+        // ===== file: second.spec =====
+        rule second() { assert true; }
+    "};
+
+    let bundle = parse_bundle(src, &BundleMarker::default()).unwrap();
+    assert_eq!(bundle.malformed_marker, None);
+
+    let [(first_name, first), (second_name, second)] = bundle.files.as_slice() else {
+        panic!("expected 2 files, got {}", bundle.files.len());
+    };
+    assert_eq!(first_name, "first.spec");
+    assert_eq!(first[0].ast.name(), Some("first"));
+    assert_eq!(first[0].span().start, 0);
+    assert_eq!(second_name, "second.spec");
+    assert_eq!(second[0].ast.name(), Some("second"));
+    assert_eq!(second[0].span().start, 0);
+
+    let malformed = indoc! {"
+        // ===== file: first.spec =====
+        rule first() { assert true; }
+        // ===== file: second.spec
+        rule second() { assert true; }
+    "};
+
+    let bundle = parse_bundle(malformed, &BundleMarker::default()).unwrap();
+    #[cfg(feature = "lsp")]
+    {
+        let diagnostic = bundle.diagnostics().into_iter().exactly_one().unwrap();
+        assert_eq!(
+            diagnostic.code,
+            Some(NumberOrString::String("malformed-marker".to_string()))
+        );
+        assert_eq!(diagnostic.range.start, lsp_types::Position::new(2, 0));
+        assert_eq!(diagnostic.range.end, lsp_types::Position::new(2, 26));
+    }
+    assert_eq!(bundle.malformed_marker.unwrap().line, 2);
+    let (name, elements) = bundle.files.iter().exactly_one().unwrap();
+    assert_eq!(name, "first.spec");
+    assert_eq!(elements.len(), 2);
+
+    let custom = BundleMarker {
+        prefix: "#file".to_string(),
+        suffix: String::new(),
+    };
+    let bundle = parse_bundle("rule a() { }\n#file b.spec\nrule b() { }", &custom).unwrap();
+    let names = bundle
+        .files
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect_vec();
+    assert_eq!(names, ["", "b.spec"]);
+}
+
+#[test]
+fn parsed_tags_are_authored() {
+    use crate::TagProvenance;
+
+    let element = parse_exactly_one("/// @notice hello\nrule foo() { }").unwrap();
+    assert_eq!(element.doc[0].provenance, TagProvenance::Authored);
+
+    let inherited = element.doc[0]
+        .clone()
+        .with_provenance(TagProvenance::Inherited {
+            from: "bar".to_string(),
+        });
+    assert!(!inherited.provenance.is_authored());
+    assert_eq!(inherited.description, element.doc[0].description);
+}
+
+#[test]
+fn render_html_marks_inherited_tags() {
+    use crate::render::{to_html, HtmlOptions};
+    use crate::TagProvenance;
+
+    let mut element = parse_exactly_one(indoc! {"
+        /// @notice authored
+        /// @notice from the base
+        /// @param x from the base <too>
+        rule foo(uint x) { assert true; }
+    "})
+    .unwrap();
+    for tag in &mut element.doc[1..] {
+        tag.provenance = TagProvenance::Inherited {
+            from: "rule <base>".to_string(),
+        };
+    }
+    let elements = [element];
+
+    let marked = HtmlOptions {
+        mark_inherited: true,
+        ..Default::default()
+    };
+    let html = to_html(&elements, &marked);
+    assert!(html.contains(indoc! {r#"
+        <p>authored</p>
+        <div class="inherited"><p>from the base</p>
+        <p class="attribution">inherited from rule &lt;base&gt;</p></div>
+        <dl class="params">
+        <dt><code>x</code></dt><dd class="inherited">from the base &lt;too&gt; <span class="attribution">inherited from rule &lt;base&gt;</span></dd>
+        </dl>
+    "#}));
+
+    // by default, inherited tags are rendered like the others
+    let html = to_html(&elements, &HtmlOptions::default());
+    assert!(!html.contains("inherited"));
+}
+
+#[test]
+fn cvl_identifiers() {
+    use crate::util::is_cvl_identifier;
+
+    for valid in ["foo", "_foo", "totalSupply", "a1", "balance$old", "Rule"] {
+        assert!(is_cvl_identifier(valid), "{valid}");
+    }
+    for invalid in [
+        "",
+        "1foo",
+        "$foo",
+        "foo-bar",
+        "foo.bar",
+        "rule",
+        "invariant",
+        "fóo",
+    ] {
+        assert!(!is_cvl_identifier(invalid), "{invalid}");
+    }
+}
+
+#[test]
+fn parse_stats() {
+    let src = indoc! {"
+        //// ## a freeform comment
+
+        /// @notice documented
+        /// @param nonexistent this parameter does not exist
+        rule documented() { assert true; }
+
+        rule undocumented() { assert true; }
+    "};
+
+    let (elements, stats) = Builder::new(src).build_with_stats().unwrap();
+
+    assert_eq!(stats.bytes, src.len());
+    assert_eq!(stats.docs, elements.len());
+    assert_eq!(stats.docs, 3);
+    assert_eq!(stats.freeform, 1);
+    assert_eq!(stats.documentation, 1);
+    assert_eq!(
+        stats.largest_block_len,
+        elements[1].doc_span.clone().unwrap().len()
+    );
+
+    #[cfg(feature = "lsp")]
+    {
+        let converter = RangeConverter::new(Rope::from_str(src));
+        let diagnostics: usize = elements
+            .iter()
+            .map(|element| element.enumerate_diagnostics(converter.clone()).len())
+            .sum();
+        assert_eq!(stats.diagnostics, diagnostics);
+        assert_eq!(stats.diagnostics, 1);
+    }
+}
+
+#[test]
+fn detach_documentation() {
+    let src = indoc! {"
+        /// @notice checks the sum
+        /// @dev see the invariant
+        rule sumIsCorrect() { assert true; }
+    "};
+
+    let element = parse_exactly_one(src).unwrap();
+    let (doc, doc_span) = (element.doc.clone(), element.doc_span.clone());
+
+    let detached = element.detach();
+    assert_eq!(detached.ast, Ast::Detached);
+    assert_eq!(detached.doc, doc);
+    assert_eq!(detached.doc_span, doc_span);
+    assert_eq!(Some(detached.element_span.clone()), doc_span);
+    assert_eq!(
+        detached.source_text(),
+        "/// @notice checks the sum\n/// @dev see the invariant"
+    );
+    assert!(detached.verify_against(src).is_ok());
+
+    let undocumented = parse_exactly_one("rule foo() { }").unwrap().detach();
+    assert_eq!(undocumented.ast, Ast::Detached);
+    assert!(undocumented.doc.is_empty());
+    assert_eq!(undocumented.source_text(), "");
+
+    let with_tag = undocumented.with_tag(DocumentationTag::notice("added"));
+    assert_eq!(with_tag.doc, [DocumentationTag::notice("added")]);
+}
+
+#[test]
+fn documentation_before_closing_brace_or_end_of_file() {
+    use crate::parse::builder::ParseConfig;
+
+    let inside_methods = indoc! {"
+        methods {
+            function foo() external;
+            /// @notice trailing documentation
+        }
+
+        rule bar() { assert true; }
+    "};
+    // comments within bodies are never documentation, so the comment is kept in the block
+    let parsed = Builder::new(inside_methods).build().unwrap();
+    assert!(parsed.iter().all(|element| element.doc.is_empty()));
+    assert!(parsed[0]
+        .ast
+        .block()
+        .unwrap()
+        .ends_with("/// @notice trailing documentation"));
+    assert_eq!(parsed[1].ast.name(), Some("bar"));
+
+    let detached_notice = |element: &CvlElement| {
+        assert_eq!(element.ast, Ast::Detached);
+        assert_eq!(element.element_span, element.doc_span.clone().unwrap());
+        element
+            .doc
+            .iter()
+            .exactly_one()
+            .unwrap()
+            .description
+            .clone()
+    };
+
+    let before_stray_brace = indoc! {"
+        /// @notice not for bar
+        }
+
+        rule bar() { assert true; }
+    "};
+    let parsed = Builder::new(before_stray_brace).build().unwrap();
+    assert_eq!(detached_notice(&parsed[0]), "not for bar");
+    assert_eq!(parsed[0].source_text(), "/// @notice not for bar");
+    assert!(parsed[1].doc.is_empty());
+    assert_eq!(parsed[1].doc_span, None);
+
+    let at_end_of_file = indoc! {"
+        rule bar() { assert true; }
+
+        /// @notice at the end of the file
+    "};
+    let parsed = Builder::new(at_end_of_file).build().unwrap();
+    assert!(parsed[0].doc.is_empty());
+    assert_eq!(detached_notice(&parsed[1]), "at the end of the file");
+
+    let followed_by_documentation = indoc! {"
+        /// @notice first
+        //// a freeform comment
+        /// @notice second
+        rule bar() { assert true; }
+    "};
+    // superseded documentation is dropped, unless it is asked for
+    let parsed = Builder::new(followed_by_documentation).build().unwrap();
+    assert!(parsed[0].is_freeform());
+    assert_eq!(parsed[1].doc[0].description, "second");
+    assert_eq!(parsed.len(), 2);
+
+    let config = ParseConfig {
+        detach_superseded_documentation: true,
+        ..Default::default()
+    };
+    let parsed = Builder::new(followed_by_documentation)
+        .with_config(config)
+        .build()
+        .unwrap();
+    assert_eq!(detached_notice(&parsed[0]), "first");
+    assert!(parsed[1].is_freeform());
+    assert_eq!(parsed[2].doc[0].description, "second");
+
+    // parse errors at the end of the file are past the source
+    for unterminated in ["rule f() {", "/// @notice x\nrule \"", "/* x", "'"] {
+        let parsed = Builder::new(unterminated).build().unwrap();
+        assert!(parsed.iter().all(|element| element.ast == Ast::Detached));
+    }
+}
+
+#[test]
+#[cfg(feature = "lsp")]
+fn detached_documentation_diagnostic() {
+    let src = indoc! {"
+        //! @title the spec
+
+        rule bar() { assert true; }
+
+        /// @notice at the end of the file
+        /// @param x not a parameter of anything
+    "};
+    let parsed = Builder::new(src).build().unwrap();
+    let converter = RangeConverter::new(Rope::from_str(src));
+    let codes = parsed
+        .iter()
+        .map(|element| {
+            element
+                .enumerate_diagnostics(converter.clone())
+                .into_iter()
+                .map(|diagnostic| diagnostic.code)
+                .collect_vec()
+        })
+        .collect_vec();
+
+    let no_associated_element = Some(NumberOrString::String("no-associated-element".into()));
+    assert_eq!(codes, [vec![], vec![], vec![no_associated_element]]);
+    let range = parsed[2].enumerate_diagnostics(converter).remove(0).range;
+    assert_eq!((range.start.line, range.end.line), (4, 5));
+}
+
+#[test]
+fn strong_invariant() {
+    let strong_of = |src| {
+        let parsed = parse_exactly_one(src).unwrap();
+        let Ast::Invariant { strong, name, .. } = parsed.ast else {
+            panic!("expected an invariant, got {}", parsed.ast)
+        };
+        assert_eq!(name, "positive");
+        strong
+    };
+
+    assert!(strong_of(
+        "/// @notice strong\nstrong invariant positive(uint x) x > 0;"
+    ));
+    assert!(strong_of(
+        "strong invariant positive(uint x) x > 0 { preserved { } }"
+    ));
+    assert!(!strong_of("invariant positive(uint x) x > 0;"));
+
+    let rule_named_strong = parse_exactly_one("rule strong() { }").unwrap();
+    assert_eq!(rule_named_strong.ast.name(), Some("strong"));
+}
+
+#[test]
+fn file_level_documentation() {
+    let src = indoc! {"
+        //! @title Token specification
+        //! @notice checks the token. really!
+
+        /// @notice the first rule
+        rule first() { assert true; }
+
+        /*!
+         * @dev more about the file
+         */
+        rule second() { assert true; }
+    "};
+
+    let parsed = Builder::new(src).build().unwrap();
+    let [top, first, middle, second] = parsed.as_slice() else {
+        panic!("expected 4 elements, got {}", parsed.len());
+    };
+
+    assert_eq!(top.ast, Ast::FileDocumentation);
+    assert_eq!(top.doc.len(), 2);
+    assert_eq!(top.doc[0].kind, TagKind::Title);
+    assert_eq!(top.doc[0].description, "Token specification");
+    assert_eq!(top.doc[1].description, "checks the token. really!");
+    assert_eq!(
+        top.source_text(),
+        "//! @title Token specification\n//! @notice checks the token. really!"
+    );
+
+    assert_eq!(first.ast.name(), Some("first"));
+    assert_eq!(first.doc[0].description, "the first rule");
+
+    assert_eq!(middle.ast, Ast::FileDocumentation);
+    assert_eq!(middle.doc[0].kind, TagKind::Dev);
+    assert_eq!(middle.doc[0].description, "more about the file");
+
+    // file documentation is never associated with the next element
+    assert_eq!(second.ast.name(), Some("second"));
+    assert!(second.doc.is_empty());
+}
+
+#[test]
+fn file_documentation_round_trips() {
+    let top = parse_exactly_one("//! @title Token specification").unwrap();
+    let comment = top.to_cvl_comment();
+    assert_eq!(comment, "/*!\n * @title Token specification\n */");
+
+    let reparsed = parse_exactly_one(&comment).unwrap();
+    assert_eq!(reparsed.ast, Ast::FileDocumentation);
+    assert_eq!(reparsed.doc[0].description, "Token specification");
+}
+
+#[test]
+fn inline_tags_start_new_tags() {
+    let src = indoc! {"
+        /**
+         * @formula x + y > 0 @param x the first summand
+         * @notice contact admin@example.com, or see @dev notes
+         */
+        rule sum(uint x, uint y) { assert true; }
+    "};
+
+    let element = parse_exactly_one(src).unwrap();
+    let tags = element
+        .doc
+        .iter()
+        .map(|tag| (tag.kind.clone(), tag.description.as_str()))
+        .collect_vec();
+
+    assert_eq!(
+        tags,
+        [
+            (TagKind::Formula, "x + y > 0"),
+            (TagKind::Param, "x the first summand"),
+            (TagKind::Notice, "contact admin@example.com, or see"),
+            (TagKind::Dev, "notes"),
+        ]
+    );
+}
+
+#[test]
+fn single_line_block_comment_with_several_tags() {
+    let src = indoc! {"
+        /** @notice text @param a the amount @return nothing */
+        function f(uint a) returns uint { return 0; }
+    "};
+
+    let element = parse_exactly_one(src).unwrap();
+    let tags = element
+        .doc
+        .iter()
+        .map(|tag| (tag.kind.clone(), tag.description.as_str()))
+        .collect_vec();
+
+    assert_eq!(
+        tags,
+        [
+            (TagKind::Notice, "text"),
+            (TagKind::Param, "a the amount"),
+            (TagKind::Return, "nothing"),
+        ]
+    );
+    assert_eq!(element.doc[1].param_name(), Some("a"));
+
+    let without_spaces = parse_exactly_one(
+        "/**@notice text @param a*/
+rule r(uint a) { }",
+    )
+    .unwrap();
+    assert_eq!(without_spaces.doc[0].description, "text");
+    assert_eq!(without_spaces.doc[1].description, "a");
+}
+
+#[test]
+fn all_descriptions() {
+    let src = indoc! {"
+        //// ## Section
+        //// about the rules
+
+        /// @title Sum
+        /// @notice checks
+        /// the sum
+        /// @param x the summand
+        rule sum(uint x) { assert true; }
+
+        rule undocumented() { assert true; }
+    "};
+
+    let parsed = Builder::new(src).build().unwrap();
+    let descriptions = parsed
+        .iter()
+        .map(CvlElement::all_descriptions)
+        .collect_vec();
+
+    assert_eq!(
+        descriptions,
+        [
+            vec!["## Section\nabout the rules"],
+            vec!["Sum", "checks\nthe sum", "x the summand"],
+            vec![],
+        ]
+    );
+}
+
+#[test]
+fn reparse_in_place() {
+    let src = indoc! {"
+        rule before() { assert true; }
+
+        /// @notice the sum
+        rule sum(uint x) { assert true; }
+    "};
+
+    let edits = [
+        "/// @notice the sum of all balances",
+        "/// @notice the sum\n/// @param x the summand\n/// @dev added lines",
+        "/** @title Sum */",
+        "/// @dev",
+    ];
+
+    for new_comment in edits {
+        let mut parsed = Builder::new(src).build().unwrap();
+        let element = &mut parsed[1];
+        element.reparse_in_place(new_comment).unwrap();
+
+        let new_src = src.replace("/// @notice the sum", new_comment);
+        let expected = Builder::new(&new_src).build().unwrap().remove(1);
+
+        assert_eq!(element.doc, expected.doc);
+        assert_eq!(element.doc_span, expected.doc_span);
+        assert_eq!(element.element_span, expected.element_span);
+        assert_eq!(element.doc_range, expected.doc_range);
+        assert_eq!(element.element_range, expected.element_range);
+        assert_eq!(element.ast, expected.ast);
+        assert_eq!(&*element.src, new_src);
+        assert!(element.verify_against(&new_src).is_ok());
+    }
+
+    let mut parsed = Builder::new(src).build().unwrap();
+    assert!(parsed[0]
+        .reparse_in_place("/// @notice undocumented")
+        .is_err());
+    assert!(parsed[1].reparse_in_place("//// freeform").is_err());
+    assert!(parsed[1].reparse_in_place("//! @notice file").is_err());
+    assert!(parsed[1]
+        .reparse_in_place("/// @notice one\nrule other() { }")
+        .is_err());
+}
+
+#[test]
+#[cfg(feature = "lsp")]
+fn duplicate_element_names() {
+    use crate::diagnostics::{check_collection, CollectionConfig};
+
+    let first_file = indoc! {"
+        rule transfer() { assert true; }
+        invariant transfer() true;
+        methods { function transfer() external; }
+
+        rule transfer() { assert false; }
+    "};
+    let second_file = indoc! {"
+        methods { function transfer() external; }
+        rule transfer() { assert true; }
+    "};
+
+    let mut docs = Builder::new(first_file).build().unwrap();
+    docs.extend(Builder::new(second_file).build().unwrap());
+
+    let diagnostics = check_collection(&docs, &CollectionConfig::default());
+    let flagged = diagnostics.iter().map(|(i, _)| *i).collect_vec();
+    assert_eq!(flagged, [3, 5]);
+
+    let (_, diagnostic) = &diagnostics[0];
+    assert_eq!(
+        diagnostic.code,
+        Some(NumberOrString::String("duplicate-element-name".to_string()))
+    );
+    assert_eq!(diagnostic.range.start, lsp_types::Position::new(4, 5));
+    assert_eq!(diagnostic.range.end, lsp_types::Position::new(4, 13));
+
+    let same_file_only = CollectionConfig {
+        cross_file_duplicates: false,
+        ..Default::default()
+    };
+    let diagnostics = check_collection(&docs, &same_file_only);
+    let flagged = diagnostics.iter().map(|(i, _)| *i).collect_vec();
+    assert_eq!(flagged, [3]);
+}
+
+#[test]
+fn file_parser() {
+    use crate::parse::file_parser::FileParser;
+
+    let src = indoc! {r#"
+        //// ## Rules
+
+        /// @notice the first rule
+        rule first() {
+            // a } in a comment
+            string s = "}";
+            assert true;
+        }
+
+        /// @notice a filtered rule
+        rule filtered_rule(method f) filtered { f -> !f.isView }
+        {
+            assert true;
+        }
+
+        invariant positive(uint x)
+            x > 0;
+
+        /** @notice a ghost */
+        ghost mathint sum;
+    "#};
+
+    let streamed = FileParser::new(src.as_bytes()).collect_vec();
+    let parsed = Builder::new(src).build().unwrap();
+
+    assert_eq!(streamed.len(), parsed.len());
+    for (streamed, parsed) in streamed.iter().zip(&parsed) {
+        // spans are relative to the entire input, of which `src` is the part read so far
+        assert!(streamed.same_content(parsed));
+        assert_eq!(streamed.element_span, parsed.element_span);
+        assert_eq!(streamed.doc_span, parsed.doc_span);
+        assert_eq!(streamed.element_range, parsed.element_range);
+        assert_eq!(streamed.doc_range, parsed.doc_range);
+        assert!(src.starts_with(&*streamed.src));
+        for (streamed, parsed) in streamed.doc.iter().zip(&parsed.doc) {
+            assert_eq!(streamed.span, parsed.span);
+            assert_eq!(streamed.range, parsed.range);
+            assert_eq!(streamed.source_map(), parsed.source_map());
+        }
+    }
+    assert_eq!(streamed.last().unwrap().src, parsed[0].src);
+
+    let mut file_parser = FileParser::new(src.as_bytes());
+    file_parser.by_ref().for_each(drop);
+    assert!(file_parser.parse_errors().is_empty());
+    assert!(file_parser.error().is_none());
+}
+
+#[test]
+#[cfg(feature = "lsp")]
+fn line_and_column_ranges() {
+    let src = indoc! {"
+        /// @notice the first rule
+        rule first() { assert true; }
+
+            /**
+             * @title Ünïcode
+             * @notice the second rule
+             */
+            rule second() { assert true; }
+    "};
+
+    let converter = RangeConverter::new(Rope::from_str(src));
+    let parsed = Builder::new(src).build().unwrap();
+    assert_eq!(parsed.len(), 2);
+
+    for element in &parsed {
+        let element_range = converter.to_simple_range(element.element_span.clone());
+        assert_eq!(element.element_range, element_range);
+        let doc_range = element.doc_span.clone().unwrap();
+        assert_eq!(
+            element.doc_range,
+            Some(converter.to_simple_range(doc_range))
+        );
+        for tag in &element.doc {
+            assert_eq!(tag.range, Some(converter.to_simple_range(tag.span.clone())));
+        }
+    }
+
+    let notice = &parsed[1].doc[1];
+    let notice_range = notice.range.unwrap();
+    assert_eq!(notice_range.start.line, 5);
+    assert_eq!(notice_range.start.character, 2);
+
+    // serialized like the LSP type
+    let range = parsed[1].element_range;
+    let json = serde_json::to_value(parsed[1].clone()).unwrap();
+    let lsp_range = serde_json::to_value(lsp_types::Range::from(range)).unwrap();
+    assert_eq!(json["element_range"], lsp_range);
+
+    let within_second = src.find("second rule").unwrap();
+    let ranged = parse_range(src, within_second..within_second + 1).unwrap();
+    assert_eq!(ranged[0].element_range, parsed[1].element_range);
+    assert_eq!(ranged[0].doc_range, parsed[1].doc_range);
+    assert_eq!(ranged[0].doc[1].range, notice.range);
+}
+
+#[test]
+fn first_and_last_tag() {
+    let src = indoc! {"
+        /// @title Sum
+        /// @notice checks the sum
+        /// @dev the last tag
+        rule sum() { assert true; }
+    "};
+
+    let element = parse_exactly_one(src).unwrap();
+    assert_eq!(element.first_tag().unwrap().kind, TagKind::Title);
+    assert_eq!(element.last_tag().unwrap().description, "the last tag");
+
+    let undocumented = parse_exactly_one("rule foo() { }").unwrap();
+    assert_eq!(undocumented.first_tag(), None);
+    assert_eq!(undocumented.last_tag(), None);
+}
+
+#[test]
+fn requirements() {
+    let src = indoc! {"
+        /// @notice transfers preserve the total supply
+        /// @requirement REQ-12, REQ-13
+        /// @satisfies REQ-13 REQ-7
+        rule transferPreservesSupply() { assert true; }
+
+        /// @satisfies REQ-12
+        invariant totalSupplyIsSum() true;
+
+        rule untracked() { assert true; }
+    "};
+
+    let parsed = Builder::new(src).build().unwrap();
+    let rule = &parsed[0];
+    assert_eq!(rule.doc[1].kind, TagKind::Requirement);
+    assert_eq!(rule.doc[1].requirement_ids(), ["REQ-12", "REQ-13"]);
+    assert_eq!(rule.doc[2].kind, TagKind::Satisfies);
+    assert_eq!(rule.doc[2].requirement_ids(), ["REQ-13", "REQ-7"]);
+    assert!(rule.doc[0].requirement_ids().is_empty());
+
+    let matrix = crate::requirements::matrix(&parsed);
+    let names = matrix
+        .iter()
+        .map(|(id, elements)| {
+            let names = elements
+                .iter()
+                .map(|element| element.ast.name().unwrap())
+                .collect_vec();
+            (id.as_str(), names)
+        })
+        .collect_vec();
+
+    assert_eq!(
+        names,
+        [
+            (
+                "REQ-12",
+                vec!["transferPreservesSupply", "totalSupplyIsSum"]
+            ),
+            ("REQ-13", vec!["transferPreservesSupply"]),
+            ("REQ-7", vec!["transferPreservesSupply"]),
+        ]
+    );
+}
+
+#[test]
+fn braces_within_strings_and_comments_of_blocks() {
+    let src = indoc! {r#"
+        /// @notice braces
+        rule braces() {
+            // an unmatched } in a comment
+            /* and { another */
+            assert true, "and a } in a string";
+            assert true, "an escaped \" does not end } a string";
+        }
+
+        /// @notice after
+        rule after() { assert true; }
+    "#};
+
+    let parsed = Builder::new(src).build().unwrap();
+    assert_eq!(parsed.len(), 2);
+
+    let block = parsed[0].ast.block().unwrap();
+    assert!(block.ends_with(r#""an escaped \" does not end } a string";"#));
+    assert_eq!(parsed[1].ast.name(), Some("after"));
+}
+
+/// a `methods` block of 3000 entries, followed by a rule with a long literal
+fn large_methods_block_with_long_literals_src() -> String {
+    let mut src = String::from("/// @notice many entries\nmethods {\n");
+    let constant = "deadbeef".repeat(16);
+    for i in 0..3000 {
+        src.push_str(&format!(
+            "    function f{i}(uint256 x) external returns (uint256) => ALWAYS(0x{constant});\n"
+        ));
+    }
+    src.push_str("}\n\n/// @notice after\nrule after() {\n    require x == 0x");
+    src.push_str(&"ab".repeat(2000));
+    src.push_str(";\n    assert true;\n}\n");
+    src
+}
+
+#[test]
+fn large_methods_block_with_long_literals() {
+    let src = large_methods_block_with_long_literals_src();
+    let parsed = Builder::new(&src).build().unwrap();
+
+    assert_eq!(parsed.len(), 2);
+    assert_eq!(parsed[0].ast.methods_entries().unwrap().len(), 3000);
+    assert_eq!(parsed[1].ast.name(), Some("after"));
+}
+
+/// the bounds are generous, so that slow machines pass, while backtracking over the block,
+/// which took seconds, does not
+#[test]
+fn large_methods_block_parses_quickly() {
+    let src = large_methods_block_with_long_literals_src();
+
+    let start = std::time::Instant::now();
+    Builder::new(&src).build().unwrap();
+    let elapsed = start.elapsed();
+
+    let limit = if cfg!(debug_assertions) {
+        std::time::Duration::from_secs(3)
+    } else {
+        std::time::Duration::from_millis(100)
+    };
+    assert!(elapsed < limit, "parsing took {elapsed:?}");
+}
+
+#[test]
+fn tag_refs_borrow_single_line_descriptions() {
+    let src = indoc! {"
+        /**
+         * @title a title
+         * @notice a notice spanning
+         *         two lines
+         */
+        rule foo() { assert true; }
+    "};
+
+    let refs = Builder::new(src).build_tag_refs().unwrap();
+    let [(tags, span)] = refs.as_slice() else {
+        panic!("expected a single documentation comment");
+    };
+    assert_eq!(span.start, 0);
+
+    assert_matches!(tags[0].description, std::borrow::Cow::Borrowed("a title"));
+    assert_matches!(tags[1].description, std::borrow::Cow::Owned(_));
+
+    let element = parse_exactly_one(src).unwrap();
+    let owned = tags
+        .iter()
+        .cloned()
+        .map(DocumentationTag::from)
+        .collect_vec();
+    assert_eq!(owned, element.doc);
+    assert_eq!(element.doc[0].as_tag_ref(), tags[0]);
+}
+
+#[test]
+fn tag_constructors_and_validation() {
+    use crate::TagValidationError;
+
+    let param = DocumentationTag::param("amount", "the amount to transfer").unwrap();
+    assert_eq!(param.param_name(), Some("amount"));
+    assert_eq!(param.validate(), Ok(()));
+    for name in ["", "two words"] {
+        assert_eq!(
+            DocumentationTag::param(name, "the amount"),
+            Err(TagValidationError::InvalidParamName(name.to_string()))
+        );
+    }
+    assert_eq!(
+        DocumentationTag::param("amount", ""),
+        Err(TagValidationError::MissingParamName)
+    );
+    assert_eq!(DocumentationTag::ret("the balance").kind, TagKind::Return);
+
+    let unnamed = DocumentationTag::new(TagKind::Param, "amount".to_string(), 0..0);
+    assert_eq!(
+        unnamed.validate(),
+        Err(TagValidationError::MissingParamName)
+    );
+
+    assert!(DocumentationTag::title("a title").is_ok());
+    assert_eq!(
+        DocumentationTag::title("a title\nspanning lines"),
+        Err(TagValidationError::MultiLineTitle)
+    );
+    let multi_line = DocumentationTag::new(TagKind::Title, "a\nb".to_string(), 0..0);
+    assert_eq!(
+        multi_line.validate(),
+        Err(TagValidationError::MultiLineTitle)
+    );
+
+    assert!(DocumentationTag::formula("x > 0").is_ok());
+    assert_eq!(
+        DocumentationTag::formula(" "),
+        Err(TagValidationError::EmptyFormula)
+    );
+}
+
+#[test]
+#[cfg(feature = "lsp")]
+fn invalid_tags_are_diagnosed() {
+    let src = indoc! {"
+        /// @title a title
+        /// @formula
+        rule foo(uint x) { assert true; }
+    "};
+
+    let element = parse_exactly_one(src).unwrap();
+    assert_eq!(element.doc[1].description, "");
+
+    let converter = RangeConverter::new(Rope::from_str(src));
+    let diagnostics = element.enumerate_diagnostics(converter);
+    let diagnostic = diagnostics.iter().exactly_one().unwrap();
+    assert_eq!(
+        diagnostic.code,
+        Some(NumberOrString::String("invalid-tag".to_string()))
+    );
+    assert_eq!(diagnostic.message, "@formula tag is empty");
+    assert_eq!(diagnostic.range.start.line, 1);
+}
+
+#[test]
+fn annotations_are_ignored_when_comparing() {
+    let src = "/// @notice foo\nrule foo() { assert true; }";
+    let element = parse_exactly_one(src).unwrap();
+    assert!(element.annotations.is_empty());
+
+    let mut annotated = element.clone();
+    annotated
+        .annotations
+        .insert("test_status".to_string(), "passing".to_string());
+    annotated
+        .annotations
+        .insert("owner".to_string(), "team".to_string());
+    assert_eq!(annotated, element);
+
+    // keys are serialized in order
+    let json = serde_json::to_string(&annotated).unwrap();
+    assert!(json.contains(r#""annotations":{"owner":"team","test_status":"passing"}"#));
+
+    // ranges are derived from the spans, and are not compared either
+    let mut moved = element.clone();
+    moved.element_range = Default::default();
+    moved.doc_range = None;
+    assert_eq!(moved, element);
+    moved.element_span = 0..1;
+    assert_ne!(moved, element);
+}
+
+#[test]
+fn render_html() {
+    use crate::render::{to_html, HtmlOptions};
+
+    let src = indoc! {r#"
+        /**
+         * @title Transfers *preserve* supply
+         * @notice See [the spec](https://example.com/spec?a=1&b=2) and `totalSupply`.
+         *
+         * Never [click](javascript:alert(1)).
+         * @param amount the <b>amount</b>
+         * @formula a < b
+         */
+        rule transferPreservesSupply(uint256 amount) { assert true; }
+
+        /// @dev <script>alert("xss")</script>
+        ghost mapping(address => uint) balances;
+
+        rule undocumented() { assert true; }
+    "#};
+    let docs = Builder::new(src).build().unwrap();
+
+    let html = to_html(&docs, &HtmlOptions::default());
+    let expected = indoc! {r#"
+        <section class="cvl-rule" id="rule-transferpreservessupply">
+        <h2>Transfers <em>preserve</em> supply</h2>
+        <pre><code class="cvl"><span class="keyword">rule</span> <span class="name">transferPreservesSupply</span>(<span class="type">uint256</span> amount)</code></pre>
+        <p>See <a href="https://example.com/spec?a=1&amp;b=2">the spec</a> and <code>totalSupply</code>.</p>
+        <p>Never [click](javascript:alert(1)).</p>
+        <pre class="formula"><code>a &lt; b</code></pre>
+        <dl class="params">
+        <dt><code>amount</code></dt><dd>the &lt;b&gt;amount&lt;/b&gt;</dd>
+        </dl>
+        </section>
+        <section class="cvl-ghost" id="ghost-balances">
+        <h2>balances</h2>
+        <pre><code class="cvl"><span class="keyword">ghost</span> <span class="type">mapping(address =&gt; uint)</span> <span class="name">balances</span></code></pre>
+        <div class="dev"><p>&lt;script&gt;alert(&quot;xss&quot;)&lt;/script&gt;</p>
+        </div>
+        </section>
+    "#};
+    assert_eq!(html, expected);
+
+    let nested = Builder::new(
+        "/// @notice approvals\nghost mapping(address  owner => mapping(uint => bool)) approved;",
+    )
+    .build()
+    .unwrap();
+    let html = to_html(&nested, &HtmlOptions::default());
+    assert!(html.contains(
+        r#"<span class="type">mapping(address owner =&gt; mapping(uint =&gt; bool))</span>"#
+    ));
+
+    let all = HtmlOptions {
+        heading_level: 3,
+        include_undocumented: true,
+        ..Default::default()
+    };
+    let html = to_html(&docs, &all);
+    assert!(html.ends_with(indoc! {r#"
+        <section class="cvl-rule" id="rule-undocumented">
+        <h3>undocumented</h3>
+        <pre><code class="cvl"><span class="keyword">rule</span> <span class="name">undocumented</span>()</code></pre>
+        </section>
+    "#}));
+    assert_eq!(html, to_html(&docs, &all));
+}
+
+#[test]
+fn render_html_inline_markup() {
+    use crate::render::{to_html, HtmlOptions};
+
+    let paragraph = |notice: &str| {
+        let src = format!("/// @notice {notice}\nrule r() {{ assert true; }}");
+        let html = to_html(
+            &Builder::new(&src).build().unwrap(),
+            &HtmlOptions::default(),
+        );
+        let start = html.find("<p>").unwrap() + "<p>".len();
+        let end = html.find("</p>").unwrap();
+        html[start..end].to_string()
+    };
+
+    assert_eq!(paragraph("*a **b** c*"), "<em>a <strong>b</strong> c</em>");
+    assert_eq!(
+        paragraph("total_supply and _x_"),
+        "total_supply and <em>x</em>"
+    );
+    // a delimiter closes the nearest open delimiter of its kind
+    assert_eq!(paragraph("*a **b* c**"), "<em>a **b</em> c**");
+    assert_eq!(
+        paragraph("`x*y` and *z*"),
+        "<code>x*y</code> and <em>z</em>"
+    );
+    assert_eq!(paragraph("** and * and ``"), "** and * and ``");
+    assert_eq!(
+        paragraph("[[a](javascript:x) [b](https://b.org)"),
+        r#"[[a](javascript:x) <a href="https://b.org">b</a>"#
+    );
+
+    // unmatched delimiters are not searched for again. the comment stays under
+    // `max_comment_bytes`, and the bound is generous, as in `large_methods_block_parses_quickly`
+    let unmatched = format!("x ** `` {}", "a_b [c ".repeat(9_000));
+    let start = std::time::Instant::now();
+    let html = paragraph(&unmatched);
+    let elapsed = start.elapsed();
+    assert_eq!(html, unmatched.trim_end());
+    let limit = if cfg!(debug_assertions) {
+        std::time::Duration::from_secs(3)
+    } else {
+        std::time::Duration::from_millis(100)
+    };
+    assert!(elapsed < limit, "rendering took {elapsed:?}");
+}
+
+#[test]
+fn inline_comments_within_bodies() {
+    let src = indoc! {r#"
+        /// @notice outer
+        rule withComments(uint x) {
+            // explanation of the next line {
+            require x > 0; // trailing comment }
+            /// @notice this is not documentation
+            assert x != 0;
+        }
+
+        invariant positive(uint x) x > 0 {
+            preserved {
+                // } inside a preserved block
+                require true;
+            }
+        }
+
+        /// @notice after
+        function f() returns uint {
+            //// nor is this a freeform comment
+            return 1;
+        }
+    "#};
+
+    let parsed = Builder::new(src).build().unwrap();
+    assert_eq!(parsed.len(), 3);
+
+    assert_eq!(parsed[0].doc.len(), 1);
+    assert_eq!(parsed[0].doc[0].description, "outer");
+    let block = parsed[0].ast.block().unwrap();
+    assert!(block.contains("/// @notice this is not documentation"));
+    assert!(block.ends_with("assert x != 0;"));
+
+    assert_matches!(parsed[1].ast, Ast::Invariant { .. });
+    assert!(parsed[1].doc.is_empty());
+
+    assert_eq!(parsed[2].ast.name(), Some("f"));
+    assert_eq!(parsed[2].doc[0].description, "after");
+}
+
+#[test]
+fn workspace_follows_imports() {
+    use crate::workspace::Workspace;
+    use std::collections::HashMap;
+
+    let files = HashMap::from([
+        (
+            "common/base.spec",
+            indoc! {r#"
+                import "../specs/token.spec";
+
+                /// @notice the total supply
+                definition supply() returns uint = 0;
+            "#},
+        ),
+        (
+            "specs/helpers.spec",
+            indoc! {r#"
+                import "../common/base.spec";
+                import "missing.spec";
+
+                /// @notice a helper
+                function helper() { }
+            "#},
+        ),
+    ]);
+    let resolver = |path: &str| files.get(path).map(|src| src.to_string());
+
+    let token = indoc! {r#"
+        import "helpers.spec";
+
+        /// @notice transfers preserve `supply`
+        rule transferPreservesSupply() { assert true; }
+    "#};
+
+    let mut workspace = Workspace::new(resolver);
+    workspace.load("specs/token.spec", token).unwrap();
+
+    let paths = workspace
+        .files()
+        .iter()
+        .map(|file| file.path.as_str())
+        .collect_vec();
+    assert_eq!(
+        paths,
+        ["specs/token.spec", "specs/helpers.spec", "common/base.spec"]
+    );
+
+    let unresolved = workspace.unresolved_imports().iter().exactly_one().unwrap();
+    assert_eq!(unresolved.importer, "specs/helpers.spec");
+    assert_eq!(unresolved.path, "specs/missing.spec");
+    assert!(workspace.failed_imports().is_empty());
+
+    let supply = workspace.resolve_name("supply").unwrap();
+    assert_matches!(supply.ast, Ast::Definition { .. });
+    assert!(workspace.resolve_name("nonexistent").is_none());
+
+    let file = workspace.file_of(0).unwrap();
+    assert_eq!(file.path, "specs/token.spec");
+
+    let links = crate::links::resolve(workspace.docs(), workspace.docs());
+    assert_eq!(links.iter().exactly_one().unwrap().to, supply);
+
+    // loading a file again does nothing
+    workspace.load("common/base.spec", "").unwrap();
+    assert_eq!(workspace.files().len(), 3);
+
+    #[cfg(feature = "lsp")]
+    {
+        let diagnostics = workspace.diagnostics();
+        let (path, diagnostic) = diagnostics.iter().exactly_one().unwrap();
+        assert_eq!(path, "specs/helpers.spec");
+        assert_eq!(
+            diagnostic.code,
+            Some(NumberOrString::String("unresolved-import".to_string()))
+        );
+        assert_eq!(diagnostic.range.start.line, 1);
+    }
+}
+
+#[test]
+#[cfg(feature = "lsp")]
+fn tabs_count_as_one_character() {
+    use crate::util::{SimplePosition, Span};
+
+    let src = "\t/**\n\t * @notice n\n\t * @param y\tbad\n\t */\n\trule foo(uint x) { }\n\n\t\t/// @param z\tbad\n\t\tfunction f() { }\n";
+    let parsed = Builder::new(src).build().unwrap();
+    let converter = RangeConverter::new(Rope::from_str(src));
+    let range = |span: &Span| {
+        let range = converter.to_simple_range(span.clone());
+        [range.start, range.end].map(|pos| (pos.line, pos.character))
+    };
+
+    let rule = &parsed[0];
+    assert_eq!(range(rule.doc_span.as_ref().unwrap()), [(0, 1), (3, 4)]);
+    assert_eq!(range(&rule.element_span), [(4, 1), (4, 21)]);
+    assert_eq!(rule.doc[1].description, "y\tbad");
+    assert_eq!(range(&rule.doc[1].span), [(2, 0), (3, 0)]);
+
+    let function = &parsed[1];
+    assert_eq!(
+        range(function.doc_span.as_ref().unwrap()),
+        [(6, 2), (6, 18)]
+    );
+    assert_eq!(range(&function.element_span), [(7, 2), (7, 18)]);
+
+    let diagnostics = function.enumerate_diagnostics(converter.clone());
+    let diagnostic = diagnostics.iter().exactly_one().unwrap();
+    assert_eq!(diagnostic.range.start, lsp_types::Position::new(6, 2));
+
+    // `function` starts after two tabs
+    let function_start = SimplePosition {
+        line: 7,
+        character: 2,
+    };
+    assert_eq!(converter.visual_column(function_start, 4), 8);
+    assert_eq!(converter.visual_column(function_start, 8), 16);
+    // the tab in `y\tbad` advances to the next tab stop
+    let after_tab = SimplePosition {
+        line: 2,
+        character: 13,
+    };
+    assert_eq!(converter.visual_column(after_tab, 4), 16);
+}
+
+#[test]
+fn sort_tags_by_kind() {
+    use crate::TagOrder;
+
+    let src = indoc! {"
+        /// @param x the first
+        /// @dev a dev note
+        /// @formula x > 0
+        /// @param y the second
+        /// @notice a notice
+        /// @title the title
+        rule foo(uint x, uint y) { assert true; }
+    "};
+    let element = parse_exactly_one(src).unwrap();
+    let kinds_of = |element: &CvlElement| {
+        element
+            .doc
+            .iter()
+            .map(|tag| (tag.kind.clone(), tag.description.clone()))
+            .collect_vec()
+    };
+
+    let sorted = element.clone().sort_tags_by_kind();
+    let expected = [
+        (TagKind::Title, "the title"),
+        (TagKind::Notice, "a notice"),
+        (TagKind::Dev, "a dev note"),
+        (TagKind::Param, "x the first"),
+        (TagKind::Param, "y the second"),
+        (TagKind::Formula, "x > 0"),
+    ]
+    .map(|(kind, description)| (kind, description.to_string()));
+    assert_eq!(kinds_of(&sorted), expected);
+
+    let params_first = TagOrder(vec![TagKind::Param, TagKind::Formula]);
+    let sorted = element.sort_tags_with(&params_first);
+    let kinds = sorted.doc.iter().map(|tag| tag.kind.clone()).collect_vec();
+    assert_eq!(
+        kinds,
+        [
+            TagKind::Param,
+            TagKind::Param,
+            TagKind::Formula,
+            TagKind::Dev,
+            TagKind::Notice,
+            TagKind::Title
+        ]
+    );
+}
+
+#[test]
+fn remove_docs() {
+    use crate::strip::{remove_docs, remove_docs_with, StripMode};
+
+    let src = indoc! {r#"
+        //! @title a file
+        import "a.spec";
+        /**
+         * @notice a rule
+         */
+        rule foo() {
+            /// kept, since it is within the body
+            assert true; // kept
+        }
+        //// a freeform comment
+        /// @notice a function
+        function f() { }
+        /* a regular comment is kept */
+    "#};
+
+    let stripped = remove_docs(src).unwrap();
+    let expected = indoc! {r#"
+        import "a.spec";
+
+        rule foo() {
+            /// kept, since it is within the body
+            assert true; // kept
+        }
+        function f() { }
+        /* a regular comment is kept */
+    "#};
+    assert_eq!(stripped, expected);
+
+    let parsed = Builder::new(&stripped).build().unwrap();
+    assert_eq!(parsed.len(), 3);
+    assert!(parsed.iter().all(|element| element.doc.is_empty()));
+
+    let keep_lines = remove_docs_with(src, StripMode::KeepLineNumbers).unwrap();
+    assert_eq!(keep_lines.lines().count(), src.lines().count());
+    let line_of = |text: &str, needle| text.lines().position(|line| line.contains(needle));
+    for needle in ["import", "rule foo", "function f", "regular comment"] {
+        assert_eq!(line_of(&keep_lines, needle), line_of(src, needle));
+    }
+    assert!(Builder::new(&keep_lines)
+        .build()
+        .unwrap()
+        .iter()
+        .all(|element| element.doc.is_empty()));
+}
+
+#[test]
+fn ast_from_cvl_snippet() {
+    let rule = Ast::from_cvl_snippet("rule", "rule foo(uint x) { assert x >= 0; }").unwrap();
+    assert_eq!(rule.name(), Some("foo"));
+    assert_eq!(rule.to_string(), "rule");
+
+    let ghost = Ast::from_cvl_snippet("ghost", "ghost mathint sum;").unwrap();
+    assert_matches!(ghost, Ast::GhostMapping { .. });
+
+    let error = |kind, src| Ast::from_cvl_snippet(kind, src).unwrap_err().to_string();
+    assert_eq!(
+        error("rule", "invariant inv() true;"),
+        "expected rule, found invariant"
+    );
+    assert_eq!(error("rule", ""), "no declaration found");
+    assert_eq!(
+        error("rule", "rule a() { } rule b() { }"),
+        "expected a single declaration, found 2"
+    );
+    assert_eq!(
+        error("rule", "/// @notice doc\nrule a() { }"),
+        "expected a declaration without documentation"
+    );
+    assert_eq!(
+        error("rule", "//// freeform"),
+        "expected a declaration, found a comment"
+    );
+}
+
+#[test]
+fn attachment_policy_with_blank_lines() {
+    use crate::parse::builder::AttachmentPolicy;
+    use crate::util::ByteSpan;
+
+    let src = indoc! {"
+        /// @notice documented
+
+        // a plain comment
+
+        rule foo() { }
+    "};
+    let build = |policy| {
+        Builder::new(src)
+            .with_attachment_policy(policy)
+            .build()
+            .unwrap()
+    };
+
+    let permissive = build(AttachmentPolicy::Permissive)
+        .into_iter()
+        .exactly_one()
+        .unwrap();
+    assert_eq!(permissive.doc.len(), 1);
+    assert!(permissive.doc_gaps.is_empty());
+
+    // rejected documentation is kept, detached, along with the gaps before the rule
+    let [detached, adjacent] = build(AttachmentPolicy::Adjacent).try_into().unwrap();
+    assert_eq!(detached.ast, Ast::Detached);
+    assert_eq!(detached.doc[0].description, "documented");
+    assert_eq!(detached.doc_gaps.len(), 2);
+    assert!(adjacent.doc.is_empty());
+    assert_eq!(adjacent.doc_span, None);
+
+    let [detached, too_far] = build(AttachmentPolicy::AttachWithWarning(1))
+        .try_into()
+        .unwrap();
+    assert_eq!(detached.ast, Ast::Detached);
+    assert_eq!(detached.doc_gaps.len(), 2);
+    assert!(too_far.doc.is_empty());
+
+    // the comment line is not part of either gap
+    let attached = build(AttachmentPolicy::AttachWithWarning(2))
+        .into_iter()
+        .exactly_one()
+        .unwrap();
+    assert_eq!(attached.doc.len(), 1);
+    let gaps = attached
+        .doc_gaps
+        .iter()
+        .map(|gap| &src[gap.to_byte_span(src).unwrap()])
+        .collect_vec();
+    assert_eq!(gaps, ["\n", "\n"]);
+
+    let adjacent_src = "/// @notice documented\n// a plain comment\nrule foo() { }";
+    let element = Builder::new(adjacent_src)
+        .with_attachment_policy(AttachmentPolicy::Adjacent)
+        .build()
+        .unwrap();
+    assert_eq!(element.into_iter().exactly_one().unwrap().doc.len(), 1);
+}
+
+#[cfg(feature = "lsp")]
+#[test]
+fn doc_element_gap_diagnostic() {
+    use crate::parse::builder::AttachmentPolicy;
+
+    let src = indoc! {"
+        /**
+         * @notice documented
+         */
+
+
+        // a plain comment
+        invariant inv() true;
+    "};
+    let element = Builder::new(src)
+        .with_attachment_policy(AttachmentPolicy::AttachWithWarning(3))
+        .build()
+        .unwrap()
+        .into_iter()
+        .exactly_one()
+        .unwrap();
+
+    let converter = RangeConverter::new(Rope::from_str(src));
+    let diagnostics = element.enumerate_diagnostics(converter);
+    let diagnostic = diagnostics
+        .iter()
+        .filter(|diag| diag.code == Some(NumberOrString::String("doc-element-gap".into())))
+        .exactly_one()
+        .unwrap();
+
+    assert_eq!(
+        diagnostic.message,
+        "2 blank lines between the documentation and the invariant"
+    );
+    assert_eq!(diagnostic.range.start.line, 3);
+    assert_eq!(diagnostic.range.start.character, 0);
+    assert_eq!(diagnostic.range.end.line, 5);
+    assert_eq!(diagnostic.range.end.character, 0);
+
+    let [detached, invariant] = Builder::new(src)
+        .with_attachment_policy(AttachmentPolicy::AttachWithWarning(1))
+        .build()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert!(invariant.doc.is_empty());
+
+    let converter = RangeConverter::new(Rope::from_str(src));
+    let diagnostics = detached
+        .enumerate_diagnostics(converter)
+        .into_iter()
+        .map(|diagnostic| {
+            (
+                diagnostic.code,
+                diagnostic.message,
+                diagnostic.range.start.line,
+            )
+        })
+        .collect_vec();
+    let code = |code: &str| Some(NumberOrString::String(code.into()));
+    assert_eq!(
+        diagnostics,
+        [
+            (
+                code("no-associated-element"),
+                "documentation has no associated element".to_string(),
+                0
+            ),
+            (
+                code("doc-element-gap"),
+                "2 blank lines between the documentation and the next declaration".to_string(),
+                3
+            ),
+        ]
+    );
+}
+
+#[test]
+fn rule_and_invariant_attributes() {
+    let without = parse_exactly_one("rule foo(uint x) { assert true; }").unwrap();
+    assert_matches!(&without.ast, Ast::Rule { attributes, .. } if attributes.is_empty());
+    assert_eq!(without.ast.attribute("description"), None);
+
+    let src = indoc! {r#"
+        /// @notice documented
+        rule foo(method f) filtered { f -> !f.isView } description "checks \"foo\"" good_description "a\\b" sanity_marker "" {
+            assert true;
+        }
+    "#};
+    let with_attributes = parse_exactly_one(src).unwrap();
+    assert_eq!(with_attributes.doc.len(), 1);
+    assert_eq!(
+        with_attributes.ast.attribute("description"),
+        Some(r#"checks "foo""#)
+    );
+    assert_eq!(
+        with_attributes.ast.attribute("good_description"),
+        Some(r"a\b")
+    );
+    assert_eq!(with_attributes.ast.attribute("sanity_marker"), Some(""));
+    assert_eq!(with_attributes.ast.filters(), Some("{ f -> !f.isView }"));
+    assert_eq!(with_attributes.ast.block(), Some("assert true;"));
+
+    let invariant = parse_exactly_one(r#"invariant inv() x > 0 description "positive";"#).unwrap();
+    assert_eq!(invariant.ast.invariant(), Some("x > 0"));
+    assert_eq!(invariant.ast.attribute("description"), Some("positive"));
+
+    let with_proof = parse_exactly_one(r#"invariant inv() x > 0 tag "a" { }"#).unwrap();
+    assert_eq!(with_proof.ast.invariant(), Some("x > 0"));
+    assert_eq!(with_proof.ast.attribute("tag"), Some("a"));
+}
+
+#[cfg(feature = "lsp")]
+#[test]
+fn compute_edit_diff_single_edit() {
+    use crate::util::compute_edit_diff;
+    use lsp_types::{Position, Range};
+
+    assert!(compute_edit_diff("same", "same").is_empty());
+
+    let old = "/**\n * @notice  old text\n */";
+    let new = "/**\n * @notice new text\n */";
+    let edit = compute_edit_diff(old, new)
+        .into_iter()
+        .exactly_one()
+        .unwrap();
+    assert_eq!(
+        edit.range,
+        Range::new(Position::new(1, 11), Position::new(1, 15))
+    );
+    assert_eq!(edit.new_text, "new");
+
+    // repeated characters at the boundary must not be counted twice
+    let edit = compute_edit_diff("aaa", "aaaa")
+        .into_iter()
+        .exactly_one()
+        .unwrap();
+    assert_eq!(
+        edit.range,
+        Range::new(Position::new(0, 3), Position::new(0, 3))
+    );
+    assert_eq!(edit.new_text, "a");
+
+    let apply = |old: &str, new: &str| {
+        let edit = compute_edit_diff(old, new)
+            .into_iter()
+            .exactly_one()
+            .unwrap();
+        let converter = RangeConverter::new(Rope::from_str(old));
+        let span = converter.to_span(edit.range);
+        let mut chars = old.chars().collect_vec();
+        chars.splice(span, edit.new_text.chars());
+        chars.into_iter().collect::<String>()
+    };
+    for (old, new) in [
+        ("", "abc"),
+        ("abc", ""),
+        ("x\ny\nz", "x\nz"),
+        ("héllo", "hello"),
+    ] {
+        assert_eq!(apply(old, new), new);
+    }
+}
+
+#[test]
+fn tag_kind_as_keyword() {
+    assert_eq!(TagKind::Notice.as_keyword(), "@notice");
+    assert_eq!(TagKind::Return.as_keyword(), "@return");
+
+    for kind in [TagKind::Title, TagKind::Formula, TagKind::Satisfies] {
+        assert_eq!(TagKind::try_from(kind.as_keyword().as_str()).unwrap(), kind);
+    }
+}
+
+#[cfg(feature = "lsp")]
+#[test]
+fn empty_tag_diagnostic() {
+    let src = indoc! {"
+        /**
+         * @notice checks the sum
+         * @
+         * @dev a @ mid-line is fine
+         */
+        rule sum() { assert true; }
+    "};
+    let element = parse_exactly_one(src).unwrap();
+
+    // the `@` line continues the previous tag, rather than being a tag of its own
+    assert_eq!(element.doc.len(), 2);
+    assert_eq!(element.doc[0].description, "checks the sum\n@");
+
+    let converter = RangeConverter::new(Rope::from_str(src));
+    let empty_tags = element
+        .enumerate_diagnostics(converter)
+        .into_iter()
+        .filter(|diag| diag.code == Some(NumberOrString::String("empty-tag".into())))
+        .collect_vec();
+
+    assert_eq!(empty_tags.len(), 1);
+    assert_eq!(empty_tags[0].range.start.line, 1);
+}
+
+#[test]
+fn ghost_blocks_with_trailing_semicolon() {
+    let src = indoc! {"
+        /// @notice a
+        ghost uint256 a;
+
+        /// @notice b
+        ghost uint256 b { axiom b == 0; }
+
+        /// @notice c
+        ghost uint256 c { axiom c == 0; };
+
+        /// @notice d
+        ghost mapping(address => uint) d { axiom true; };
+
+        /// @notice f
+        ghost f(uint) returns uint { axiom true; };
+
+        /// @notice r
+        rule r() { assert true; }
+    "};
+    let elements = Builder::new(src).build().unwrap();
+
+    let names_and_docs = elements
+        .iter()
+        .map(|element| {
+            (
+                element.ast.name().unwrap(),
+                element.doc[0].description.as_str(),
+            )
+        })
+        .collect_vec();
+    assert_eq!(
+        names_and_docs,
+        [
+            ("a", "a"),
+            ("b", "b"),
+            ("c", "c"),
+            ("d", "d"),
+            ("f", "f"),
+            ("r", "r")
+        ]
+    );
+    assert_eq!(elements[2].ast.block(), Some("{ axiom c == 0; }"));
+    assert!(elements[2].source_text().ends_with("};"));
+
+    #[cfg(feature = "lsp")]
+    {
+        let redundant_semicolons = elements
+            .iter()
+            .map(|element| {
+                let converter = RangeConverter::new(Rope::from_str(src));
+                element
+                    .enumerate_diagnostics(converter)
+                    .into_iter()
+                    .filter(|diag| {
+                        diag.code == Some(NumberOrString::String("redundant-semicolon".into()))
+                    })
+                    .map(|diag| (diag.range.start.line, diag.range.start.character))
+                    .collect_vec()
+            })
+            .collect_vec();
+
+        let expected: [&[(u32, u32)]; 6] = [&[], &[], &[(7, 33)], &[(10, 48)], &[(13, 42)], &[]];
+        assert_eq!(redundant_semicolons, expected);
+    }
+}
+
+#[test]
+fn tags_are_ordered_by_kind_then_position() {
+    assert!(TagKind::Title < TagKind::Notice);
+    assert!(TagKind::Formula < TagKind::Requirement);
+
+    let src = indoc! {"
+        /// @param y second
+        /// @notice checks
+        /// @param x first
+        /// @title Sum
+        rule sum(uint x, uint y) { assert true; }
+    "};
+    let mut doc = parse_exactly_one(src).unwrap().doc;
+    doc.sort();
+
+    let sorted = doc
+        .iter()
+        .map(|tag| (tag.kind.clone(), tag.description.as_str()))
+        .collect_vec();
+    assert_eq!(
+        sorted,
+        [
+            (TagKind::Title, "Sum"),
+            (TagKind::Notice, "checks"),
+            (TagKind::Param, "y second"),
+            (TagKind::Param, "x first"),
+        ]
+    );
+}
+
+#[test]
+fn with_tag_appends_tags() {
+    let built = CvlElement::default()
+        .with_tag(DocumentationTag::title("Sum").unwrap())
+        .with_tag(DocumentationTag::notice("checks the sum"));
+    assert_eq!(built.ast, Ast::Detached);
+    assert_eq!(
+        built.doc,
+        [
+            DocumentationTag::title("Sum").unwrap(),
+            DocumentationTag::notice("checks the sum")
+        ]
+    );
+    assert_eq!(built.source_text(), "");
+
+    let element = parse_exactly_one("/// @notice checks\nrule sum(uint x) { }")
+        .unwrap()
+        .with_tag(DocumentationTag::param("x", "the summand").unwrap());
+    assert_eq!(element.doc.len(), 2);
+    assert_eq!(element.doc[1].param_name(), Some("x"));
+
+    let freeform = parse_exactly_one("//// some text").unwrap();
+    let span = freeform.element_span.clone();
+    let converted = freeform
+        .with_tag(DocumentationTag::notice("converted"))
+        .with_tag(DocumentationTag::dev("twice"));
+    assert_eq!(converted.ast, Ast::Detached);
+    assert_eq!(
+        converted.doc,
+        [
+            DocumentationTag::notice("converted"),
+            DocumentationTag::dev("twice")
+        ]
+    );
+    assert_eq!(converted.doc_span, Some(span.clone()));
+    assert_eq!(converted.element_span, span);
+}
+
+#[cfg(feature = "lsp")]
+#[test]
+fn render_diagnostics() {
+    use crate::diagnostics::{render, render_all, RenderOptions};
+    use lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range};
+
+    let src = "rule foo() {\n\tassert x;\n}\n";
+    let diag = |range: Range, severity, code: Option<&str>, message: &str| Diagnostic {
+        range,
+        severity: Some(severity),
+        code: code.map(|code| NumberOrString::String(code.to_string())),
+        message: message.to_string(),
+        ..Default::default()
+    };
+    let range = |start: (u32, u32), end: (u32, u32)| {
+        Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+    };
+    let opts = RenderOptions::default();
+
+    let single_line = diag(
+        range((1, 8), (1, 9)),
+        DiagnosticSeverity::ERROR,
+        Some("unknown-name"),
+        "no such variable",
+    );
+    assert_eq!(
+        render(&single_line, src, "a.spec", &opts),
+        indoc! {"
+            error[unknown-name]: no such variable
+             --> a.spec:2:9
+              |
+            2 | \tassert x;
+              | \t       ^
+        "}
+    );
+
+    let multi_line = diag(
+        range((0, 5), (2, 1)),
+        DiagnosticSeverity::WARNING,
+        None,
+        "rule is undocumented",
+    );
+    assert_eq!(
+        render(&multi_line, src, "a.spec", &opts),
+        indoc! {"
+            warning: rule is undocumented
+             --> a.spec:1:6
+              |
+            1 | rule foo() {
+              |      ^^^^^^^...
+        "}
+    );
+
+    let end_of_file = diag(
+        range((3, 0), (3, 0)),
+        DiagnosticSeverity::INFORMATION,
+        None,
+        "missing newline",
+    );
+    assert_eq!(
+        render(&end_of_file, src, "a.spec", &opts),
+        indoc! {"
+            info: missing newline
+             --> a.spec:4:1
+              |
+            4 | 
+              | ^
+        "}
+    );
+
+    // inverted ranges are rendered as if their ends were swapped
+    for (start, end) in [((1, 9), (1, 8)), ((2, 1), (0, 5))] {
+        let inverted = diag(
+            range(start, end),
+            DiagnosticSeverity::ERROR,
+            None,
+            "inverted",
+        );
+        let ordered = diag(
+            range(end, start),
+            DiagnosticSeverity::ERROR,
+            None,
+            "inverted",
+        );
+        assert_eq!(
+            render(&inverted, src, "a.spec", &opts),
+            render(&ordered, src, "a.spec", &opts)
+        );
+    }
+
+    let colored = render(&single_line, src, "a.spec", &RenderOptions { ansi: true });
+    assert!(colored.starts_with("\x1b[1;31merror[unknown-name]\x1b[0m: no such variable\n"));
+
+    let all = render_all(
+        &[end_of_file, single_line.clone(), multi_line, single_line],
+        src,
+        "a.spec",
+        &opts,
+    );
+    let headers = all.lines().filter(|line| line.contains(": ")).collect_vec();
+    assert_eq!(
+        headers,
+        [
+            "warning: rule is undocumented",
+            "error[unknown-name]: no such variable",
+            "error[unknown-name]: no such variable",
+            "info: missing newline",
+        ]
+    );
+    assert!(all.ends_with("\n2 errors, 1 warning, 1 info\n"));
+    assert_eq!(render_all(&[], src, "a.spec", &opts), "no diagnostics\n");
+}
+
+#[test]
+fn slashed_freeform_with_tags_as_documentation() {
+    use crate::parse::builder::ParseConfig;
+
+    let src = indoc! {"
+        //// ## Section
+        //// about sums
+
+        //// @notice checks the sum
+        //// @param x the summand
+        rule sum(uint x) { assert true; }
+    "};
+
+    let default = Builder::new(src).build().unwrap();
+    assert_eq!(default.len(), 3);
+    assert!(default[2].doc.is_empty());
+
+    let config = ParseConfig {
+        slash_tags_as_documentation: true,
+        ..Default::default()
+    };
+    let elements = Builder::new(src).with_config(config).build().unwrap();
+    assert_eq!(elements.len(), 2);
+    assert_matches!(&elements[0].ast, Ast::FreeFormComment { text, .. } if text == "## Section\nabout sums");
+
+    let rule = &elements[1];
+    assert_eq!(rule.ast.name(), Some("sum"));
+    let tags = rule
+        .doc
+        .iter()
+        .map(|tag| (tag.kind.clone(), tag.description.as_str()))
+        .collect_vec();
+    assert_eq!(
+        tags,
+        [
+            (TagKind::Notice, "checks the sum"),
+            (TagKind::Param, "x the summand")
+        ]
+    );
+    assert!(rule.source_text().starts_with("//// @notice"));
+}
+
+#[test]
+fn fingerprints_ignore_whitespace_and_spans() {
+    use crate::fingerprint::{baseline, changed, compute, Fingerprint};
+
+    let old_src = indoc! {"
+        /// @notice checks the sum
+        rule sum() { assert true; }
+
+        /// @notice checks the product
+        rule product() { assert true; }
+
+        rule undocumented() { assert true; }
+    "};
+    let new_src = indoc! {"
+        /**
+         * @notice   checks the
+         *   sum
+         */
+        rule sum() { assert false; }
+
+        /// @notice checks the quotient
+        rule product() { assert true; }
+
+        /// @notice a new rule
+        rule added() { assert true; }
+
+        rule undocumented() { assert true; }
+    "};
+    let old = Builder::new(old_src).build().unwrap();
+    let new = Builder::new(new_src).build().unwrap();
+
+    assert_eq!(compute(&old[0]), compute(&new[0]));
+    assert_ne!(compute(&old[1]), compute(&new[1]));
+
+    // the hash is part of the baseline format, so it must not change
+    assert_eq!(compute(&old[0]), Fingerprint(0xb61aa89151d0ff86));
+    assert_eq!(compute(&old[0]).to_string(), "b61aa89151d0ff86");
+
+    let baseline = baseline(&old);
+    assert_eq!(
+        baseline.fingerprints.keys().collect_vec(),
+        ["rule product", "rule sum"]
+    );
+
+    let changed_names = changed(&new, &baseline)
+        .into_iter()
+        .map(|element| element.ast.name().unwrap())
+        .collect_vec();
+    assert_eq!(changed_names, ["product", "added"]);
+}
+
+#[test]
+fn callable_elements() {
+    let src = indoc! {"
+        rule r() { assert true; }
+        invariant inv() true;
+        function f() { }
+        ghost uint g;
+        ghost mapping(uint => uint) m;
+        definition d() returns bool = true;
+        methods { function balanceOf(address) external returns (uint) envfree; }
+    "};
+    let callable = Builder::new(src)
+        .build()
+        .unwrap()
+        .iter()
+        .map(|element| element.ast.is_callable())
+        .collect_vec();
+
+    assert_eq!(callable, [true, true, true, false, false, false, false]);
+}
+
+#[test]
+fn methods_params_document_receivers() {
+    let src = indoc! {"
+        /**
+         * @notice summaries
+         * @param token the linked ERC20
+         * @param _ any contract
+         * @param vault not mentioned
+         */
+        methods {
+            function token.balanceOf(address) external returns (uint) envfree;
+            function _.transfer(address, uint) external => DISPATCHER(true);
+            function mytoken.totalSupply() external returns (uint);
+        }
+    "};
+    let element = parse_exactly_one(src).unwrap();
+
+    let pairs = element
+        .methods_param_docs()
+        .unwrap()
+        .into_iter()
+        .map(|(tag, entries)| (tag.param_name().unwrap(), entries))
+        .collect_vec();
+    assert_eq!(
+        pairs,
+        [
+            (
+                "token",
+                vec!["function token.balanceOf(address) external returns (uint) envfree"]
+            ),
+            (
+                "_",
+                vec!["function _.transfer(address, uint) external => DISPATCHER(true)"]
+            ),
+            ("vault", vec![]),
+        ]
+    );
+
+    let rule = parse_exactly_one("/// @param x doc\nrule r(uint x) { }").unwrap();
+    assert_eq!(rule.methods_param_docs(), None);
+
+    #[cfg(feature = "lsp")]
+    {
+        use crate::diagnostics::LintConfig;
+
+        let messages = |config: &LintConfig| {
+            let converter = RangeConverter::new(Rope::from_str(src));
+            element
+                .enumerate_diagnostics_with(converter, config)
+                .into_iter()
+                .map(|diag| diag.message)
+                .collect_vec()
+        };
+
+        assert_eq!(
+            messages(&LintConfig::default()),
+            ["no entry of the methods block mentions vault"]
+        );
+
+        let strict = LintConfig {
+            strict_methods_params: true,
+            ..Default::default()
+        };
+        let strict_messages = messages(&strict);
+        assert_eq!(
+            strict_messages
+                .iter()
+                .filter(|message| *message == "this tag is unsupported for methods blocks")
+                .count(),
+            3
+        );
+    }
+}
+
+#[test]
+fn deserialize_tag_kind_with_or_without_at_sign() {
+    use serde::de::{value::Error, IntoDeserializer};
+    use serde::Deserialize;
+
+    let deserialize = |s: &str| TagKind::deserialize(s.into_deserializer());
+
+    for s in ["notice", "@notice", "Notice"] {
+        assert_eq!(deserialize(s), Ok::<_, Error>(TagKind::Notice));
+    }
+    assert_eq!(deserialize("@satisfies"), Ok(TagKind::Satisfies));
+
+    let error = deserialize("@unknown").unwrap_err();
+    assert_eq!(error.to_string(), "unrecognized tag: unknown");
+    assert!(deserialize("@").is_err());
+}
+
+#[test]
+fn simple_range_json() {
+    use crate::util::{SimplePosition, SimpleRange};
+
+    let range = SimpleRange {
+        start: SimplePosition {
+            line: 1,
+            character: 2,
+        },
+        end: SimplePosition {
+            line: 3,
+            character: 4,
+        },
+    };
+    let json = r#"{"start":{"line":1,"character":2},"end":{"line":3,"character":4}}"#;
+
+    assert_eq!(serde_json::to_string(&range).unwrap(), json);
+    assert_eq!(serde_json::from_str::<SimpleRange>(json).unwrap(), range);
+
+    #[cfg(feature = "lsp")]
+    {
+        let lsp_range = lsp_types::Range::from(range);
+        assert_eq!(serde_json::to_string(&lsp_range).unwrap(), json);
+    }
+}
+
+#[test]
+fn complexity_scores() {
+    use crate::coverage::{complexity_score, max_score};
+
+    let src = indoc! {"
+        /// @title Transfer
+        /// @notice moves tokens
+        /// @param amount the amount
+        /// @param amount.low repeated parameters count once
+        /// @formula balance == balance - amount
+        rule transfer(uint amount, address to) { assert true; }
+
+        /// @notice the supply
+        /// @return the total
+        /// @formula ignored, functions do not support formulas
+        function supply() returns uint { return 0; }
+
+        rule undocumented() { assert true; }
+
+        /// @notice method parameters are not scored
+        /// @param f the method
+        /// @param x the amount
+        rule parametric(method f, method g, uint x) { assert true; }
+
+        //// ## a freeform comment
+    "};
+
+    let elements = Builder::new(src).build().unwrap();
+    let scores = elements.iter().map(complexity_score).collect_vec();
+    let max_scores = elements.iter().map(max_score).collect_vec();
+
+    assert_eq!(scores, [6, 3, 0, 3, 0]);
+    assert_eq!(max_scores, [8, 4, 6, 7, 0]);
+}
+
+#[cfg(feature = "lsp")]
+#[test]
+fn return_shadows_param_diagnostic() {
+    let src = indoc! {"
+        /// @param balance the balance
+        /// @return balance, plus interest
+        function withInterest(uint balance) returns uint { return balance; }
+
+        /// @param a the first
+        /// @return a sum
+        function sum(uint a, uint b) returns uint { return a + b; }
+
+        /// @return balance
+        function balance() returns uint { return 0; }
+
+        /// @param balance the balance
+        /// @return balance
+        rule check(uint balance) { assert true; }
+    "};
+    let elements = Builder::new(src).build().unwrap();
+
+    let shadowing = |element: &CvlElement| {
+        let converter = RangeConverter::new(Rope::from_str(src));
+        element
+            .enumerate_diagnostics(converter)
+            .into_iter()
+            .filter(|diag| diag.code == Some(NumberOrString::String("return-shadows-param".into())))
+            .collect_vec()
+    };
+
+    let diags = shadowing(&elements[0]);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].range.start.line, 1);
+    assert_eq!(
+        diags[0].message,
+        "@return starts with the parameter name balance: rename the return value, or remove the leading word"
+    );
+
+    // `a` is a stop word, and the other elements lack either parameters or a return value
+    assert!(elements[1..]
+        .iter()
+        .all(|element| shadowing(element).is_empty()));
+}
+
+#[test]
+fn query_documented_elements() {
+    use crate::query::{documented, functions, invariants, rules};
+
+    let src = indoc! {"
+        //! @title the spec
+
+        /// @notice a rule
+        rule transfer(uint amount) filtered { f -> !f.isView } { assert true; }
+
+        rule undocumented() { assert true; }
+
+        //// ## a freeform comment
+
+        /// @notice an invariant
+        strong invariant positive(address a) balanceOf(a) >= 0;
+
+        /// @notice a function
+        function double(uint x) returns uint { return 2 * x; }
+    "};
+    let docs = Builder::new(src).build().unwrap();
+
+    let kinds = documented(&docs)
+        .map(|(ast, _)| ast.to_string())
+        .collect_vec();
+    assert_eq!(kinds, ["rule", "invariant", "function"]);
+
+    let all_rules = rules(&docs).collect_vec();
+    assert_eq!(all_rules.len(), 1);
+    let rule = all_rules[0];
+    assert_eq!(rule.name, "transfer");
+    assert_eq!(rule.params[0].name, "amount");
+    assert_eq!(rule.filters, Some("{ f -> !f.isView }"));
+    assert_eq!(rule.doc[0].description, "a rule");
+
+    let all_invariants = invariants(&docs).collect_vec();
+    assert_eq!(all_invariants.len(), 1);
+    let invariant = all_invariants[0];
+    assert_eq!(invariant.name, "positive");
+    assert_eq!(invariant.invariant, "balanceOf(a) >= 0;");
+    assert_eq!(invariant.filters, None);
+
+    let all_functions = functions(&docs).collect_vec();
+    assert_eq!(all_functions.len(), 1);
+    let function = all_functions[0];
+    assert_eq!(function.name, "double");
+    assert_eq!(function.returns, Some("uint"));
+    assert!(std::ptr::eq(function.element, &docs[docs.len() - 1]));
+}
+
+#[test]
+fn tag_aliases() {
+    use std::collections::HashMap;
+
+    let src = indoc! {"
+        /// @precondition the caller is approved
+        /// @invariant_ref @dev see the inline tag
+        /// @unknown stays in the description
+        rule transfer() { assert true; }
+    "};
+    let aliases = HashMap::from([
+        ("@precondition".to_string(), "notice".to_string()),
+        ("invariant_ref".to_string(), "@formula".to_string()),
+    ]);
+
+    let element = Builder::new(src)
+        .with_tag_aliases(&aliases)
+        .unwrap()
+        .build()
+        .unwrap()
+        .into_iter()
+        .exactly_one()
+        .unwrap();
+
+    let doc = element
+        .doc
+        .iter()
+        .map(|tag| (tag.kind.clone(), tag.description.as_str()))
+        .collect_vec();
+    assert_eq!(
+        doc,
+        [
+            (TagKind::Notice, "the caller is approved"),
+            (TagKind::Formula, ""),
+            (
+                TagKind::Dev,
+                "see the inline tag\n@unknown stays in the description"
+            ),
+        ]
+    );
+
+    // without the aliases, the tags are plain text
+    let unaliased = parse_exactly_one(src).unwrap();
+    assert_eq!(unaliased.doc.len(), 2);
+    assert_eq!(unaliased.doc[0].kind, TagKind::Notice);
+
+    let invalid = |alias: &str, target: &str| {
+        let aliases = HashMap::from([(alias.to_string(), target.to_string())]);
+        Builder::new(src).with_tag_aliases(&aliases).err().unwrap()
+    };
+    assert_eq!(
+        invalid("precondition", "unexpected").to_string(),
+        "alias @precondition is for an unrecognized tag: unexpected"
+    );
+    assert_eq!(
+        invalid("@notice", "dev").to_string(),
+        "cannot alias the built-in tag @notice"
+    );
+    assert_eq!(
+        invalid("two words", "dev").to_string(),
+        "invalid tag alias: \"two words\""
+    );
+}
+
+#[test]
+fn strip_tags() {
+    let src = indoc! {"
+        /// @title Transfer
+        /// @notice moves tokens
+        /// @dev internal detail
+        /// @param amount the amount
+        /// @dev another internal detail
+        rule transfer(uint amount) { assert true; }
+    "};
+    let element = parse_exactly_one(src).unwrap();
+
+    let stripped = element.clone().strip_tags(&[TagKind::Dev, TagKind::Title]);
+    let kinds = stripped
+        .doc
+        .iter()
+        .map(|tag| tag.kind.clone())
+        .collect_vec();
+    assert_eq!(kinds, [TagKind::Notice, TagKind::Param]);
+    assert_eq!(stripped.doc_span, element.doc_span);
+
+    assert_eq!(element.clone().strip_tags(&[]), element);
+}
+
+#[test]
+fn leading_directives() {
+    use crate::parse::directives;
+
+    let file_doc = indoc! {"
+        //! @title the spec
+        //! @notice checks transfers
+
+        /// @notice a rule
+        rule transfer() { assert true; }
+    "};
+
+    for lines in 0..=3 {
+        let header = (0..lines)
+            .map(|i| format!("// certora: --flag_{i}\n"))
+            .join("\n");
+        let src = format!("{header}{file_doc}");
+
+        let found = directives(&src);
+        let texts = found.iter().map(|(_, text)| text.as_str()).collect_vec();
+        let expected = (0..lines)
+            .map(|i| format!("certora: --flag_{i}"))
+            .collect_vec();
+        assert_eq!(texts, expected);
+
+        for (span, text) in &found {
+            let raw = src
+                .chars()
+                .skip(span.start)
+                .take(span.len())
+                .collect::<String>();
+            assert_eq!(raw, format!("// {text}"));
+        }
+
+        let elements = Builder::new(&src).build().unwrap();
+        let kinds = elements
+            .iter()
+            .map(|element| element.ast.to_string())
+            .collect_vec();
+        assert_eq!(kinds, ["file", "rule"]);
+        assert_eq!(elements[0].doc.len(), 2);
+        assert_eq!(elements[0].doc[0].description, "the spec");
+    }
+
+    // comments after the first declaration are not directives
+    let src = "rule a() { assert true; }\n// certora: --flag\n";
+    assert!(directives(src).is_empty());
+
+    // reading stops at the first line which is not a plain comment, even an unterminated one
+    let src = "  // first\r\n\n// second\n/* block */\n// third\nrule a() {";
+    assert_eq!(
+        directives(src),
+        [(2..10, "first".to_string()), (13..22, "second".to_string())]
+    );
+}
+
+#[test]
+fn compose_doc_comment_parser() {
+    use crate::parse::doc_comment_parser;
+    use chumsky::prelude::*;
+
+    let src = "  /// @notice adds\n/// @param x the value\nadd;/** @dev starred */sub;";
+
+    let documented_name = doc_comment_parser()
+        .padded()
+        .then(text::ident())
+        .then_ignore(just(';'));
+    let (parsed, errors) = documented_name
+        .repeated()
+        .then_ignore(end())
+        .parse_recovery(src);
+    assert!(errors.is_empty());
+
+    let parsed = parsed.unwrap();
+    let names = parsed.iter().map(|(_, name)| name.as_str()).collect_vec();
+    assert_eq!(names, ["add", "sub"]);
+
+    let (add_doc, _) = &parsed[0];
+    assert_eq!(add_doc.len(), 2);
+    assert_eq!(add_doc[1].kind, TagKind::Param);
+    assert_eq!(add_doc[1].description, "x the value");
+
+    // spans are relative to the input of the composed parser
+    let slice = |span: &crate::util::Span| {
+        src.chars()
+            .skip(span.start)
+            .take(span.len())
+            .collect::<String>()
+    };
+    assert_eq!(slice(&add_doc[0].span), "/// @notice adds\n");
+
+    let (sub_doc, _) = &parsed[1];
+    assert_eq!(sub_doc[0].kind, TagKind::Dev);
+    assert_eq!(sub_doc[0].description, "starred");
+    assert!(slice(&sub_doc[0].span).contains("@dev starred"));
+    // the line and column of the comment are unknown
+    assert_eq!(sub_doc[0].range, None);
+
+    assert!(doc_comment_parser().parse("//// freeform").is_err());
+}
+
+#[cfg(feature = "lsp")]
+#[test]
+fn freeform_header_diagnostics() {
+    use crate::diagnostics::{check_collection, CollectionConfig};
+
+    let src = indoc! {"
+        //// # Token
+
+        //// ## Transfers
+        //// how transfers work
+
+        //// ### Invariants
+
+        //// ## Approvals
+
+        //// ### invariants
+
+        //// ## transfers
+
+        //// #### Too deep
+
+        //// ## Approvals
+
+        //// not a header
+    "};
+    let docs = Builder::new(src).build().unwrap();
+
+    let headers = docs
+        .iter()
+        .map(|element| element.freeform_header())
+        .collect_vec();
+    assert_eq!(headers[1], Some((2, "Transfers")));
+    assert_eq!(headers[8], None);
+
+    let codes = |config: &CollectionConfig| {
+        check_collection(&docs, config)
+            .into_iter()
+            .map(|(i, diag)| {
+                let Some(NumberOrString::String(code)) = diag.code else {
+                    panic!("diagnostics have codes");
+                };
+                (i, code)
+            })
+            .collect_vec()
+    };
+
+    // `### invariants` repeats a header under another parent, which is allowed, while
+    // `## transfers` repeats `## Transfers` under the same parent, as does `## Approvals`
+    assert_eq!(
+        codes(&CollectionConfig::default()),
+        [
+            (5, "duplicate-header".to_string()),
+            (6, "header-level-skip".to_string()),
+            (7, "duplicate-header".to_string()),
+        ]
+    );
+
+    let only_skips = CollectionConfig {
+        duplicate_header: false,
+        ..Default::default()
+    };
+    assert_eq!(codes(&only_skips), [(6, "header-level-skip".to_string())]);
+
+    let diags = check_collection(&docs, &CollectionConfig::default());
+    assert_eq!(diags[1].1.message, "header skips from level 2 to level 4");
+    assert_eq!(diags[1].1.range.start.line, 13);
+}
+
+#[test]
+fn params_named_count() {
+    let src = indoc! {"
+        /// @notice a rule
+        rule transfer(uint amount, address to) { assert true; }
+
+        /// @notice a ghost
+        ghost balances(address, uint) returns uint;
+    "};
+    let elements = Builder::new(src).build().unwrap();
+
+    assert_eq!(elements[0].ast.params_named_count(), 2);
+    assert_eq!(elements[1].ast.params_named_count(), 0);
+
+    let ast = Ast::CVLMacro {
+        name: "m".to_string(),
+        params: vec![Param::new("uint", "x"), Param::new("uint", "")],
+        body: "x".to_string(),
+    };
+    assert_eq!(ast.params_named_count(), 1);
+    assert_eq!(ast.params().map(|params| params.len()), Some(2));
+}
+
+#[test]
+fn element_name_and_kind() {
+    use crate::ElementKind;
+
+    let src = indoc! {"
+        //! @title the spec
+
+        /// @notice a function ghost
+        ghost sum(address) returns uint;
+
+        /// @notice a mapping ghost
+        ghost mapping(address => uint) balances;
+
+        /// @notice a rule
+        rule transfer() { assert true; }
+
+        //// ## a freeform comment
+    "};
+    let elements = Builder::new(src).build().unwrap();
+
+    let names = elements.iter().map(CvlElement::element_name).collect_vec();
+    assert_eq!(
+        names,
+        [None, Some("sum"), Some("balances"), Some("transfer"), None]
+    );
+
+    let kinds = elements.iter().map(CvlElement::element_kind).collect_vec();
+    assert_eq!(
+        kinds,
+        [
+            None,
+            Some(ElementKind::GhostFunction),
+            Some(ElementKind::GhostMapping),
+            Some(ElementKind::Rule),
+            None,
+        ]
+    );
+
+    let freeform = elements.iter().map(CvlElement::is_freeform).collect_vec();
+    assert_eq!(freeform, [false, false, false, false, true]);
+    assert!(elements
+        .iter()
+        .all(|element| element.is_documentation() != element.is_freeform()));
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn element_from_json() {
+    use serde_json::json;
+
+    let src = indoc! {"
+        /// @notice transfers
+        /// @param amount the amount
+        rule transfer(uint amount) { assert true; }
+    "};
+    let element = parse_exactly_one(src).unwrap();
+
+    let value = serde_json::to_value(&element).unwrap();
+    let from_json = CvlElement::try_from(value).unwrap();
+    assert_eq!(from_json.doc, element.doc);
+    assert_eq!(from_json.ast, element.ast);
+    assert_eq!(from_json.span(), element.span());
+    assert!(from_json.src.is_empty());
+
+    let fixture = json!({
+        "doc": [{ "kind": "Dev", "description": "a fixture", "span": { "start": 0, "end": 16 } }],
+        "ast": { "type": "Methods", "block": "" },
+        "element_span": { "start": 17, "end": 29 },
+    });
+    let from_fixture = CvlElement::try_from(fixture).unwrap();
+    assert_eq!(from_fixture.doc[0].kind, TagKind::Dev);
+    assert_eq!(from_fixture.doc_span, None);
+
+    let missing = json!({ "doc": [] });
+    assert_eq!(
+        CvlElement::try_from(missing).unwrap_err().to_string(),
+        "element is missing `ast`, `element_span`"
+    );
+
+    let invalid = json!({ "doc": [], "ast": { "type": "Nonsense" }, "element_span": { "start": 0, "end": 1 } });
+    let error = CvlElement::try_from(invalid).unwrap_err().to_string();
+    assert!(
+        error.starts_with("invalid element: unknown variant `Nonsense`"),
+        "{error}"
+    );
+
+    assert!(CvlElement::try_from(json!([])).is_err());
+}
+
+#[test]
+fn decorative_comments() {
+    use crate::parse::builder::ParseConfig;
+    use crate::render::{to_html, HtmlOptions};
+
+    let src = indoc! {"
+        //// ==========
+
+        ////////////////////////
+
+        //// ----------
+
+        /*** ******* ***/
+
+        /**********************/
+
+        //// ═══════ ─────── ▀▀▀▀
+
+        //// ## Section
+
+        //// see section 2.1 --- below
+
+        rule a() { assert true; }
+    "};
+
+    let elements = Builder::new(src).build().unwrap();
+    let decorative = elements.iter().map(CvlElement::is_decorative).collect_vec();
+    assert_eq!(
+        decorative,
+        [true, true, true, true, true, true, false, false, false]
+    );
+
+    let config = ParseConfig {
+        detect_decorative: false,
+        ..Default::default()
+    };
+    let undetected = Builder::new(src).with_config(config).build().unwrap();
+    assert!(undetected.iter().all(|element| !element.is_decorative()));
+    assert_eq!(undetected.len(), elements.len());
+
+    let html = to_html(&elements, &HtmlOptions::default());
+    assert_eq!(html.matches("<section").count(), 2);
+    assert!(!html.contains("=========="));
+
+    let with_decorative = HtmlOptions {
+        include_decorative: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        to_html(&elements, &with_decorative)
+            .matches("<section")
+            .count(),
+        8
+    );
+}
+
+#[cfg(feature = "cache")]
+#[test]
+fn parse_cache() {
+    use crate::cache::ParseCache;
+    use std::sync::Arc;
+
+    let src_a = "/// @notice a\nrule a() { assert true; }\n";
+    let src_b = "/// @notice b\nrule b() { assert true; }\n";
+    let src_c = "/// @notice c\nrule c() { assert true; }\n";
+
+    let cache = ParseCache::new(2);
+    let a = cache.parse("a.spec", src_a).unwrap();
+    assert!(Arc::ptr_eq(&a, &cache.parse("a.spec", src_a).unwrap()));
+
+    // a changed source is reparsed, and replaces the cached result
+    let changed = cache.parse("a.spec", src_b).unwrap();
+    assert!(!Arc::ptr_eq(&a, &changed));
+    assert_eq!(changed[0].element_name(), Some("b"));
+    assert_eq!(cache.len(), 1);
+
+    // the least recently used file is evicted
+    cache.parse("a.spec", src_a).unwrap();
+    cache.parse("b.spec", src_b).unwrap();
+    cache.parse("a.spec", src_a).unwrap();
+    cache.parse("c.spec", src_c).unwrap();
+    assert!(cache.contains("a.spec"));
+    assert!(!cache.contains("b.spec"));
+    assert!(cache.contains("c.spec"));
+
+    cache.invalidate("a.spec");
+    assert!(!cache.contains("a.spec"));
+    assert_eq!(cache.len(), 1);
+
+    // with every source hashing alike, the sources themselves tell files apart
+    let colliding = ParseCache::with_hash_fn(2, |_| 0);
+    let a = colliding.parse("x.spec", src_a).unwrap();
+    let b = colliding.parse("x.spec", src_b).unwrap();
+    assert_eq!(a[0].element_name(), Some("a"));
+    assert_eq!(b[0].element_name(), Some("b"));
+    assert!(Arc::ptr_eq(&b, &colliding.parse("x.spec", src_b).unwrap()));
+
+    let disabled = ParseCache::new(0);
+    disabled.parse("a.spec", src_a).unwrap();
+    assert!(disabled.is_empty());
+}
+
+#[cfg(feature = "cache")]
+#[test]
+fn parse_cache_concurrent_access() {
+    use crate::cache::ParseCache;
+
+    let cache = ParseCache::new(4);
+    let sources = (0..8)
+        .map(|i| format!("/// @notice rule {i}\nrule r{i}() {{ assert true; }}\n"))
+        .collect_vec();
+
+    std::thread::scope(|scope| {
+        for thread in 0..8 {
+            let cache = &cache;
+            let sources = &sources;
+            scope.spawn(move || {
+                for round in 0..50 {
+                    let i = (thread + round) % sources.len();
+                    let uri = format!("{i}.spec");
+                    let elements = cache.parse(&uri, &sources[i]).unwrap();
+                    assert_eq!(elements[0].element_name(), Some(format!("r{i}").as_str()));
+                }
+            });
+        }
+    });
+
+    assert!(cache.len() <= 4);
+}
+
+#[test]
+fn param_with_backticked_type() {
+    use crate::render::{to_html, HtmlOptions};
+
+    let src = indoc! {"
+        /// @param amount `uint256` the number of tokens
+        /// @param `address` to the recipient
+        /// @param data `bytes` is `calldata`
+        rule transfer(uint256 amount, address to, bytes data) { assert true; }
+    "};
+    let element = parse_exactly_one(src).unwrap();
+
+    // descriptions are kept verbatim
+    assert_eq!(
+        element.doc[0].description,
+        "amount `uint256` the number of tokens"
+    );
+    assert_eq!(element.doc[1].description, "`address` to the recipient");
+
+    let params = element
+        .doc
+        .iter()
+        .map(|tag| {
+            (
+                tag.param_name().unwrap(),
+                tag.param_type(),
+                tag.param_description().unwrap(),
+            )
+        })
+        .collect_vec();
+    assert_eq!(
+        params,
+        [
+            ("amount", Some("uint256"), "the number of tokens"),
+            ("to", Some("address"), "the recipient"),
+            ("data", Some("bytes"), "is `calldata`"),
+        ]
+    );
+
+    #[cfg(feature = "lsp")]
+    {
+        let converter = RangeConverter::new(Rope::from_str(src));
+        assert!(element.enumerate_diagnostics(converter).is_empty());
+    }
+
+    let html = to_html(&[element], &HtmlOptions::default());
+    assert!(html.contains("<dt><code>to</code></dt><dd><code>address</code> the recipient</dd>"));
+}
+
+#[test]
+fn count_lines() {
+    use crate::util::count_lines;
+
+    assert_eq!(count_lines(""), 1);
+    assert_eq!(count_lines("a"), 1);
+    assert_eq!(count_lines("a\n"), 2);
+    assert_eq!(count_lines("a\r\nb"), 2);
+    assert_eq!(count_lines("\n\n"), 3);
+    // unlike ropey, only `\n` breaks lines
+    assert_eq!(count_lines("a\rb\u{2028}c"), 1);
+
+    for s in ["", "a\nb", "a\r\nb\n", "\u{2500}\n\n"] {
+        assert_eq!(count_lines(s), ropey::Rope::from_str(s).len_lines());
+    }
+}
+
+#[test]
+fn source_map_of_decorated_block_comment() {
+    let src = indoc! {"
+        /**
+         * @notice transfers the tokens
+         *    of the sender\tto
+         * the recipient
+         * @dev reverts on overflow   
+         */
+        rule transfer() { assert true; }
+    "};
+    let element = parse_exactly_one(src).unwrap();
+    let notice = &element.doc[0];
+    assert_eq!(
+        notice.description,
+        "transfers the tokens\nof the sender\tto\nthe recipient"
+    );
+
+    let map = notice.source_map();
+    for word in ["transfers", "sender", "to", "recipient"] {
+        let start = notice.description.rfind(word).unwrap();
+        let range = map.original_range(start..start + word.len()).unwrap();
+        assert_eq!(&src[range], word);
+    }
+
+    // a range across lines covers the decoration in between
+    let start = notice.description.find("tokens").unwrap();
+    let end = notice.description.find("sender").unwrap() + "sender".len();
+    let range = map.original_range(start..end).unwrap();
+    assert_eq!(&src[range], "tokens\n *    of the sender");
+
+    // the line terminator maps to the end of its line
+    let newline = notice.description.find('\n').unwrap();
+    assert_eq!(
+        map.original_position(newline),
+        src.find("tokens\n").map(|i| i + 6)
+    );
+
+    let dev = &element.doc[1];
+    assert_eq!(dev.description, "reverts on overflow");
+    let range = dev.source_map().original_range(0..dev.description.len());
+    assert_eq!(range.map(|range| &src[range]), Some("reverts on overflow"));
+
+    assert!(DocumentationTag::notice("built").source_map().is_empty());
+    assert_eq!(
+        DocumentationTag::notice("built")
+            .source_map()
+            .original_position(0),
+        None
+    );
+}
+
+#[test]
+fn source_map_after_reparse() {
+    let src = "invariant zero() x == 0;\n/// @notice old\nrule r() { assert true; }\n";
+    let mut element = Builder::new(src).build().unwrap().remove(1);
+    element
+        .reparse_in_place("/// @notice — the new notice")
+        .unwrap();
+
+    let notice = &element.doc[0];
+    let range = notice
+        .source_map()
+        .original_range(0..notice.description.len());
+    assert_eq!(
+        range.map(|range| &element.src[range]),
+        Some("— the new notice")
+    );
+}
+
+#[cfg(feature = "lsp")]
+#[test]
+fn position_to_tag() {
+    use lsp_types::Position;
+
+    let src = indoc! {"
+        /// @notice moves tokens
+        /// @param amount the number of tokens
+        rule transfer(uint256 amount) { assert true; }
+    "};
+    let element = parse_exactly_one(src).unwrap();
+    let at = |line, character| element.position_to_tag(Position { line, character });
+
+    assert_eq!(at(0, 4).map(|tag| &tag.kind), Some(&TagKind::Notice));
+    assert_eq!(at(0, 20).map(|tag| &tag.kind), Some(&TagKind::Notice));
+    assert_eq!(at(1, 15).map(|tag| &tag.kind), Some(&TagKind::Param));
+    assert_eq!(at(1, 200).map(|tag| &tag.kind), Some(&TagKind::Param));
+    assert_eq!(at(2, 5), None);
+    assert_eq!(at(40, 0), None);
+
+    // tags added by tools have no position in the source
+    let element = element
+        .strip_tags(&[TagKind::Notice, TagKind::Param])
+        .with_tag(DocumentationTag::notice("generated"));
+    assert_eq!(element.position_to_tag(Position::new(0, 0)), None);
+}
+
+#[test]
+fn assert_messages() {
+    use crate::extract::assert_messages;
+    use crate::render::{to_html, HtmlOptions};
+    use crate::util::ByteSpan;
+
+    let src = indoc! {r#"
+        /// @notice transfers preserve the supply
+        rule transfer(address to, uint256 amount) {
+            // assert false, "commented out";
+            require to != 0, "the \"zero\" address";
+            requireInvariant totalIsSum();
+            if (amount > 0) {
+                assert balanceOf(to) >= f(amount, { a: 1 }, "not a message"),
+                    /* the message: */ "balance, \\ increases";
+            }
+            assert true;
+            assert x == 1, "unterminated" + y;
+            assert_helper(1, "not a statement");
+            assert amount == 0 => g(1, 2), "implication; with a semicolon";
+        }
+    "#};
+    let element = parse_exactly_one(src).unwrap();
+    let messages = assert_messages(&element);
+
+    let texts = messages.iter().map(|(_, m)| m.as_str()).collect_vec();
+    assert_eq!(
+        texts,
+        [
+            r#"the "zero" address"#,
+            r"balance, \ increases",
+            "implication; with a semicolon",
+        ]
+    );
+
+    let literals = messages
+        .iter()
+        .map(|(span, _)| span.byte_slice(src).unwrap())
+        .collect_vec();
+    assert_eq!(literals[0], r#""the \"zero\" address""#);
+    assert_eq!(literals[1], r#""balance, \\ increases""#);
+
+    let function = parse_exactly_one("function f() { assert x, \"in a function\"; }").unwrap();
+    assert_eq!(assert_messages(&function)[0].1, "in a function");
+    let ghost = parse_exactly_one("ghost uint256 x;").unwrap();
+    assert!(assert_messages(&ghost).is_empty());
+
+    let opts = HtmlOptions {
+        include_assertions: true,
+        ..Default::default()
+    };
+    let html = to_html(std::slice::from_ref(&element), &opts);
+    assert!(html.contains(
+        "<h3>Assertions</h3>\n<ul class=\"assertions\">\n<li>the &quot;zero&quot; address</li>\n"
+    ));
+    assert!(!to_html(&[element], &HtmlOptions::default()).contains("Assertions"));
+}
+
+#[test]
+fn parametric_rules() {
+    use crate::coverage::CompletenessConfig;
+    use crate::render::{to_html, HtmlOptions};
+
+    let src = indoc! {"
+        /// @notice no method changes the supply
+        rule supplyUnchanged(method f, env e) { assert true; }
+
+        /// @notice two methods commute
+        /// @param f the first method
+        rule commute(method f, method g) { assert true; }
+
+        /// @notice not parametric
+        /// @param method a number, despite its name
+        rule named(uint256 method) { assert true; }
+
+        /// @notice not a rule
+        function helper(method f) { }
+    "};
+    let elements = Builder::new(src).build().unwrap();
+    let parametric = elements
+        .iter()
+        .map(|element| element.ast.is_parametric())
+        .collect_vec();
+    assert_eq!(parametric, [true, true, false, false]);
+
+    let json = serde_json::to_value(&elements[0]).unwrap();
+    assert_eq!(json["ast"]["parametric"], true);
+    assert_eq!(json["ast"]["type"], "Rule");
+    let ast: Ast = serde_json::from_value(json["ast"].clone()).unwrap();
+    assert_eq!(ast, elements[0].ast);
+    let json = serde_json::to_value(&elements[2]).unwrap();
+    assert!(json["ast"].get("parametric").is_none());
+
+    // `method` parameters need no `@param` by default, while `env` parameters still do
+    assert!(!elements[0].has_complete_documentation());
+    assert!(elements[1].has_complete_documentation());
+    assert!(elements[2].has_complete_documentation());
+    let strict = CompletenessConfig {
+        require_method_params: true,
+        ..Default::default()
+    };
+    assert!(!strict.is_complete(&elements[1]));
+
+    let html = to_html(&elements[1..3], &HtmlOptions::default());
+    assert!(html.contains(r#"<h2>commute <span class="badge">parametric</span></h2>"#));
+    assert!(html.contains("<h2>named</h2>"));
+}
+
+#[test]
+fn mapping_types() {
+    use crate::MappingType;
+
+    let src = indoc! {"
+        ghost mapping(address => uint256) balances;
+        ghost mapping(address  =>  mapping (uint256 => bool)) nested;
+        ghost uint256 counter;
+    "};
+    let elements = Builder::new(src).build().unwrap();
+    let types = elements
+        .iter()
+        .map(|element| element.ast.mapping_type().cloned())
+        .collect_vec();
+
+    let balances = types[0].clone().unwrap();
+    assert_eq!(balances.key_types().collect_vec(), ["address"]);
+    assert_eq!(balances.value, "uint256");
+    assert_eq!(balances.to_string(), "mapping(address => uint256)");
+
+    let nested = types[1].clone().unwrap();
+    assert_eq!(nested.key_types().collect_vec(), ["address", "uint256"]);
+    assert_eq!(nested.value, "bool");
+    assert_eq!(
+        nested.to_string(),
+        "mapping(address => mapping(uint256 => bool))"
+    );
+    assert_eq!(MappingType::parse(&nested.to_string()), Some(nested));
+
+    assert_eq!(types[2], None);
+
+    let arrays = MappingType::parse("mapping(bytes32 => uint256[])").unwrap();
+    assert_eq!(arrays.value, "uint256[]");
+
+    let named = MappingType::parse("mapping(address owner=>uint256 balance)").unwrap();
+    assert_eq!(named.keys, [Param::new("address", "owner")]);
+    assert_eq!(named.to_string(), "mapping(address owner => uint256)");
+    assert_eq!(MappingType::parse(&named.to_string()), Some(named));
+    for invalid in [
+        "mapping(=> uint)",
+        "mapping(address => )",
+        "mapping(address)",
+        "uint",
+    ] {
+        assert_eq!(MappingType::parse(invalid), None, "{invalid}");
+    }
+}
+
+#[test]
+fn into_parts() {
+    let src = indoc! {"
+        //! @title the spec
+
+        //// a freeform comment
+
+        /// @notice documented
+        rule documented() { assert true; }
+
+        rule undocumented() { assert true; }
+    "};
+    let parts = Builder::new(src)
+        .build()
+        .unwrap()
+        .into_iter()
+        .map(CvlElement::into_parts)
+        .collect_vec();
+
+    let [file_doc, freeform, documented, undocumented] = parts.as_slice() else {
+        panic!("expected 4 elements, found {}", parts.len());
+    };
+    assert_matches!(file_doc, (Some(doc), None) if doc[0].kind == TagKind::Title);
+    assert_eq!(freeform, &(None, None));
+    assert_matches!(
+        documented,
+        (Some(doc), Some(Ast::Rule { name, .. })) if doc.len() == 1 && name == "documented"
+    );
+    assert_matches!(undocumented, (Some(doc), Some(Ast::Rule { .. })) if doc.is_empty());
+}
+
+#[cfg(feature = "lsp")]
+#[test]
+fn param_order_fix() {
+    use crate::util::ByteSpan;
+    use lsp_types::TextEdit;
+
+    let apply = |src: &str, edits: &[TextEdit]| {
+        let [edit] = edits else {
+            panic!("expected a single edit, found {edits:?}")
+        };
+        let converter = RangeConverter::new(Rope::from_str(src));
+        let span = converter.to_span(edit.range).to_byte_span(src).unwrap();
+        format!(
+            "{}{}{}",
+            &src[..span.start],
+            edit.new_text,
+            &src[span.end..]
+        )
+    };
+    let descriptions = |element: &CvlElement| {
+        element
+            .doc
+            .iter()
+            .map(|tag| (tag.kind.clone(), tag.description.clone()))
+            .sorted()
+            .collect_vec()
+    };
+
+    let slashed = indoc! {"
+        /// @notice transfers tokens
+        /// @param amount the amount,
+        ///     which must be positive
+        /// @dev stays in place
+        /// @param to the recipient
+        /// @param from the sender
+        function transfer(address from, address to, uint256 amount) { }
+    "};
+    let starred = indoc! {"
+        /**
+         * @notice transfers tokens
+         * @param to the recipient
+         * @param from the sender,
+         *   who pays
+         */
+        function transfer(address from, address to) { }
+    "};
+
+    for (src, expected_order) in [
+        (slashed, ["from", "to", "amount"].as_slice()),
+        (starred, ["from", "to"].as_slice()),
+    ] {
+        let element = parse_exactly_one(src).unwrap();
+        let converter = RangeConverter::new(Rope::from_str(src));
+        let diagnostics = element.enumerate_diagnostics(converter);
+        let [diagnostic] = diagnostics.as_slice() else {
+            panic!("expected a single diagnostic, found {diagnostics:?}")
+        };
+        assert_eq!(
+            diagnostic.code,
+            Some(NumberOrString::String("param-order".to_string()))
+        );
+
+        let data = diagnostic.data.clone().unwrap();
+        let edits: Vec<TextEdit> = serde_json::from_value(data["edits"].clone()).unwrap();
+        assert_eq!(Some(&edits), element.param_order_fix().as_ref());
+
+        let fixed_src = apply(src, &edits);
+        let fixed = parse_exactly_one(&fixed_src).unwrap();
+        let order = fixed
+            .doc
+            .iter()
+            .filter_map(|tag| tag.param_name())
+            .collect_vec();
+        assert_eq!(order, expected_order);
+        assert_eq!(descriptions(&fixed), descriptions(&element));
+        assert!(fixed.param_order_fix().is_none());
+        assert!(fixed
+            .enumerate_diagnostics(RangeConverter::new(Rope::from_str(&fixed_src)))
+            .is_empty());
+    }
+
+    // the other tags are untouched
+    let fixed = apply(
+        slashed,
+        &parse_exactly_one(slashed)
+            .unwrap()
+            .param_order_fix()
+            .unwrap(),
+    );
+    assert!(fixed.contains("/// @notice transfers tokens\n/// @param from"));
+    assert!(
+        fixed.contains("/// @dev stays in place\n/// @param to the recipient\n/// @param amount")
+    );
+
+    // a tag on the closing line of the comment cannot be moved
+    let src = "/** @notice n\n * @param to t\n * @param from f */\nfunction f(address from, address to) { }";
+    let element = parse_exactly_one(src).unwrap();
+    assert!(element.param_order_fix().is_none());
+    let diagnostics = element.enumerate_diagnostics(RangeConverter::new(Rope::from_str(src)));
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].data, None);
+}
+
+#[test]
+fn freeform_following_element() {
+    use crate::parse::builder::ParseConfig;
+    use crate::{ElementKind, ElementRef};
+
+    let src = indoc! {"
+        //// The following rules cover pausing behavior
+
+        //// ## Pausing
+
+        /// @notice only the owner pauses
+        rule onlyOwnerPauses() { assert true; }
+
+        methods { function paused() external returns (bool) envfree; }
+
+        //// the end
+    "};
+    let following = |elements: &[CvlElement]| {
+        elements
+            .iter()
+            .filter_map(|element| match &element.ast {
+                Ast::FreeFormComment {
+                    following_element, ..
+                } => Some(following_element.clone()),
+                _ => None,
+            })
+            .collect_vec()
+    };
+
+    let default = Builder::new(src).build().unwrap();
+    assert_eq!(following(&default), [None, None, None]);
+
+    let config = ParseConfig {
+        record_following_element: true,
+        ..Default::default()
+    };
+    let elements = Builder::new(src).with_config(config).build().unwrap();
+    let rule = ElementRef {
+        kind: ElementKind::Rule,
+        name: Some("onlyOwnerPauses".to_string()),
+        span: elements[2].element_span.clone(),
+    };
+    // a freeform comment followed by another freeform comment refers past it
+    assert_eq!(following(&elements), [Some(rule.clone()), Some(rule), None]);
+    assert!(elements[0].is_freeform() && !elements[0].is_decorative());
+
+    let json = serde_json::to_value(&elements[0]).unwrap();
+    assert_eq!(json["ast"]["following_element"]["name"], "onlyOwnerPauses");
+    assert!(serde_json::to_value(&default[0]).unwrap()["ast"]
+        .get("following_element")
+        .is_none());
+
+    let methods = Builder::new("//// entries\nmethods { }\n")
+        .with_config(config)
+        .build()
+        .unwrap();
+    assert_matches!(
+        &methods[0].ast,
+        Ast::FreeFormComment {
+            following_element: Some(ElementRef {
+                kind: ElementKind::Methods,
+                name: None,
+                ..
+            }),
+            ..
+        }
+    );
+}
+
+#[test]
+fn oversized_comments() {
+    use crate::parse::builder::ParseConfig;
+
+    let config = ParseConfig {
+        max_comment_bytes: 64,
+        ..Default::default()
+    };
+    let long_line = "é".repeat(40);
+    let src = formatdoc! {"
+        /**
+         * @notice {long_line}
+         * @dev more
+         */
+        rule large() {{ assert true; }}
+
+        /// @notice small
+        rule small() {{ assert true; }}
+    "};
+
+    let elements = Builder::new(&src).with_config(config).build().unwrap();
+    assert_eq!(elements.len(), 3);
+    let Ast::FreeFormComment {
+        text, truncated, ..
+    } = &elements[0].ast
+    else {
+        panic!("expected a freeform comment, found {:?}", elements[0].ast);
+    };
+    assert!(*truncated);
+    assert!(text.starts_with("@notice éé"));
+    assert!(text.len() < 64);
+    assert!(elements[1].doc.is_empty());
+    assert_eq!(elements[2].doc[0].description, "small");
+
+    let default = Builder::new(&src).build().unwrap();
+    assert_eq!(default.len(), 2);
+    assert_eq!(default[0].doc.len(), 2);
+
+    #[cfg(feature = "lsp")]
+    {
+        let converter = RangeConverter::new(Rope::from_str(&src));
+        let diagnostics = elements[0].enumerate_diagnostics(converter);
+        let codes = diagnostics
+            .iter()
+            .map(|diag| diag.code.clone())
+            .collect_vec();
+        assert_eq!(
+            codes,
+            [Some(NumberOrString::String(
+                "comment-too-large".to_string()
+            ))]
+        );
+    }
+}
+
+#[test]
+fn replace_associated() {
+    let src = indoc! {"
+        //// ## Transfers
+
+        /// @notice transfers preserve the supply
+        rule transferPreservesSupply() { assert true; }
+    "};
+    let elements = Builder::new(src).build().unwrap();
+    let replacement =
+        Ast::from_cvl_snippet("rule", "rule renamed(uint256 x) { assert x > 0; }").unwrap();
+
+    let replaced = elements[1]
+        .clone()
+        .replace_associated(replacement.clone())
+        .unwrap();
+    assert_eq!(replaced.element_name(), Some("renamed"));
+    assert_eq!(replaced.doc, elements[1].doc);
+
+    let freeform = elements[0].clone();
+    assert_eq!(
+        freeform.clone().replace_associated(replacement),
+        Err(Box::new(freeform))
+    );
+
+    let ghost = Ast::from_cvl_snippet("ghost", "ghost uint256 g;").unwrap();
+    let file_doc = parse_exactly_one("//! @title the spec\n").unwrap();
+    assert!(file_doc.replace_associated(ghost).is_err());
+
+    let freeform_ast = elements[0].ast.clone();
+    assert_eq!(
+        elements[1].clone().replace_associated(freeform_ast),
+        Err(Box::new(elements[1].clone()))
+    );
+}
+
+#[test]
+fn partial_buffers() {
+    use crate::parse::builder::ParseConfig;
+    use crate::util::ByteSpan;
+
+    let partial = ParseConfig {
+        assume_clean_start: false,
+        ..Default::default()
+    };
+
+    let inside_comment = indoc! {"
+         * the rest of the documentation of
+         * rule hidden() { assert false; }
+         */
+        rule tail() { assert true; }
+
+        /// @notice next
+        rule next() { assert true; }
+    "};
+    let skipped_text = &inside_comment[..inside_comment.find("*/").unwrap() + 2];
+
+    let builder = Builder::new(inside_comment).with_config(partial);
+    let skipped = builder.skipped_start().unwrap();
+    assert_eq!(skipped.byte_slice(inside_comment), Some(skipped_text));
+    #[cfg(feature = "lsp")]
+    {
+        let diagnostics = builder.source_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("started-inside-comment".to_string()))
+        );
+        assert_eq!(diagnostics[0].range.start, lsp_types::Position::new(0, 0));
+        assert_eq!(diagnostics[0].range.end, lsp_types::Position::new(2, 3));
+    }
+
+    let elements = builder.build().unwrap();
+    let names = elements
+        .iter()
+        .map(|e| e.element_name().unwrap())
+        .collect_vec();
+    assert_eq!(names, ["tail", "next"]);
+    assert_eq!(elements[1].doc[0].description, "next");
+    assert_eq!(elements[0].source_text(), "rule tail() { assert true; }");
+
+    // by default, the comment is taken as code
+    let default = Builder::new(inside_comment);
+    assert_eq!(default.skipped_start(), None);
+    let names = default
+        .build()
+        .unwrap()
+        .into_iter()
+        .filter_map(|e| e.element_name().map(str::to_string))
+        .collect_vec();
+    assert!(names.contains(&"hidden".to_string()));
+
+    // a buffer starting inside a rule block has no comment to skip
+    let inside_block = indoc! {"
+            assert x > 0;
+        }
+
+        /// @notice next
+        rule next() { assert true; }
+    "};
+    let builder = Builder::new(inside_block).with_config(partial);
+    assert_eq!(builder.skipped_start(), None);
+    #[cfg(feature = "lsp")]
+    assert!(builder.source_diagnostics().is_empty());
+    let elements = builder.build().unwrap();
+    assert_eq!(elements.len(), 1);
+    assert_eq!(elements[0].doc[0].description, "next");
+
+    // a comment which opens before the first `*/` is not skipped
+    let clean = "/* a comment */\nrule r() { assert true; }\n";
+    assert_eq!(
+        Builder::new(clean).with_config(partial).skipped_start(),
+        None
+    );
+
+    // nor is a `*/` in a line comment or a string
+    for clean in [
+        "// ends with */\nrule r() { assert true; }\n",
+        "rule r() { assert \"*/\" == \"\"; }\n",
+        "/// @notice see /* a */\nrule r() { assert true; }\n",
+    ] {
+        assert_eq!(
+            Builder::new(clean).with_config(partial).skipped_start(),
+            None,
+            "{clean}"
+        );
+    }
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_reports_association_failures() {
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::TRACE)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+
+    let src = indoc! {"
+        /// @notice documents something unknown
+        frobnicate something;
+
+        /// @notice documents a rule
+        rule known() { assert true; }
+    "};
+    tracing::subscriber::with_default(subscriber, || Builder::new(src).build().unwrap());
+
+    let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    let line_of = |message: &str| {
+        output
+            .lines()
+            .find(|line| line.contains(message))
+            .unwrap_or_else(|| panic!("no event with {message:?} in:\n{output}"))
+    };
+
+    let failed = line_of("association failed: next token frobnicate");
+    assert!(failed.contains("doc_candidate{style=Slashed start_line=0}"));
+    let associated = line_of("associated with rule known");
+    assert!(associated.contains("start_line=3"));
+    line_of("tag kind=\"notice\"");
+}
+
+#[test]
+fn map_block() {
+    let src = indoc! {"
+        rule r() { assert true; }
+
+        function declared(uint x);
+
+        invariant positive() x > 0 { preserved { require true; } }
+
+        definition two() returns uint = 2;
+    "};
+    let elements = Builder::new(src).build().unwrap();
+    let mapped = elements
+        .into_iter()
+        .map(|element| element.ast.map_block(|block| block.trim().to_uppercase()))
+        .collect_vec();
+
+    assert_eq!(mapped[0].block(), Some("ASSERT TRUE;"));
+    assert_eq!(mapped[1].block(), None);
+    assert_eq!(mapped[2].block(), Some("PRESERVED { REQUIRE TRUE; }"));
+    assert_matches!(&mapped[3], Ast::Definition { definition, .. } if definition == "2");
+}
+
+#[test]
+fn indented_continuations_of_slashed_tags() {
+    let src = indoc! {"
+        /// @notice transfers tokens
+        /// @param amount the amount
+        ///     (must be non-zero)
+        /// @dev assumes a single recipient
+        ///   see the spec of `transfer`
+        ///     for the full conditions
+        /// @param to the recipient, not
+        ///     contact@example.com or @dev notes
+        ///  nor the sender
+        rule transfer(uint256 amount, address to) { assert true; }
+
+        /**
+         * @notice starred comments are unchanged
+         *     despite the indentation
+         */
+        rule starred() { assert true; }
+    "};
+    let elements = Builder::new(src).build().unwrap();
+
+    let descriptions = elements[0]
+        .doc
+        .iter()
+        .map(|tag| (tag.kind.clone(), tag.description.as_str()))
+        .collect_vec();
+    assert_eq!(
+        descriptions,
+        [
+            (TagKind::Notice, "transfers tokens"),
+            (TagKind::Param, "amount the amount\n    (must be non-zero)"),
+            (
+                TagKind::Dev,
+                "assumes a single recipient\n  see the spec of `transfer`\n    for the full conditions"
+            ),
+            (
+                TagKind::Param,
+                "to the recipient, not\n    contact@example.com or @dev notes\nnor the sender"
+            ),
+        ]
+    );
+
+    let tag = &elements[0].doc[1];
+    let continuation = tag.description.find('(').unwrap();
+    let source = tag.source_map().original_position(continuation).unwrap();
+    assert_eq!(&src[source..source + 1], "(");
+
+    assert_eq!(
+        elements[1].doc[0].description,
+        "starred comments are unchanged\ndespite the indentation"
+    );
+}
+
+#[test]
+fn ghost_mapping_keys_and_value() {
+    let src = indoc! {"
+        ghost mapping(address => uint256) depth1;
+        ghost mapping(address  =>mapping (
+            uint256  =>  bool
+        )) depth2;
+        ghost mapping(address owner => mapping(uint256 id => mapping(bytes32 => uint256[]))) depth3;
+        ghost mathint notMapping;
+    "};
+    let elements = Builder::new(src).build().unwrap();
+    let keys_and_value = |element: &CvlElement| {
+        let mapping_type = element.ast.mapping_type().unwrap();
+        (mapping_type.keys.clone(), mapping_type.value.clone())
+    };
+
+    let (keys, value) = keys_and_value(&elements[0]);
+    assert_eq!(keys, [Param::new("address", "")]);
+    assert_eq!(value, "uint256");
+
+    let (keys, value) = keys_and_value(&elements[1]);
+    assert_eq!(keys, [Param::new("address", ""), Param::new("uint256", "")]);
+    assert_eq!(value, "bool");
+
+    let (keys, value) = keys_and_value(&elements[2]);
+    assert_eq!(
+        keys,
+        [
+            Param::new("address", "owner"),
+            Param::new("uint256", "id"),
+            Param::new("bytes32", "")
+        ]
+    );
+    assert_eq!(value, "uint256[]");
+
+    assert_matches!(elements[3].ast, Ast::GhostMapping { .. });
+    assert_eq!(elements[3].ast.mapping_type(), None);
+}
+
+#[test]
+#[cfg(feature = "lsp")]
+fn ghost_mapping_key_params() {
+    let src = indoc! {"
+        /// @param key0 the owner
+        /// @param id the token
+        /// @param key1 the token, again
+        /// @param key2 there is no third key
+        /// @param other not a key
+        ghost mapping(address => mapping(uint256 id => bool)) approved;
+    "};
+
+    let element = parse_exactly_one(src).unwrap();
+    let converter = RangeConverter::new(Rope::from_str(src));
+    let messages = element
+        .enumerate_diagnostics(converter)
+        .into_iter()
+        .map(|diagnostic| diagnostic.message)
+        .collect_vec();
+    assert_eq!(
+        messages,
+        ["no such parameter: key2", "no such parameter: other"]
+    );
+}
+
+#[test]
+#[cfg(feature = "lsp")]
+fn tag_completions() {
+    use crate::completion::tag_completions;
+
+    let src = indoc! {"
+        /// @notice a rule
+        rule foo() { assert true; }
+
+        /// @notice a function
+        function bar(uint x) returns uint { return x; }
+    "};
+    let elements = Builder::new(src).build().unwrap();
+    let labels = |ast| {
+        tag_completions(ast)
+            .into_iter()
+            .map(|item| item.label)
+            .collect_vec()
+    };
+
+    let rule_labels = labels(Some(&elements[0].ast));
+    assert!(rule_labels.contains(&"@requirement".to_string()));
+    assert!(rule_labels.contains(&"@satisfies".to_string()));
+    assert_eq!(
+        labels(Some(&elements[1].ast)),
+        ["@notice", "@dev", "@param", "@return"]
+    );
+    assert_eq!(labels(None).len(), TagKind::ALL.len());
+}
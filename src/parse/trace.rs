@@ -0,0 +1,96 @@
+//! events of the parse pipeline, for a `tracing` subscriber.
+//! without the `tracing` feature, these do nothing.
+#![cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+
+use super::{Span, Style};
+use crate::{Ast, DocumentationTagRef};
+
+/// a documentation comment, from when it is parsed until it is associated with an element or dropped.
+/// events about the comment are emitted within its `doc_candidate` span.
+pub(super) struct Candidate {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+/// the zero-based line of each candidate, counted from the previous candidate
+/// rather than from the start of the source, since candidates come in source order.
+#[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+pub(super) struct LineCursor<'src> {
+    src: &'src str,
+    char_offset: usize,
+    byte_offset: usize,
+    line: usize,
+}
+
+impl<'src> LineCursor<'src> {
+    pub(super) fn new(src: &'src str) -> LineCursor<'src> {
+        LineCursor {
+            src,
+            char_offset: 0,
+            byte_offset: 0,
+            line: 0,
+        }
+    }
+
+    /// the line of the char at `char_index`. moving backwards starts over from the start of the source.
+    #[cfg(feature = "tracing")]
+    fn line_at(&mut self, char_index: usize) -> usize {
+        if char_index < self.char_offset {
+            *self = LineCursor::new(self.src);
+        }
+        for (i, c) in self.src[self.byte_offset..].chars().enumerate() {
+            if self.char_offset + i == char_index {
+                break;
+            }
+            self.byte_offset += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+            }
+        }
+        self.char_offset = char_index;
+        self.line
+    }
+}
+
+impl Candidate {
+    /// a comment at `span` of the source of `cursor`. its `start_line` is zero-based.
+    pub(super) fn new(style: &Style, cursor: &mut LineCursor, span: &Span) -> Candidate {
+        #[cfg(feature = "tracing")]
+        let start_line = cursor.line_at(span.start);
+        Candidate {
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!("doc_candidate", style = ?style, start_line),
+        }
+    }
+
+    pub(super) fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        #[cfg(feature = "tracing")]
+        return self.span.in_scope(f);
+        #[cfg(not(feature = "tracing"))]
+        f()
+    }
+
+    pub(super) fn associated(&self, ast: &Ast) {
+        #[cfg(feature = "tracing")]
+        self.in_scope(|| match ast.name() {
+            Some(name) => tracing::debug!("associated with {ast} {name}"),
+            None => tracing::debug!("associated with {ast}"),
+        });
+    }
+
+    /// `next_token` is the start of the text which follows the comment
+    pub(super) fn association_failed(&self, next_token: &str) {
+        #[cfg(feature = "tracing")]
+        self.in_scope(|| tracing::debug!("association failed: next token {next_token}"));
+    }
+}
+
+pub(super) fn builder_failed(error: &eyre::Report) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!("builder failed: {error}");
+}
+
+pub(super) fn tag(tag: &DocumentationTagRef) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(kind = tag.kind.as_str(), span = ?tag.span, "tag");
+}
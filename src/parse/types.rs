@@ -16,6 +16,8 @@ pub enum Token {
     Filtered,
     CvlDocSlashed,
     CvlDocStarred,
+    FileDocSlashed,
+    FileDocStarred,
     FreeFormSlashed,
     FreeFormStarred,
     RoundOpen,
@@ -24,6 +26,8 @@ pub enum Token {
     SquareClose,
     CurlyOpen,
     CurlyClose,
+    /// a balanced `{...}` block, found by a linear scan of the source rather than lexed
+    CodeBlock,
     Ident(String),
     String(String),
     Number(String),
@@ -83,6 +87,7 @@ impl Display for Token {
             Token::SquareClose => write!(f, "]"),
             Token::CurlyOpen => write!(f, "{{"),
             Token::CurlyClose => write!(f, "}}"),
+            Token::CodeBlock => write!(f, "{{...}}"),
             Token::Dot => write!(f, "."),
             Token::Comma => write!(f, ","),
             Token::Semicolon => write!(f, ";"),
@@ -121,6 +126,8 @@ impl Display for Token {
 
             Token::CvlDocSlashed
             | Token::CvlDocStarred
+            | Token::FileDocSlashed
+            | Token::FileDocStarred
             | Token::FreeFormSlashed
             | Token::FreeFormStarred
             | Token::SingleLineComment
@@ -139,18 +146,20 @@ impl FromIterator<Token> for String {
 pub enum Intermediate {
     FreeFormComment(Style, Span),
     Documentation(Style, Span),
+    FileDocumentation(Style, Span),
     Methods(Span),
     Function {
         name: String,
         params: Vec<Param>,
-        returns: Option<String>,
-        block: Span,
+        return_types: Vec<String>,
+        block: Option<Span>,
     },
     GhostMapping {
         persistent: bool,
         name: String,
         mapping: String,
         axioms: Option<Span>,
+        initial_value: Option<Span>,
     },
     GhostFunction {
         persistent: bool,
@@ -163,6 +172,7 @@ pub enum Intermediate {
         name: String,
         params: Option<Vec<Param>>,
         filters: Option<Span>,
+        attributes: Vec<(String, String)>,
         block: Span,
     },
     Definition {
@@ -171,12 +181,19 @@ pub enum Intermediate {
         returns: String,
         definition: Span,
     },
+    Macro {
+        name: String,
+        params: Vec<Param>,
+        body: Span,
+    },
     Invariant {
+        strong: bool,
         name: String,
         params: Vec<Param>,
         invariant: Span,
         filters: Option<Span>,
         proof: Option<Span>,
+        attributes: Vec<(String, String)>,
     },
     Import(String),
     UseRule {
@@ -215,6 +232,10 @@ pub enum Intermediate {
         contract_name: String,
         spec_name: String,
     },
+    Unknown {
+        first_token: Span,
+        header: Span,
+    },
     ParseError,
 }
 
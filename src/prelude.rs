@@ -0,0 +1,9 @@
+//! the types most consumers need, importable with a single glob:
+//! `use cvldoc_parser_core::prelude::*;`
+
+pub use crate::parse::builder::Builder;
+pub use crate::util::{RangeConverter, SimplePosition, SimpleRange, Span};
+pub use crate::{Ast, CvlElement, DocumentationTag, Param, TagKind};
+
+#[cfg(feature = "lsp")]
+pub use lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range};
@@ -4,6 +4,7 @@ from typing import Any, Dict, List, Optional, Union
 
 class DocumentationTag:
     kind: TagKind
+    keyword: str
     description: str
     def param_name_and_description(self) -> Optional[tuple[str, str]]: ...
 
@@ -25,6 +26,10 @@ class AstKind(Enum):
     HookSstore = 14
     HookCreate = 15
     HookOpcode = 16
+    Unknown = 17
+    FileDocumentation = 18
+    CVLMacro = 19
+    Detached = 20
 
 class TagKind(Enum):
     Title = 0
@@ -33,6 +38,10 @@ class TagKind(Enum):
     Param = 3
     Return = 4
     Formula = 5
+    Requirement = 6
+    Satisfies = 7
+    Other = 8
+    def as_keyword(self) -> str: ...
 
 class Span:
     start: int
@@ -46,9 +55,11 @@ class CvlElement:
     doc: List[DocumentationTag]
     ast: Ast
     def span(self) -> Span: ...
-    def raw(self) -> str: ...
+    def source_text(self) -> str: ...
+    def raw(self) -> str: ...  # deprecated: renamed to `source_text`
     def element_name(self) -> Optional[str]: ...
     def element_returns(self) -> Optional[str]: ...
+    def element_return_types(self) -> List[str]: ...
     def element_params(self) -> Optional[List[tuple[str, str]]]: ...
 
 def parse(path: Union[str, PathLike]) -> List[CvlElement]: ...
@@ -20,18 +20,27 @@ impl CvlElementPy {
         self.inner.span().into()
     }
 
+    pub fn source_text(&self) -> &str {
+        self.inner.source_text()
+    }
+
+    /// deprecated: renamed to `source_text`
     pub fn raw(&self) -> &str {
-        self.inner.raw()
+        self.inner.source_text()
     }
 
     pub fn element_name(&self) -> Option<&str> {
-        self.inner.ast.name()
+        self.inner.element_name()
     }
 
     pub fn element_returns(&self) -> Option<&str> {
         self.inner.ast.returns()
     }
 
+    pub fn element_return_types(&self) -> Vec<&str> {
+        self.inner.ast.return_types()
+    }
+
     pub fn element_params(&self) -> Option<Vec<(String, String)>> {
         if let Some(params) = self.inner.ast.params() {
             let params = params
@@ -107,6 +116,10 @@ pub enum AstKindPy {
     HookSstore,
     HookCreate,
     HookOpcode,
+    Unknown,
+    FileDocumentation,
+    CVLMacro,
+    Detached,
 }
 
 #[pymethods]
@@ -127,6 +140,10 @@ impl AstKindPy {
             | AstKindPy::HookSstore
             | AstKindPy::HookCreate
             | AstKindPy::HookOpcode => "hook",
+            AstKindPy::Unknown => "unknown",
+            AstKindPy::FileDocumentation => "file",
+            AstKindPy::CVLMacro => "macro",
+            AstKindPy::Detached => "detached",
         }
     }
 }
@@ -151,6 +168,10 @@ impl From<&Ast> for AstKindPy {
             Ast::HookSstore { .. } => AstKindPy::HookSstore,
             Ast::HookCreate { .. } => AstKindPy::HookCreate,
             Ast::HookOpcode { .. } => AstKindPy::HookOpcode,
+            Ast::Unknown { .. } => AstKindPy::Unknown,
+            Ast::FileDocumentation => AstKindPy::FileDocumentation,
+            Ast::CVLMacro { .. } => AstKindPy::CVLMacro,
+            Ast::Detached => AstKindPy::Detached,
         }
     }
 }
@@ -174,6 +195,11 @@ pub enum TagKindPy {
     Param,
     Return,
     Formula,
+    Requirement,
+    Satisfies,
+    /// a kind this version of the wrapper does not know.
+    /// the tag as written is kept in [DocumentationTagPy::keyword].
+    Other,
 }
 
 #[pymethods]
@@ -186,8 +212,15 @@ impl TagKindPy {
             TagKindPy::Param => "param",
             TagKindPy::Return => "return",
             TagKindPy::Formula => "formula",
+            TagKindPy::Requirement => "requirement",
+            TagKindPy::Satisfies => "satisfies",
+            TagKindPy::Other => "other",
         }
     }
+
+    fn as_keyword(&self) -> String {
+        format!("@{}", self.__str__())
+    }
 }
 
 impl From<&TagKind> for TagKindPy {
@@ -199,6 +232,9 @@ impl From<&TagKind> for TagKindPy {
             TagKind::Param => TagKindPy::Param,
             TagKind::Return => TagKindPy::Return,
             TagKind::Formula => TagKindPy::Formula,
+            TagKind::Requirement => TagKindPy::Requirement,
+            TagKind::Satisfies => TagKindPy::Satisfies,
+            _ => TagKindPy::Other,
         }
     }
 }
@@ -231,6 +267,8 @@ impl SpanPy {
 #[derive(Debug, Clone)]
 pub struct DocumentationTagPy {
     pub kind: TagKindPy,
+    /// the tag as written, such as `@notice`, which identifies kinds that are [TagKindPy::Other]
+    pub keyword: String,
     pub description: String,
 }
 
@@ -238,6 +276,7 @@ impl From<&DocumentationTag> for DocumentationTagPy {
     fn from(value: &DocumentationTag) -> Self {
         DocumentationTagPy {
             kind: TagKindPy::from(&value.kind),
+            keyword: value.kind.as_keyword(),
             description: value.description.to_owned(),
         }
     }
@@ -259,7 +298,7 @@ impl DocumentationTagPy {
     }
 
     fn __repr__(&self) -> String {
-        let kind = self.kind.__str__();
+        let kind = self.keyword.trim_start_matches('@');
         let description = &self.description;
         format!("DocumentationTag({kind}, {description})")
     }
@@ -0,0 +1,136 @@
+//! iteration over documented elements, optionally of a single kind,
+//! with the fields of that kind already destructured.
+//!
+//! ```
+//! use cvldoc_parser_core::parse::builder::Builder;
+//! use cvldoc_parser_core::{query, TagKind};
+//!
+//! let src = "
+//!     /// @notice the supply is the sum of all balances
+//!     invariant totalIsSum() to_mathint(totalSupply()) == sumOfBalances;
+//!
+//!     invariant undocumented() true;
+//! ";
+//! let docs = Builder::new(src).build().unwrap();
+//!
+//! let invariants: Vec<_> = query::invariants(&docs)
+//!     .map(|invariant| (invariant.name, invariant.invariant, invariant.doc[0].kind.clone()))
+//!     .collect();
+//!
+//! assert_eq!(
+//!     invariants,
+//!     [(
+//!         "totalIsSum",
+//!         "to_mathint(totalSupply()) == sumOfBalances;",
+//!         TagKind::Notice
+//!     )]
+//! );
+//! ```
+
+use crate::{Ast, CvlElement, DocumentationTag, Param};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DocumentedRule<'a> {
+    pub element: &'a CvlElement,
+    pub name: &'a str,
+    pub params: &'a [Param],
+    pub filters: Option<&'a str>,
+    pub doc: &'a [DocumentationTag],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DocumentedInvariant<'a> {
+    pub element: &'a CvlElement,
+    pub name: &'a str,
+    pub params: &'a [Param],
+    /// the invariant expression
+    pub invariant: &'a str,
+    pub filters: Option<&'a str>,
+    pub doc: &'a [DocumentationTag],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DocumentedFunction<'a> {
+    pub element: &'a CvlElement,
+    pub name: &'a str,
+    pub params: &'a [Param],
+    pub returns: Option<&'a str>,
+    pub doc: &'a [DocumentationTag],
+}
+
+/// the elements of `docs` which have documentation, paired with it.
+/// freeform comments, file documentation and detached documentation are skipped,
+/// since they document no element.
+pub fn documented(docs: &[CvlElement]) -> impl Iterator<Item = (&Ast, &[DocumentationTag])> {
+    documented_elements(docs)
+        .filter(|element| {
+            !matches!(
+                element.ast,
+                Ast::FreeFormComment { .. } | Ast::FileDocumentation | Ast::Detached
+            )
+        })
+        .map(|element| (&element.ast, element.doc.as_slice()))
+}
+
+/// the documented rules of `docs`
+pub fn rules(docs: &[CvlElement]) -> impl Iterator<Item = DocumentedRule<'_>> {
+    documented_elements(docs).filter_map(|element| match &element.ast {
+        Ast::Rule {
+            name,
+            params,
+            filters,
+            ..
+        } => Some(DocumentedRule {
+            element,
+            name,
+            params,
+            filters: filters.as_deref(),
+            doc: &element.doc,
+        }),
+        _ => None,
+    })
+}
+
+/// the documented invariants of `docs`, including strong invariants
+pub fn invariants(docs: &[CvlElement]) -> impl Iterator<Item = DocumentedInvariant<'_>> {
+    documented_elements(docs).filter_map(|element| match &element.ast {
+        Ast::Invariant {
+            name,
+            params,
+            invariant,
+            filters,
+            ..
+        } => Some(DocumentedInvariant {
+            element,
+            name,
+            params,
+            invariant,
+            filters: filters.as_deref(),
+            doc: &element.doc,
+        }),
+        _ => None,
+    })
+}
+
+/// the documented CVL functions of `docs`
+pub fn functions(docs: &[CvlElement]) -> impl Iterator<Item = DocumentedFunction<'_>> {
+    documented_elements(docs).filter_map(|element| match &element.ast {
+        Ast::Function {
+            name,
+            params,
+            returns,
+            ..
+        } => Some(DocumentedFunction {
+            element,
+            name,
+            params,
+            returns: returns.as_deref(),
+            doc: &element.doc,
+        }),
+        _ => None,
+    })
+}
+
+fn documented_elements(docs: &[CvlElement]) -> impl Iterator<Item = &CvlElement> {
+    docs.iter().filter(|element| !element.doc.is_empty())
+}
@@ -0,0 +1,510 @@
+//! rendering of documentation as HTML fragments, for embedding in other pages.
+//!
+//! all text from the source is escaped. descriptions go through a minimal Markdown conversion,
+//! which supports paragraphs, emphasis, code spans and links. links are only kept
+//! for `http`, `https` and `mailto` URLs, and for relative URLs.
+//!
+//! the output has no styles of its own. elements are marked with classes instead,
+//! for which [STYLESHEET] is a starting point.
+
+use crate::coverage::MethodsCoverage;
+use crate::extract::assert_messages;
+use crate::parse::mapping::normalize_type;
+use crate::util::slugify_all;
+use crate::{Ast, CvlElement, DocumentationTag, Param, TagKind, TagProvenance};
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HtmlOptions {
+    /// the level of the heading of each element, from 1 to 6
+    pub heading_level: u8,
+    /// whether to render elements without documentation
+    pub include_undocumented: bool,
+    /// whether to render decorative freeform comments, such as `//// ======`
+    pub include_decorative: bool,
+    /// whether to list the messages of `assert` and `require` statements of each element,
+    /// under an "Assertions" heading. see [crate::extract::assert_messages].
+    pub include_assertions: bool,
+    /// whether to mark tags inherited from another element with the `inherited` class,
+    /// followed by an `attribution` naming that element. see [TagProvenance::Inherited].
+    pub mark_inherited: bool,
+}
+
+/// styles for the classes of the output: inherited tags are in italics, with a muted attribution
+pub const STYLESHEET: &str = "\
+.inherited { font-style: italic; }
+.attribution { color: #6a737d; font-size: smaller; }
+.undocumented-summary { background-color: #fff5b1; }
+";
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        HtmlOptions {
+            heading_level: 2,
+            include_undocumented: false,
+            include_decorative: false,
+            include_assertions: false,
+            mark_inherited: false,
+        }
+    }
+}
+
+/// renders each element as a `<section>`, with the element signature wrapped in `<code class="cvl">`,
+/// and `@param` tags as a `<dl>`. the output only depends on `docs` and `opts`.
+pub fn to_html(docs: &[CvlElement], opts: &HtmlOptions) -> String {
+    let slugs = slugify_all(docs);
+    let level = opts.heading_level.clamp(1, 6);
+    let mut html = String::new();
+
+    for element in docs {
+        let is_freeform = element.is_freeform();
+        if element.doc.is_empty() && !is_freeform && !opts.include_undocumented {
+            continue;
+        }
+        if element.is_decorative() && !opts.include_decorative {
+            continue;
+        }
+
+        let kind = element.ast.to_string().replace(' ', "-");
+        match slugs.get(&(element as *const CvlElement)) {
+            Some(slug) => writeln!(
+                html,
+                r#"<section class="cvl-{kind}" id="{}">"#,
+                escape(slug)
+            ),
+            None => writeln!(html, r#"<section class="cvl-{kind}">"#),
+        }
+        .unwrap();
+
+        if let Ast::FreeFormComment { text, .. } = &element.ast {
+            html.push_str(&markdown_to_html(text));
+            html.push_str("</section>\n");
+            continue;
+        }
+
+        let heading = element
+            .doc
+            .iter()
+            .find(|tag| tag.kind == TagKind::Title)
+            .map(|tag| inline_to_html(&tag.description))
+            .or_else(|| element.element_name().map(escape))
+            .unwrap_or_else(|| escape(&element.ast.to_string()));
+        let badge = match element.ast.is_parametric() {
+            true => r#" <span class="badge">parametric</span>"#,
+            false => "",
+        };
+        writeln!(html, "<h{level}>{heading}{badge}</h{level}>").unwrap();
+
+        if let Some(signature) = signature(&element.ast) {
+            writeln!(html, r#"<pre><code class="cvl">{signature}</code></pre>"#).unwrap();
+        }
+
+        render_tags(&mut html, &element.doc, opts);
+        if let Ast::Methods {
+            coverage: Some(coverage),
+            ..
+        } = &element.ast
+        {
+            render_methods_coverage(&mut html, coverage);
+        }
+        if opts.include_assertions {
+            render_assertions(&mut html, element, (level + 1).min(6));
+        }
+        html.push_str("</section>\n");
+    }
+
+    html
+}
+
+fn render_tags(html: &mut String, doc: &[DocumentationTag], opts: &HtmlOptions) {
+    let inherited_from = |tag: &DocumentationTag| match &tag.provenance {
+        TagProvenance::Inherited { from } if opts.mark_inherited => Some(escape(from)),
+        _ => None,
+    };
+
+    for tag in doc {
+        let mut rendered = String::new();
+        match tag.kind {
+            TagKind::Title | TagKind::Param => continue,
+            TagKind::Notice => rendered.push_str(&markdown_to_html(&tag.description)),
+            TagKind::Formula => {
+                let formula = escape(&tag.description);
+                writeln!(
+                    rendered,
+                    r#"<pre class="formula"><code>{formula}</code></pre>"#
+                )
+                .unwrap();
+            }
+            _ => {
+                let class = tag.kind.as_str();
+                let description = markdown_to_html(&tag.description);
+                write!(rendered, r#"<div class="{class}">{description}</div>"#).unwrap();
+                rendered.push('\n');
+            }
+        }
+
+        match inherited_from(tag) {
+            Some(from) => writeln!(
+                html,
+                r#"<div class="inherited">{rendered}<p class="attribution">inherited from {from}</p></div>"#
+            )
+            .unwrap(),
+            None => html.push_str(&rendered),
+        }
+    }
+
+    let params = doc.iter().filter_map(|tag| {
+        let name = tag.param_name()?;
+        let description = tag.param_description()?;
+        Some((name, tag.param_type(), description, inherited_from(tag)))
+    });
+
+    let mut params = params.peekable();
+    if params.peek().is_some() {
+        html.push_str("<dl class=\"params\">\n");
+        for (name, ty, description, inherited_from) in params {
+            let name = escape(name);
+            let mut description = inline_to_html(description);
+            if let Some(ty) = ty {
+                description = format!("<code>{}</code> {description}", escape(ty));
+            }
+            match inherited_from {
+                Some(from) => writeln!(
+                    html,
+                    r#"<dt><code>{name}</code></dt><dd class="inherited">{description} <span class="attribution">inherited from {from}</span></dd>"#
+                ),
+                None => writeln!(html, "<dt><code>{name}</code></dt><dd>{description}</dd>"),
+            }
+            .unwrap();
+        }
+        html.push_str("</dl>\n");
+    }
+}
+
+fn render_assertions(html: &mut String, element: &CvlElement, level: u8) {
+    let messages = assert_messages(element);
+    if messages.is_empty() {
+        return;
+    }
+
+    writeln!(html, "<h{level}>Assertions</h{level}>").unwrap();
+    html.push_str("<ul class=\"assertions\">\n");
+    for (_, message) in messages {
+        writeln!(html, "<li>{}</li>", escape(&message)).unwrap();
+    }
+    html.push_str("</ul>\n");
+}
+
+/// a table of the entries of a `methods` block and their documentation, documented entries first.
+/// undocumented summaries are marked, see [MethodsCoverage::undocumented_summaries].
+fn render_methods_coverage(html: &mut String, coverage: &MethodsCoverage) {
+    if coverage.documented.is_empty() && coverage.undocumented.is_empty() {
+        return;
+    }
+
+    html.push_str("<table class=\"methods-coverage\">\n");
+    html.push_str("<tr><th>Entry</th><th>Documentation</th></tr>\n");
+    for entry in coverage.documented.iter().chain(&coverage.undocumented) {
+        let class = match entry.doc.is_none() && entry.is_summarized() {
+            true => r#" class="undocumented-summary""#,
+            false => "",
+        };
+        let text = escape(&entry.text);
+        let doc = entry.doc.as_deref().map(inline_to_html).unwrap_or_default();
+        writeln!(
+            html,
+            "<tr{class}><td><code>{text}</code></td><td>{doc}</td></tr>"
+        )
+        .unwrap();
+    }
+    html.push_str("</table>\n");
+}
+
+/// the highlighted declaration of the element, without its body
+fn signature(ast: &Ast) -> Option<String> {
+    let keyword = |kw: &str| format!(r#"<span class="keyword">{kw}</span>"#);
+    let name = |name: &str| format!(r#"<span class="name">{}</span>"#, escape(name));
+    let ty = |ty: &str| format!(r#"<span class="type">{}</span>"#, escape(ty));
+    let params = |params: &[Param]| {
+        let params = params
+            .iter()
+            .map(|param| format!("{} {}", ty(&param.ty), escape(&param.name)))
+            .join(", ");
+        format!("({params})")
+    };
+    let returns = |returns: Option<&str>| match returns {
+        Some(returns) => format!(" {} {}", keyword("returns"), ty(returns)),
+        None => String::new(),
+    };
+    let persistent = |persistent: bool| match persistent {
+        true => format!("{} ", keyword("persistent")),
+        false => String::new(),
+    };
+
+    let signature = match ast {
+        Ast::FreeFormComment { .. } | Ast::FileDocumentation | Ast::Detached => return None,
+        Ast::Rule {
+            name: rule_name,
+            params: rule_params,
+            ..
+        } => format!(
+            "{} {}{}",
+            keyword("rule"),
+            name(rule_name),
+            params(rule_params)
+        ),
+        Ast::Invariant {
+            strong,
+            name: invariant_name,
+            params: invariant_params,
+            ..
+        } => {
+            let kw = if *strong {
+                "strong invariant"
+            } else {
+                "invariant"
+            };
+            format!(
+                "{} {}{}",
+                keyword(kw),
+                name(invariant_name),
+                params(invariant_params)
+            )
+        }
+        Ast::Function {
+            name: function_name,
+            params: function_params,
+            returns: function_returns,
+            ..
+        } => format!(
+            "{} {}{}{}",
+            keyword("function"),
+            name(function_name),
+            params(function_params),
+            returns(function_returns.as_deref())
+        ),
+        Ast::Definition {
+            name: definition_name,
+            params: definition_params,
+            returns: definition_returns,
+            ..
+        } => format!(
+            "{} {}{}{}",
+            keyword("definition"),
+            name(definition_name),
+            params(definition_params),
+            returns(Some(definition_returns))
+        ),
+        Ast::CVLMacro {
+            name: macro_name,
+            params: macro_params,
+            ..
+        } => format!(
+            "{} {}{}",
+            keyword("define macro"),
+            name(macro_name),
+            params(macro_params)
+        ),
+        Ast::GhostFunction {
+            persistent: is_persistent,
+            name: ghost_name,
+            ty_list,
+            returns: ghost_returns,
+            ..
+        } => format!(
+            "{}{} {}({}){}",
+            persistent(*is_persistent),
+            keyword("ghost"),
+            name(ghost_name),
+            ty_list.iter().map(|t| ty(t)).join(", "),
+            returns(Some(ghost_returns))
+        ),
+        Ast::GhostMapping {
+            persistent: is_persistent,
+            name: ghost_name,
+            mapping,
+            mapping_type,
+            ..
+        } => {
+            let mapping = match mapping_type {
+                Some(mapping_type) => mapping_type.to_string(),
+                None => normalize_type(mapping),
+            };
+            format!(
+                "{}{} {} {}",
+                persistent(*is_persistent),
+                keyword("ghost"),
+                ty(&mapping),
+                name(ghost_name)
+            )
+        }
+        Ast::Unknown { raw_header, .. } => escape(raw_header),
+        ast => keyword(&ast.to_string()),
+    };
+
+    Some(signature)
+}
+
+/// escapes the characters that are significant in HTML text and attribute values
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// splits `text` into paragraphs on blank lines, and wraps each in `<p>`
+fn markdown_to_html(text: &str) -> String {
+    let lines = text.lines().map(str::trim).collect_vec();
+
+    lines
+        .split(|line| line.is_empty())
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| format!("<p>{}</p>\n", inline_to_html(&paragraph.join("\n"))))
+        .collect()
+}
+
+/// converts code spans, `**strong**`, `*emphasis*`, `_emphasis_` and `[links](url)`,
+/// escaping all other text. unmatched delimiters are kept as text.
+/// emphasis is matched in a single pass: each delimiter closes the nearest open delimiter of
+/// its kind, and delimiters opened after that one are left unmatched.
+fn inline_to_html(text: &str) -> String {
+    let chars = text.chars().collect_vec();
+    // the html of each delimiter and each run of text. an open delimiter is replaced by
+    // its opening tag once it is closed
+    let mut pieces: Vec<String> = Vec::new();
+    // each open delimiter, its index in `pieces`, and the index of the char following it
+    let mut open: Vec<(&str, usize, usize)> = Vec::new();
+    // how many delimiters of each kind are open, so that the stack is only searched when
+    // it holds an opener. everything above the opener is closed with it, so each open
+    // delimiter is searched past at most once
+    let mut open_count: HashMap<&str, usize> = HashMap::new();
+    // the shortest run of backticks which is known not to occur past `i`
+    let mut unclosed_run = usize::MAX;
+    // the first `](` past `i`, the `)` following it, and whether the url between them is safe.
+    // it is searched for again only once `i` passes it
+    let mut next_link: Option<Option<(usize, usize, bool)>> = None;
+    let mut i = 0;
+
+    let find_from = |from: usize, pattern: &[char]| {
+        (from..chars.len()).find(|&at| chars[at..].starts_with(pattern))
+    };
+    let slice = |from: usize, to: usize| chars[from..to].iter().collect::<String>();
+
+    while i < chars.len() {
+        let delimiter = match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => Some("**"),
+            '*' => Some("*"),
+            '_' => Some("_"),
+            _ => None,
+        };
+        if let Some(delimiter) = delimiter {
+            let after = i + delimiter.len();
+            let opener = match open_count.get(delimiter) {
+                Some(&count) if count > 0 => open
+                    .iter()
+                    .rposition(|&(open_delimiter, _, _)| open_delimiter == delimiter)
+                    .filter(|&at| open[at].2 < i),
+                _ => None,
+            };
+
+            if let Some(at) = opener {
+                let (_, piece, _) = open[at];
+                let tag = if delimiter == "**" { "strong" } else { "em" };
+                pieces[piece] = format!("<{tag}>");
+                pieces.push(format!("</{tag}>"));
+                for (unmatched, _, _) in open.drain(at..) {
+                    *open_count.entry(unmatched).or_default() -= 1;
+                }
+            } else {
+                // `_` within a word, as in `total_supply`, is not emphasis
+                let starts_word = i == 0 || !chars[i - 1].is_alphanumeric();
+                if delimiter != "_" || starts_word {
+                    open.push((delimiter, pieces.len(), after));
+                    *open_count.entry(delimiter).or_default() += 1;
+                }
+                pieces.push(delimiter.to_string());
+            }
+            i = after;
+            continue;
+        }
+
+        let mut html = String::new();
+        match chars[i] {
+            '`' => {
+                let run = chars[i..].iter().take_while(|&&c| c == '`').count();
+                let closing = if run < unclosed_run {
+                    find_from(i + run, &vec!['`'; run])
+                } else {
+                    None
+                };
+
+                if let Some(closing) = closing {
+                    let code = slice(i + run, closing);
+                    write!(html, "<code>{}</code>", escape(code.trim())).unwrap();
+                    i = closing + run;
+                } else {
+                    unclosed_run = unclosed_run.min(run);
+                    html.push_str(&"`".repeat(run));
+                    i += run;
+                }
+            }
+            '[' => {
+                if matches!(next_link, Some(Some((middle, _, _))) if middle <= i) {
+                    next_link = None;
+                }
+                let link = *next_link.get_or_insert_with(|| {
+                    let middle = find_from(i + 1, &[']', '('])?;
+                    let end = find_from(middle + 2, &[')'])?;
+                    Some((middle, end, is_safe_url(&slice(middle + 2, end))))
+                });
+
+                match link {
+                    Some((middle, end, true)) => {
+                        let url = escape(slice(middle + 2, end).trim());
+                        let label = inline_to_html(&slice(i + 1, middle));
+                        write!(html, r#"<a href="{url}">{label}</a>"#).unwrap();
+                        i = end + 1;
+                    }
+                    _ => {
+                        html.push('[');
+                        i += 1;
+                    }
+                }
+            }
+            c => {
+                html.push_str(&escape(&c.to_string()));
+                i += 1;
+            }
+        }
+        pieces.push(html);
+    }
+
+    pieces.concat()
+}
+
+/// relative URLs, and URLs with an allowed scheme. this rejects `javascript:` URLs, among others.
+fn is_safe_url(url: &str) -> bool {
+    let url = url.trim();
+    let scheme_end = url.find([':', '/', '?', '#']);
+
+    match scheme_end {
+        Some(end) if url[end..].starts_with(':') => {
+            let scheme = url[..end].to_ascii_lowercase();
+            matches!(scheme.as_str(), "http" | "https" | "mailto")
+        }
+        _ => true,
+    }
+}
@@ -0,0 +1,22 @@
+//! traceability of requirements, as listed by `@requirement` and `@satisfies` tags.
+
+use crate::CvlElement;
+use std::collections::BTreeMap;
+
+/// maps each requirement ID to the elements that list it, in order.
+/// an element is listed once per ID, even if more than one of its tags lists it.
+pub fn matrix(docs: &[CvlElement]) -> BTreeMap<String, Vec<&CvlElement>> {
+    let mut matrix: BTreeMap<String, Vec<&CvlElement>> = BTreeMap::new();
+
+    for element in docs {
+        for id in element.doc.iter().flat_map(|tag| tag.requirement_ids()) {
+            let elements = matrix.entry(id.to_string()).or_default();
+
+            if !elements.iter().any(|listed| std::ptr::eq(*listed, element)) {
+                elements.push(element);
+            }
+        }
+    }
+
+    matrix
+}
@@ -0,0 +1,66 @@
+//! removal of documentation from a spec, for measuring code against docs,
+//! or for producing minimized reproductions.
+
+use crate::parse::builder::Builder;
+use crate::parse::types::Token;
+use crate::util::{count_lines, strip_bom, ByteSpan};
+use eyre::{eyre, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StripMode {
+    /// comments are removed entirely
+    #[default]
+    Remove,
+    /// each line of a removed comment is replaced with an empty line,
+    /// so that line numbers of the remaining text are unchanged
+    KeepLineNumbers,
+}
+
+/// removes all documentation, freeform and file documentation comments from `src`.
+/// see [remove_docs_with].
+pub fn remove_docs(src: &str) -> Result<String> {
+    remove_docs_with(src, StripMode::Remove)
+}
+
+/// removes the comments the parser would take as documentation: `///`, `/** */`, `////`,
+/// `/*** ***/`, `//!` and `/*! */`. comments within element bodies are not documentation,
+/// so they are kept. all other text is unchanged, including indentation before a removed comment,
+/// and a byte order mark at the start of `src`.
+pub fn remove_docs_with(src: &str, mode: StripMode) -> Result<String> {
+    let lexed = Builder::new(src).lex()?;
+
+    // spans are relative to the source without its byte order mark
+    let (bom, src) = src.split_at(src.len() - strip_bom(src).len());
+    let mut stripped = String::with_capacity(src.len() + bom.len());
+    stripped.push_str(bom);
+    let mut kept_from = 0;
+
+    for (token, span) in lexed {
+        if !matches!(
+            token,
+            Token::CvlDocSlashed
+                | Token::CvlDocStarred
+                | Token::FreeFormSlashed
+                | Token::FreeFormStarred
+                | Token::FileDocSlashed
+                | Token::FileDocStarred
+        ) {
+            continue;
+        }
+
+        let byte_span = span
+            .to_byte_span(src)
+            .ok_or_else(|| eyre!("{span:?}: not in source bounds"))?;
+        stripped.push_str(&src[kept_from..byte_span.start]);
+
+        if mode == StripMode::KeepLineNumbers {
+            let newlines = count_lines(&src[byte_span.clone()]) - 1;
+            stripped.extend(std::iter::repeat_n('\n', newlines));
+        }
+
+        kept_from = byte_span.end;
+    }
+
+    stripped.push_str(&src[kept_from..]);
+    Ok(stripped)
+}
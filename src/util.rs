@@ -1,14 +1,71 @@
-use color_eyre::eyre::ContextCompat;
-use color_eyre::Result;
-use lsp_types::{Position, Range};
+use crate::CvlElement;
+use eyre::{ContextCompat, Result};
+#[cfg(feature = "lsp")]
+use lsp_types::{Position, Range, TextEdit};
 use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::ops::RangeBounds;
 
 pub type Span = std::ops::Range<usize>;
 pub type Spanned<T> = (T, Span);
 
+#[cfg(feature = "lsp")]
 pub type Ranged<T> = (T, Range);
 
+/// a line/column position, mirroring [lsp_types::Position] so that it is usable
+/// without the `lsp` feature. both fields are zero-based.
+/// it is serialized exactly as [lsp_types::Position] is.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+pub struct SimplePosition {
+    pub line: u32,
+    pub character: u32,
+}
+
+/// a range of [SimplePosition]s, mirroring [lsp_types::Range].
+/// it is serialized exactly as [lsp_types::Range] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct SimpleRange {
+    pub start: SimplePosition,
+    pub end: SimplePosition,
+}
+
+#[cfg(feature = "lsp")]
+impl From<SimplePosition> for Position {
+    fn from(SimplePosition { line, character }: SimplePosition) -> Position {
+        Position { line, character }
+    }
+}
+
+#[cfg(feature = "lsp")]
+impl From<Position> for SimplePosition {
+    fn from(Position { line, character }: Position) -> SimplePosition {
+        SimplePosition { line, character }
+    }
+}
+
+#[cfg(feature = "lsp")]
+impl From<SimpleRange> for Range {
+    fn from(SimpleRange { start, end }: SimpleRange) -> Range {
+        Range {
+            start: start.into(),
+            end: end.into(),
+        }
+    }
+}
+
+#[cfg(feature = "lsp")]
+impl From<Range> for SimpleRange {
+    fn from(Range { start, end }: Range) -> SimpleRange {
+        SimpleRange {
+            start: start.into(),
+            end: end.into(),
+        }
+    }
+}
+
 /// converts from char indices to byte indices
 pub trait ByteSpan<'a> {
     fn to_byte_span(&self, s: &'a str) -> Option<Span>;
@@ -20,6 +77,16 @@ pub trait ByteSpan<'a> {
 
 impl<'a> ByteSpan<'a> for Span {
     fn to_byte_span(&self, s: &str) -> Option<Span> {
+        if self.is_empty() {
+            // an empty span may be at the end of `s`
+            let start = s
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain([s.len()])
+                .nth(self.start)?;
+            return Some(start..start);
+        }
+
         let mut iter = s.char_indices();
 
         let (start, _) = iter.nth(self.start)?;
@@ -47,7 +114,7 @@ impl RangeConverter {
         RangeConverter(rope)
     }
 
-    fn position_of(&self, char_idx: usize) -> Position {
+    fn position_of(&self, char_idx: usize) -> SimplePosition {
         let rope = &self.0;
         assert!(char_idx <= rope.len_chars());
 
@@ -55,13 +122,20 @@ impl RangeConverter {
         let line_start_idx = rope.line_to_char(line);
         let character = char_idx - line_start_idx;
 
-        Position {
+        SimplePosition {
             line: line as u32,
             character: character as u32,
         }
     }
 
-    fn char_idx_of(&self, pos: Position) -> usize {
+    /// like [RangeConverter::to_simple_range], but spans reaching past the end of the source,
+    /// such as those of parse errors at the end of the input, end at the end of the source
+    pub(crate) fn to_clamped_range(&self, span: Span) -> SimpleRange {
+        let len = self.0.len_chars();
+        self.to_simple_range(span.start.min(len)..span.end.min(len))
+    }
+
+    fn char_idx_of(&self, pos: SimplePosition) -> usize {
         let rope = &self.0;
         let [line, character] = [pos.line, pos.character].map(|n| n as usize);
 
@@ -72,18 +146,320 @@ impl RangeConverter {
         line_start_idx + character
     }
 
-    pub fn to_range(&self, span: Span) -> Range {
+    pub fn to_simple_range(&self, span: Span) -> SimpleRange {
         let [start, end] = [span.start, span.end].map(|char_idx| self.position_of(char_idx));
-        Range { start, end }
+        SimpleRange { start, end }
     }
 
-    pub fn to_span(&self, range: Range) -> Span {
-        let [start, end] = [range.start, range.end].map(|range| self.char_idx_of(range));
+    pub fn simple_range_to_span(&self, range: SimpleRange) -> Span {
+        let [start, end] = [range.start, range.end].map(|pos| self.char_idx_of(pos));
         start..end
     }
 
+    #[cfg(feature = "lsp")]
+    pub fn to_range(&self, span: Span) -> Range {
+        self.to_simple_range(span).into()
+    }
+
+    #[cfg(feature = "lsp")]
+    pub fn to_span(&self, range: Range) -> Span {
+        self.simple_range_to_span(range.into())
+    }
+
+    /// the column of `pos` as displayed with tabs expanded to the next multiple of `tab_width`.
+    /// positions and ranges themselves never expand tabs: as in LSP, a tab is one character.
+    pub fn visual_column(&self, pos: SimplePosition, tab_width: u32) -> u32 {
+        let line_start = self.char_idx_of(SimplePosition {
+            character: 0,
+            ..pos
+        });
+        let tab_width = tab_width.max(1);
+
+        self.0
+            .chars_at(line_start)
+            .take(pos.character as usize)
+            .fold(0, |column, c| match c {
+                '\t' => (column / tab_width + 1) * tab_width,
+                _ => column + 1,
+            })
+    }
+
     pub fn slice(&self, char_range: impl RangeBounds<usize>) -> Result<String> {
         let rope_slice = self.0.get_slice(char_range).wrap_err("not in range")?;
         Ok(rope_slice.to_string())
     }
 }
+
+/// the difference between two parses of the same file.
+#[derive(Debug, Clone, Default)]
+pub struct DocDiff {
+    pub added: Vec<CvlElement>,
+    pub removed: Vec<CvlElement>,
+    /// pairs of `(old, new)`
+    pub changed: Vec<(CvlElement, CvlElement)>,
+}
+
+/// compares two parses of the same file, identifying elements by the start of their span.
+/// an element counts as changed if its documentation, its ast, or its span differ.
+pub fn diff_docs(old: &[CvlElement], new: &[CvlElement]) -> DocDiff {
+    let old_by_start: HashMap<usize, &CvlElement> = old
+        .iter()
+        .map(|element| (element.span().start, element))
+        .collect();
+    let new_by_start: HashMap<usize, &CvlElement> = new
+        .iter()
+        .map(|element| (element.span().start, element))
+        .collect();
+
+    let mut diff = DocDiff::default();
+
+    for new_element in new {
+        match old_by_start.get(&new_element.span().start) {
+            None => diff.added.push(new_element.clone()),
+            Some(old_element) if !old_element.same_content(new_element) => {
+                let old_element = CvlElement::clone(old_element);
+                diff.changed.push((old_element, new_element.clone()))
+            }
+            Some(_) => (),
+        }
+    }
+
+    diff.removed = old
+        .iter()
+        .filter(|element| !new_by_start.contains_key(&element.span().start))
+        .cloned()
+        .collect();
+
+    diff
+}
+
+/// the edit turning `old` into `new`: a single [TextEdit] replacing everything between
+/// their longest common prefix and longest common suffix, with a range in `old`.
+/// there are no edits if the strings are equal.
+#[cfg(feature = "lsp")]
+pub fn compute_edit_diff(old: &str, new: &str) -> Vec<TextEdit> {
+    let old_chars: Vec<char> = old.chars().collect();
+    let new_chars: Vec<char> = new.chars().collect();
+
+    let prefix = old_chars
+        .iter()
+        .zip(&new_chars)
+        .take_while(|(old, new)| old == new)
+        .count();
+    if prefix == old_chars.len() && prefix == new_chars.len() {
+        return Vec::new();
+    }
+
+    // the suffix may not overlap the prefix in either string
+    let max_suffix = old_chars.len().min(new_chars.len()) - prefix;
+    let suffix = old_chars
+        .iter()
+        .rev()
+        .zip(new_chars.iter().rev())
+        .take(max_suffix)
+        .take_while(|(old, new)| old == new)
+        .count();
+
+    let converter = RangeConverter::new(Rope::from_str(old));
+    let edit = TextEdit {
+        range: converter.to_range(prefix..old_chars.len() - suffix),
+        new_text: new_chars[prefix..new_chars.len() - suffix].iter().collect(),
+    };
+
+    vec![edit]
+}
+
+/// whether `receiver` is used as the receiver of a call in `text`, as in `receiver.method`
+pub(crate) fn mentions_receiver(text: &str, receiver: &str) -> bool {
+    let is_ident_char = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+
+    text.match_indices(receiver).any(|(i, _)| {
+        let starts_word = !text[..i].ends_with(is_ident_char);
+        starts_word && text[i + receiver.len()..].starts_with('.')
+    })
+}
+
+/// lowercases `s`, and collapses any run of non-alphanumeric characters to a single hyphen.
+pub(crate) fn slugify(s: &str) -> String {
+    let mut slug = String::new();
+
+    for ch in s.chars() {
+        if ch.is_alphanumeric() {
+            slug.extend(ch.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+
+    slug
+}
+
+/// computes the [CvlElement::slug] of every element that has one, making them unique
+/// by appending `-2`, `-3`, etc. to repeated slugs, in source order.
+pub fn slugify_all(elements: &[CvlElement]) -> HashMap<*const CvlElement, String> {
+    let mut taken = HashSet::new();
+    let mut slugs = HashMap::new();
+
+    for element in elements {
+        let Some(slug) = element.slug() else {
+            continue;
+        };
+
+        let mut unique_slug = slug.clone();
+        let mut n = 1;
+
+        while !taken.insert(unique_slug.clone()) {
+            n += 1;
+            unique_slug = format!("{slug}-{n}");
+        }
+
+        slugs.insert(element as *const CvlElement, unique_slug);
+    }
+
+    slugs
+}
+
+/// words which cannot be used as the name of an element.
+const CVL_KEYWORDS: &[&str] = &[
+    "as",
+    "assert",
+    "axiom",
+    "builtin",
+    "definition",
+    "else",
+    "exists",
+    "false",
+    "filtered",
+    "forall",
+    "function",
+    "ghost",
+    "hook",
+    "if",
+    "import",
+    "invariant",
+    "mapping",
+    "methods",
+    "override",
+    "persistent",
+    "preserved",
+    "require",
+    "return",
+    "returns",
+    "rule",
+    "true",
+    "use",
+    "using",
+];
+
+/// whether `s` is a valid CVL identifier: it starts with a letter or an underscore,
+/// continues with letters, digits, underscores or dollar signs, and is not a keyword.
+pub fn is_cvl_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+
+    let valid_start = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    let valid_rest = chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+
+    valid_start && valid_rest && !CVL_KEYWORDS.contains(&s)
+}
+
+/// `src` without its UTF-8 byte order mark, if it starts with one.
+/// the mark is a single character, so spans in the result are one character before those in `src`.
+pub fn strip_bom(src: &str) -> &str {
+    src.strip_prefix('\u{feff}').unwrap_or(src)
+}
+
+/// the number of lines of `s`, numbered as in an editor: one more than the number of `\n`.
+/// an empty string has 1 line, and a trailing `\n` starts a final empty line,
+/// so `"a\n"` has 2 lines. the number of line breaks is `count_lines(s) - 1`.
+///
+/// only `\n` breaks lines, so `\r\n` is a single line break. this agrees with
+/// [ropey::Rope::len_lines] for text whose line breaks are `\n` or `\r\n`, but ropey also
+/// breaks lines at a lone `\r` and at Unicode line breaks such as U+2028, which are not
+/// counted here.
+pub fn count_lines(s: &str) -> usize {
+    s.bytes().filter(|&b| b == b'\n').count() + 1
+}
+
+/// maps byte offsets in the description of a tag to byte offsets in the source it was parsed from.
+///
+/// a description is made of runs of source text. between runs, comment markers, indentation
+/// and trailing whitespace were removed, and line terminators were copied from elsewhere in the line.
+/// tags which were not parsed from a source, such as those of [crate::DocumentationTag::new],
+/// have an empty map.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SourceMap {
+    /// `(description offset, source offset, length)` of each run, in order
+    runs: Vec<(usize, usize, usize)>,
+}
+
+impl SourceMap {
+    /// appends a run of `len` bytes, at `cleaned` in the description and `original` in the source
+    pub(crate) fn push(&mut self, cleaned: usize, original: usize, len: usize) {
+        if len > 0 {
+            self.runs.push((cleaned, original, len));
+        }
+    }
+
+    /// drops the parts of runs at or after `len`, for when the description was truncated
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.runs.retain(|&(cleaned, _, _)| cleaned < len);
+        if let Some((cleaned, _, run_len)) = self.runs.last_mut() {
+            *run_len = (*run_len).min(len - *cleaned);
+        }
+    }
+
+    pub(crate) fn shifted(mut self, by: usize) -> SourceMap {
+        for (_, original, _) in &mut self.runs {
+            *original += by;
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// the anchor pairs `(description offset, source offset)` at the start of each run
+    pub fn anchors(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.runs
+            .iter()
+            .map(|&(cleaned, original, _)| (cleaned, original))
+    }
+
+    /// the source offset of the byte at `cleaned_offset` in the description.
+    /// an offset which is not copied from the source, such as that of a line terminator,
+    /// maps to the end of the preceding run, as does an offset past the end of the description.
+    /// returns `None` if the map is empty.
+    pub fn original_position(&self, cleaned_offset: usize) -> Option<usize> {
+        self.map_offset(|cleaned| cleaned <= cleaned_offset, cleaned_offset)
+    }
+
+    /// the source byte range of `cleaned`, a range of the description.
+    /// it includes the removed decoration if `cleaned` spans several lines,
+    /// but a range ending at the end of a line stops before the decoration of the next one.
+    pub fn original_range(&self, cleaned: Span) -> Option<Span> {
+        let start = self.original_position(cleaned.start)?;
+        let end = self.map_offset(|run_start| run_start < cleaned.end, cleaned.end)?;
+        Some(start..end.max(start))
+    }
+
+    /// maps `offset` within the last run whose start satisfies `in_run`, or before the first run
+    fn map_offset(&self, in_run: impl Fn(usize) -> bool, offset: usize) -> Option<usize> {
+        let first = self.runs.first()?;
+        let i = self
+            .runs
+            .partition_point(|&(cleaned, _, _)| in_run(cleaned));
+        if i == 0 {
+            return Some(first.1);
+        }
+
+        let (cleaned, original, len) = self.runs[i - 1];
+        Some(original + (offset - cleaned).min(len))
+    }
+}
@@ -0,0 +1,255 @@
+//! parsing of a spec together with the specs it imports, transitively.
+
+use crate::parse::builder::Builder;
+use crate::util::{strip_bom, Span};
+use crate::{Ast, CvlElement};
+use eyre::Result;
+use std::collections::{HashSet, VecDeque};
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceFile {
+    pub path: String,
+    pub src: String,
+    /// the range of the elements of this file in [Workspace::docs]
+    pub elements: Range<usize>,
+}
+
+/// an `import` statement whose file the resolver could not find
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnresolvedImport {
+    /// the path of the importing file
+    pub importer: String,
+    /// the imported path, relative to the workspace, as passed to the resolver
+    pub path: String,
+    /// the span of the `import` statement in the importing file
+    pub span: Span,
+}
+
+/// an imported file which was found, but could not be parsed. it is not loaded,
+/// and the files it imports are not followed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedImport {
+    /// the path of the importing file
+    pub importer: String,
+    /// the imported path, relative to the workspace, as passed to the resolver
+    pub path: String,
+    /// the span of the `import` statement in the importing file
+    pub span: Span,
+    /// the parse error
+    pub error: String,
+}
+
+/// a collection of spec files, loaded along with everything they import.
+///
+/// imported paths are relative to the directory of the importing file, and are normalized
+/// before being passed to the resolver, so `specs/a.spec` importing `"../common.spec"`
+/// resolves `common.spec`. each file is loaded at most once, so import cycles terminate.
+pub struct Workspace<R> {
+    resolver: R,
+    docs: Vec<CvlElement>,
+    files: Vec<WorkspaceFile>,
+    unresolved_imports: Vec<UnresolvedImport>,
+    failed_imports: Vec<FailedImport>,
+}
+
+impl<R: Fn(&str) -> Option<String>> Workspace<R> {
+    pub fn new(resolver: R) -> Self {
+        Workspace {
+            resolver,
+            docs: Vec::new(),
+            files: Vec::new(),
+            unresolved_imports: Vec::new(),
+            failed_imports: Vec::new(),
+        }
+    }
+
+    /// parses `src` as the file at `path`, then loads the files it imports, in order.
+    /// a byte order mark at the start of a file is not kept in [WorkspaceFile::src].
+    /// files which are already loaded are skipped. fails only if `src` itself cannot be parsed,
+    /// in which case nothing is loaded. imported files which cannot be parsed are skipped,
+    /// and listed in [Workspace::failed_imports].
+    pub fn load(&mut self, path: &str, src: &str) -> Result<()> {
+        let mut seen: HashSet<String> = self.files.iter().map(|file| file.path.clone()).collect();
+        if !seen.insert(path.to_string()) {
+            return Ok(());
+        }
+
+        // each file is queued with the import statement that led to it, if any
+        let src = strip_bom(src).to_string();
+        let mut queue = VecDeque::from([(path.to_string(), src, None::<UnresolvedImport>)]);
+
+        while let Some((path, src, import)) = queue.pop_front() {
+            let elements = match (Builder::new(&src).build(), import) {
+                (Ok(elements), _) => elements,
+                // the root file is the first in the queue, so nothing was loaded yet
+                (Err(error), None) => return Err(error),
+                (Err(error), Some(import)) => {
+                    self.failed_imports.push(FailedImport {
+                        importer: import.importer,
+                        path: import.path,
+                        span: import.span,
+                        error: error.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            for element in &elements {
+                let Ast::Import { imported } = &element.ast else {
+                    continue;
+                };
+
+                let import_path = join_import(&path, imported);
+                if !seen.insert(import_path.clone()) {
+                    continue;
+                }
+
+                let import = UnresolvedImport {
+                    importer: path.clone(),
+                    path: import_path.clone(),
+                    span: element.element_span.clone(),
+                };
+                match (self.resolver)(&import_path) {
+                    Some(imported_src) => {
+                        let imported_src = strip_bom(&imported_src).to_string();
+                        queue.push_back((import_path, imported_src, Some(import)))
+                    }
+                    None => self.unresolved_imports.push(import),
+                }
+            }
+
+            let start = self.docs.len();
+            self.docs.extend(elements);
+            self.files.push(WorkspaceFile {
+                path,
+                src,
+                elements: start..self.docs.len(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// the elements of all loaded files, in load order.
+    /// this can serve as the index of [crate::links::resolve], to link across files.
+    pub fn docs(&self) -> &[CvlElement] {
+        &self.docs
+    }
+
+    pub fn files(&self) -> &[WorkspaceFile] {
+        &self.files
+    }
+
+    /// the file an element of [Workspace::docs] belongs to
+    pub fn file_of(&self, element_index: usize) -> Option<&WorkspaceFile> {
+        self.files
+            .iter()
+            .find(|file| file.elements.contains(&element_index))
+    }
+
+    /// the first loaded element with this name
+    pub fn resolve_name(&self, name: &str) -> Option<&CvlElement> {
+        self.docs
+            .iter()
+            .find(|element| element.element_name() == Some(name))
+    }
+
+    pub fn unresolved_imports(&self) -> &[UnresolvedImport] {
+        &self.unresolved_imports
+    }
+
+    pub fn failed_imports(&self) -> &[FailedImport] {
+        &self.failed_imports
+    }
+}
+
+#[cfg(feature = "lsp")]
+impl<R: Fn(&str) -> Option<String>> Workspace<R> {
+    /// the diagnostics of every loaded file, paired with the path of the file:
+    /// those of each element, those of [crate::diagnostics::check_collection] across all files,
+    /// an `unresolved-import` error for each of [Workspace::unresolved_imports],
+    /// and an `unparsable-import` error for each of [Workspace::failed_imports],
+    /// both at the `import` statement.
+    pub fn diagnostics(&self) -> Vec<(String, lsp_types::Diagnostic)> {
+        use crate::diagnostics::{check_collection, CollectionConfig};
+        use crate::util::RangeConverter;
+        use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+        use ropey::Rope;
+
+        let converter = |src: &str| RangeConverter::new(Rope::from_str(src));
+        let mut diagnostics = Vec::new();
+
+        for file in &self.files {
+            let converter = converter(&file.src);
+            let elements = &self.docs[file.elements.clone()];
+
+            let element_diagnostics = elements
+                .iter()
+                .flat_map(|element| element.enumerate_diagnostics(converter.clone()));
+            diagnostics.extend(element_diagnostics.map(|diag| (file.path.clone(), diag)));
+        }
+
+        for (i, diag) in check_collection(&self.docs, &CollectionConfig::default()) {
+            if let Some(file) = self.file_of(i) {
+                diagnostics.push((file.path.clone(), diag));
+            }
+        }
+
+        let unresolved = self.unresolved_imports.iter().map(|import| {
+            let message = format!("cannot find imported file: {}", import.path);
+            (&import.importer, &import.span, "unresolved-import", message)
+        });
+        let failed = self.failed_imports.iter().map(|import| {
+            let message = format!(
+                "cannot parse imported file {}: {}",
+                import.path, import.error
+            );
+            (&import.importer, &import.span, "unparsable-import", message)
+        });
+
+        for (importer, span, code, message) in unresolved.chain(failed) {
+            let Some(file) = self.files.iter().find(|file| &file.path == importer) else {
+                continue;
+            };
+
+            let diag = Diagnostic {
+                range: converter(&file.src).to_range(span.clone()),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String(code.to_string())),
+                message,
+                ..Default::default()
+            };
+            diagnostics.push((file.path.clone(), diag));
+        }
+
+        diagnostics
+    }
+}
+
+/// `imported` relative to the directory of `importer`, with `.` and `..` components removed
+fn join_import(importer: &str, imported: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+
+    let importer_dir = match importer.rsplit_once('/') {
+        Some((dir, _)) if !imported.starts_with('/') => dir,
+        _ => "",
+    };
+
+    for component in importer_dir.split('/').chain(imported.split('/')) {
+        match component {
+            "" | "." => (),
+            ".." if components.last().is_some_and(|last| *last != "..") => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+
+    let joined = components.join("/");
+    if imported.starts_with('/') {
+        format!("/{joined}")
+    } else {
+        joined
+    }
+}
@@ -8,7 +8,7 @@ def as_list(
 ) -> list[tuple[str, str | None, str | None, list[tuple[str, str]] | None]]:
     return [
         (
-            x.raw(),
+            x.source_text(),
             x.element_name(),
             x.element_returns(),
             x.element_params(),